@@ -0,0 +1,281 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Matching of the brackets in the `bracket_pairs` config.
+
+use xi_rope::{Cursor, Interval, Rope};
+
+use layers::Layers;
+
+/// The maximum number of bytes that are searched for a matching bracket,
+/// so that matching stays cheap in large files.
+const MAX_SEARCH_LEN: usize = 64 * 1024;
+
+/// A pair of matching brackets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BracketPair {
+    /// The interval of the opening bracket.
+    pub open: Interval,
+    /// The interval of the closing bracket.
+    pub close: Interval,
+}
+
+/// Finds matching brackets, ignoring brackets in strings and comments
+/// unless the search starts from one.
+pub struct BracketMatcher<'a> {
+    text: &'a Rope,
+    layers: &'a Layers,
+    /// The pairs of distinct single characters in the config; pairs such
+    /// as quotes, which open and close with the same character, can't be
+    /// matched by nesting.
+    pairs: Vec<(char, char)>,
+}
+
+impl BracketPair {
+    /// Returns the offset the cursor moves to when jumping from `offset`
+    /// to the matching bracket. From either side of one of the brackets,
+    /// this is the same side of the other one; from inside the pair, it is
+    /// the start of the closing bracket.
+    pub fn matching_offset(&self, offset: usize) -> usize {
+        if offset == self.open.start() {
+            self.close.start()
+        } else if offset == self.open.end() {
+            self.close.end()
+        } else if offset == self.close.start() {
+            self.open.start()
+        } else if offset == self.close.end() {
+            self.open.end()
+        } else {
+            self.close.start()
+        }
+    }
+}
+
+impl<'a> BracketMatcher<'a> {
+    pub fn new(text: &'a Rope, pairs: &[(String, String)], layers: &'a Layers) -> Self {
+        let pairs = pairs
+            .iter()
+            .filter_map(|(open, close)| {
+                let mut open = open.chars();
+                let mut close = close.chars();
+                match (open.next(), open.next(), close.next(), close.next()) {
+                    (Some(open), None, Some(close), None) if open != close => Some((open, close)),
+                    _ => None,
+                }
+            })
+            .collect();
+        BracketMatcher { text, layers, pairs }
+    }
+
+    /// Returns the pair of brackets around `offset`: the pair of the bracket
+    /// after `offset`, else of the bracket before it, else the innermost pair
+    /// enclosing it.
+    pub fn pair_at(&self, offset: usize) -> Option<BracketPair> {
+        if self.pairs.is_empty() {
+            return None;
+        }
+        let mut cursor = Cursor::new(self.text, offset);
+        if let Some(c) = cursor.next_codepoint() {
+            if let Some(pair) = self.pair_of_bracket(offset, c) {
+                return Some(pair);
+            }
+        }
+        let mut cursor = Cursor::new(self.text, offset);
+        if let Some(c) = cursor.prev_codepoint() {
+            if let Some(pair) = self.pair_of_bracket(cursor.pos(), c) {
+                return Some(pair);
+            }
+        }
+        self.enclosing_pair(offset, self.layers.is_string_or_comment(offset))
+    }
+
+    /// Returns `true` if there are no pairs to match.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns the pair formed by the bracket `c` at `offset` and the
+    /// bracket that matches it, if any.
+    fn pair_of_bracket(&self, offset: usize, c: char) -> Option<BracketPair> {
+        let bracket = Interval::new(offset, offset + c.len_utf8());
+        let in_string = self.layers.is_string_or_comment(offset);
+        if let Some(&(open, close)) = self.pairs.iter().find(|p| p.0 == c) {
+            let mut depth = 0;
+            let close = self.find_bracket(bracket.end(), true, in_string, |c| {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    if depth == 0 {
+                        return true;
+                    }
+                    depth -= 1;
+                }
+                false
+            })?;
+            Some(BracketPair { open: bracket, close })
+        } else if let Some(&(open, close)) = self.pairs.iter().find(|p| p.1 == c) {
+            let mut depth = 0;
+            let open = self.find_bracket(bracket.start(), false, in_string, |c| {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    if depth == 0 {
+                        return true;
+                    }
+                    depth -= 1;
+                }
+                false
+            })?;
+            Some(BracketPair { open, close: bracket })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the innermost pair of brackets enclosing `offset`, among
+    /// the brackets in strings and comments if `in_string` is `true`, or
+    /// among the others otherwise.
+    pub fn enclosing_pair(&self, offset: usize, in_string: bool) -> Option<BracketPair> {
+        let mut depths = vec![0; self.pairs.len()];
+        let pairs = &self.pairs;
+        let open = self.find_bracket(offset, false, in_string, |c| {
+            for (&(open, close), depth) in pairs.iter().zip(depths.iter_mut()) {
+                if c == close {
+                    *depth += 1;
+                } else if c == open {
+                    if *depth == 0 {
+                        return true;
+                    }
+                    *depth -= 1;
+                }
+            }
+            false
+        })?;
+        let c = self.text.slice_to_cow(open).chars().next()?;
+        self.pair_of_bracket(open.start(), c)
+    }
+
+    /// Returns the first bracket after (or, if `forward` is `false`, before)
+    /// `offset` for which `f` returns `true`, skipping brackets that are not
+    /// in a string or comment if `in_string` is `true`, or that are in one
+    /// otherwise.
+    fn find_bracket<F>(
+        &self,
+        offset: usize,
+        forward: bool,
+        in_string: bool,
+        mut f: F,
+    ) -> Option<Interval>
+    where
+        F: FnMut(char) -> bool,
+    {
+        let mut cursor = Cursor::new(self.text, offset);
+        loop {
+            let (c, pos) = if forward {
+                let pos = cursor.pos();
+                if pos - offset > MAX_SEARCH_LEN {
+                    return None;
+                }
+                (cursor.next_codepoint()?, pos)
+            } else {
+                if offset - cursor.pos() > MAX_SEARCH_LEN {
+                    return None;
+                }
+                let c = cursor.prev_codepoint()?;
+                (c, cursor.pos())
+            };
+            let is_bracket = self.pairs.iter().any(|&(open, close)| c == open || c == close);
+            if is_bracket && self.layers.is_string_or_comment(pos) == in_string && f(c) {
+                return Some(Interval::new(pos, pos + c.len_utf8()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugins::PluginPid;
+    use styles::ThemeStyleMap;
+    use xi_rope::spans::SpansBuilder;
+    use xi_rope::RopeDelta;
+
+    fn default_pairs() -> Vec<(String, String)> {
+        [("\"", "\""), ("(", ")"), ("[", "]"), ("{", "}")]
+            .iter()
+            .map(|&(open, close)| (open.to_owned(), close.to_owned()))
+            .collect()
+    }
+
+    fn pair_at(text: &str, layers: &Layers, offset: usize) -> Option<(usize, usize)> {
+        let text = Rope::from(text);
+        let matcher = BracketMatcher::new(&text, &default_pairs(), layers);
+        matcher.pair_at(offset).map(|pair| (pair.open.start(), pair.close.start()))
+    }
+
+    #[test]
+    fn adjacent_brackets() {
+        let layers = Layers::default();
+        let text = "f(a[1], {b: (c)})";
+        assert_eq!(pair_at(text, &layers, 1), Some((1, 16)));
+        assert_eq!(pair_at(text, &layers, 2), Some((1, 16)));
+        assert_eq!(pair_at(text, &layers, 17), Some((1, 16)));
+        assert_eq!(pair_at(text, &layers, 3), Some((3, 5)));
+        assert_eq!(pair_at(text, &layers, 12), Some((12, 14)));
+        // the bracket after the offset is preferred
+        assert_eq!(pair_at(text, &layers, 15), Some((8, 15)));
+        // unbalanced
+        assert_eq!(pair_at("((a)", &layers, 0), None);
+        assert_eq!(pair_at("(a]", &layers, 0), None);
+        // quotes are not matched
+        assert_eq!(pair_at("\"a\"", &layers, 0), None);
+    }
+
+    #[test]
+    fn enclosing_brackets() {
+        let layers = Layers::default();
+        let text = "{ a(b) [c] d }";
+        assert_eq!(pair_at(text, &layers, 11), Some((0, 13)));
+        assert_eq!(pair_at(text, &layers, 8), Some((7, 9)));
+        assert_eq!(pair_at("a b", &layers, 1), None);
+    }
+
+    #[test]
+    fn skip_strings_and_comments() {
+        let text = "f(\")\", /* ( */ x)";
+        let style_map = ThemeStyleMap::new(None);
+        let mut layers = Layers::default();
+        let pid = PluginPid(1);
+        let scopes = vec![
+            vec!["source.rust".to_owned()],
+            vec!["source.rust".to_owned(), "string.quoted.double".to_owned()],
+            vec!["source.rust".to_owned(), "comment.block".to_owned()],
+        ];
+        layers.add_scopes(pid, scopes, &style_map);
+        let mut sb = SpansBuilder::new(text.len());
+        sb.add_span(Interval::new(0, 2), 0);
+        sb.add_span(Interval::new(2, 5), 1);
+        sb.add_span(Interval::new(5, 7), 0);
+        sb.add_span(Interval::new(7, 14), 2);
+        sb.add_span(Interval::new(14, text.len()), 0);
+        layers.update_all(&RopeDelta::simple_edit(Interval::new(0, 0), Rope::from(text), 0));
+        layers.update_layer(pid, Interval::new(0, text.len()), sb.build());
+
+        assert_eq!(pair_at(text, &layers, 1), Some((1, 16)));
+        assert_eq!(pair_at(text, &layers, 17), Some((1, 16)));
+        // brackets in strings and comments are matched among themselves
+        assert_eq!(pair_at(text, &layers, 10), None);
+        assert_eq!(pair_at(text, &layers, 3), None);
+    }
+}
@@ -14,12 +14,17 @@
 
 //! Requests and notifications from the core to front-ends.
 
+use std::path::Path;
 use std::time::Instant;
 
 use serde_json::{self, Value};
 use xi_rpc::{self, RpcPeer};
 
+use completion::CompletionItem;
 use config::Table;
+use config_schema::ValidationError;
+use file::CharacterEncoding;
+use modal::Mode;
 use plugins::rpc::ClientPluginInfo;
 use plugins::Command;
 use styles::ThemeSettings;
@@ -91,6 +96,12 @@ impl Client {
         );
     }
 
+    /// Notify the client of invalid config settings, or config files that
+    /// could not be loaded.
+    pub fn config_errors(&self, errors: &[ValidationError]) {
+        self.0.send_rpc_notification("config_errors", &json!({ "errors": errors }));
+    }
+
     pub fn language_changed(&self, view_id: ViewId, new_lang: &LanguageId) {
         self.0.send_rpc_notification(
             "language_changed",
@@ -101,6 +112,54 @@ impl Client {
         );
     }
 
+    pub fn mode_changed(&self, view_id: ViewId, mode: Mode) {
+        self.0.send_rpc_notification(
+            "mode_changed",
+            &json!({
+                "view_id": view_id,
+                "mode": mode,
+            }),
+        );
+    }
+
+    pub fn encoding_changed(&self, view_id: ViewId, encoding: CharacterEncoding) {
+        self.0.send_rpc_notification(
+            "encoding_changed",
+            &json!({
+                "view_id": view_id,
+                "encoding": encoding,
+            }),
+        );
+    }
+
+    /// Notify the client that the file backing a view has been changed on
+    /// disk by another process, while the view's buffer has unsaved changes.
+    /// `conflicts` are the first and last lines of regions where the changes
+    /// could not be merged cleanly.
+    pub fn file_changed(&self, view_id: ViewId, path: &Path, conflicts: &[(usize, usize)]) {
+        self.0.send_rpc_notification(
+            "file_changed",
+            &json!({
+                "view_id": view_id,
+                "path": path,
+                "conflicts": conflicts,
+            }),
+        );
+    }
+
+    /// Notify the client that a recovery file with unsaved changes to the
+    /// file backing a view, which is newer than the file, was found. `path`
+    /// is `None` for an untitled buffer.
+    pub fn recovery_available(&self, view_id: ViewId, path: Option<&Path>) {
+        self.0.send_rpc_notification(
+            "recovery_available",
+            &json!({
+                "view_id": view_id,
+                "path": path,
+            }),
+        );
+    }
+
     /// Notify the client that a plugin has started.
     pub fn plugin_started(&self, view_id: ViewId, plugin: &str) {
         self.0.send_rpc_notification(
@@ -235,6 +294,24 @@ impl Client {
         )
     }
 
+    pub fn completion_list(
+        &self,
+        view_id: ViewId,
+        request_id: usize,
+        prefix: &str,
+        items: &[CompletionItem],
+    ) {
+        self.0.send_rpc_notification(
+            "completion_list",
+            &json!({
+                "view_id": view_id,
+                "request_id": request_id,
+                "prefix": prefix,
+                "items": items,
+            }),
+        )
+    }
+
     pub fn schedule_idle(&self, token: usize) {
         self.0.schedule_idle(token)
     }
@@ -0,0 +1,128 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Selection of rectangular blocks of text, which span the same visual
+//! columns on consecutive lines.
+
+use std::cmp::{max, min};
+
+use xi_rope::{LinesMetric, Rope};
+
+use selection::{SelRegion, Selection};
+
+/// A position in the text, as a line and a visual column, in which a tab
+/// extends to the next tab stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnPosition {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl ColumnPosition {
+    pub fn new(line: usize, col: usize) -> Self {
+        ColumnPosition { line, col }
+    }
+
+    /// Returns the position of `offset`.
+    pub fn from_offset(text: &Rope, offset: usize, tab_size: usize) -> Self {
+        let line = text.line_of_offset(offset);
+        let start = text.offset_of_line(line);
+        let col =
+            text.slice_to_cow(start..offset).chars().fold(0, |col, c| next_col(col, c, tab_size));
+        ColumnPosition { line, col }
+    }
+}
+
+/// Returns the visual column after the character `c` at column `col`.
+fn next_col(col: usize, c: char, tab_size: usize) -> usize {
+    if c == '\t' {
+        (col / tab_size + 1) * tab_size
+    } else {
+        col + 1
+    }
+}
+
+/// Returns the offset of the character boundary at, or else just before,
+/// the visual column `col` of `line`, or the end of the line if it ends
+/// before that column.
+pub fn offset_of_col(text: &Rope, line: usize, col: usize, tab_size: usize) -> usize {
+    let start = text.offset_of_line(line);
+    let line_text = text.lines(start..).next().unwrap_or_default();
+    let mut offset = start;
+    let mut current = 0;
+    for c in line_text.chars() {
+        current = next_col(current, c, tab_size);
+        if current > col {
+            break;
+        }
+        offset += c.len_utf8();
+    }
+    offset
+}
+
+/// Returns the selection of the block between `anchor` and `head`: a region
+/// on each line from the column of `anchor` to the column of `head`. Lines
+/// that end before the block get a caret at their end.
+pub fn column_selection(
+    text: &Rope,
+    anchor: ColumnPosition,
+    head: ColumnPosition,
+    tab_size: usize,
+) -> Selection {
+    let last_line = text.measure::<LinesMetric>();
+    let first = min(min(anchor.line, head.line), last_line);
+    let last = min(max(anchor.line, head.line), last_line);
+    let mut sel = Selection::new();
+    for line in first..=last {
+        let start = offset_of_col(text, line, anchor.col, tab_size);
+        let end = offset_of_col(text, line, head.col, tab_size);
+        sel.add_region(SelRegion::new(start, end));
+    }
+    sel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(sel: &Selection) -> Vec<(usize, usize)> {
+        sel.iter().map(|region| (region.start, region.end)).collect()
+    }
+
+    #[test]
+    fn columns_with_tabs() {
+        let text = Rope::from("a\tb\n\t\tc");
+        assert_eq!(ColumnPosition::from_offset(&text, 2, 4), ColumnPosition::new(0, 4));
+        assert_eq!(ColumnPosition::from_offset(&text, 7, 4), ColumnPosition::new(1, 9));
+        assert_eq!(ColumnPosition::from_offset(&text, 7, 2), ColumnPosition::new(1, 5));
+        // columns inside a tab are before it
+        assert_eq!(offset_of_col(&text, 0, 3, 4), 1);
+        assert_eq!(offset_of_col(&text, 0, 4, 4), 2);
+        assert_eq!(offset_of_col(&text, 1, 5, 4), 5);
+        assert_eq!(offset_of_col(&text, 0, 10, 4), 3);
+    }
+
+    #[test]
+    fn block_selection() {
+        let text = Rope::from("abcd\nab\n\tcdef\nabcdef");
+        let sel = column_selection(&text, ColumnPosition::new(0, 1), ColumnPosition::new(3, 3), 2);
+        assert_eq!(offsets(&sel), vec![(1, 3), (6, 7), (8, 10), (15, 17)]);
+        // backwards, and past the end of the text
+        let sel = column_selection(&text, ColumnPosition::new(4, 5), ColumnPosition::new(2, 4), 2);
+        assert_eq!(offsets(&sel), vec![(12, 11), (19, 18)]);
+        // short lines get carets
+        let sel = column_selection(&text, ColumnPosition::new(0, 3), ColumnPosition::new(1, 4), 4);
+        assert_eq!(offsets(&sel), vec![(3, 4), (7, 7)]);
+    }
+}
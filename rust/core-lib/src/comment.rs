@@ -0,0 +1,234 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Commenting and uncommenting lines with the comment tokens of a language.
+
+use std::collections::BTreeSet;
+
+use xi_rope::{DeltaBuilder, Interval, LinesMetric, Rope, RopeDelta};
+
+use line_ops::touched_lines;
+use selection::SelRegion;
+
+/// A line touched by a selection region.
+struct Line {
+    /// The offset of the start of the line.
+    start: usize,
+    /// The text of the line, without its line ending.
+    text: String,
+}
+
+impl Line {
+    /// The length in bytes of the leading whitespace.
+    fn indent(&self) -> usize {
+        self.text.len() - self.text.trim_start().len()
+    }
+
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    /// The text after the leading whitespace.
+    fn content(&self) -> &str {
+        &self.text[self.indent()..]
+    }
+}
+
+/// Comments every line touched by `regions` with the line comment `token`,
+/// or, if every one of them is already commented, uncomments them.
+///
+/// Comment tokens are aligned at the smallest indentation of the lines, and
+/// blank lines are left alone unless every line is blank. A token ending in
+/// a space, such as `REM `, only matches when it is followed by whitespace or
+/// the end of the line.
+pub fn toggle_line_comment(text: &Rope, regions: &[SelRegion], token: &str) -> Option<RopeDelta> {
+    let lines = lines_to_comment(text, regions);
+    let indent = lines.iter().map(Line::indent).min()?;
+    let needs_separator = token.ends_with(char::is_whitespace);
+    let token = token.trim_end();
+    let is_commented = |line: &Line| {
+        let content = line.content();
+        content.starts_with(token)
+            && (!needs_separator
+                || content[token.len()..].chars().next().map_or(true, char::is_whitespace))
+    };
+    let mut builder = DeltaBuilder::new(text.len());
+    if lines.iter().all(is_commented) {
+        for line in &lines {
+            let start = line.indent();
+            let mut end = start + token.len();
+            if line.text[end..].starts_with(' ') {
+                end += 1;
+            }
+            builder.delete(Interval::new(line.start + start, line.start + end));
+        }
+    } else {
+        let comment = Rope::from(format!("{} ", token));
+        for line in &lines {
+            let offset = line.start + indent;
+            builder.replace(Interval::new(offset, offset), comment.clone());
+        }
+    }
+    Some(builder.build())
+}
+
+/// Wraps every line touched by `regions` in the block comment tokens `open`
+/// and `close`, or, if every one of them is already wrapped, unwraps them.
+///
+/// Opening tokens are aligned at the smallest indentation of the lines, and
+/// blank lines are left alone unless every line is blank.
+pub fn toggle_block_comment(
+    text: &Rope,
+    regions: &[SelRegion],
+    open: &str,
+    close: &str,
+) -> Option<RopeDelta> {
+    let lines = lines_to_comment(text, regions);
+    let indent = lines.iter().map(Line::indent).min()?;
+    let is_commented = |line: &Line| {
+        let content = line.content().trim_end();
+        content.len() >= open.len() + close.len()
+            && content.starts_with(open)
+            && content.ends_with(close)
+    };
+    let mut builder = DeltaBuilder::new(text.len());
+    if lines.iter().all(is_commented) {
+        for line in &lines {
+            let mut open_end = line.indent() + open.len();
+            let mut close_start = line.text.trim_end().len() - close.len();
+            if open_end < close_start && line.text[open_end..].starts_with(' ') {
+                open_end += 1;
+            }
+            if open_end < close_start && line.text[..close_start].ends_with(' ') {
+                close_start -= 1;
+            }
+            let close_end = line.text.trim_end().len();
+            builder.delete(Interval::new(line.start + line.indent(), line.start + open_end));
+            builder.delete(Interval::new(line.start + close_start, line.start + close_end));
+        }
+    } else {
+        let open = Rope::from(format!("{} ", open));
+        let close = Rope::from(format!(" {}", close));
+        for line in &lines {
+            let offset = line.start + indent;
+            let end = line.start + line.text.len();
+            builder.replace(Interval::new(offset, offset), open.clone());
+            builder.replace(Interval::new(end, end), close.clone());
+        }
+    }
+    Some(builder.build())
+}
+
+/// Returns the lines touched by any of `regions`, without the blank ones,
+/// unless all of them are blank.
+fn lines_to_comment(text: &Rope, regions: &[SelRegion]) -> Vec<Line> {
+    let mut line_numbers = BTreeSet::new();
+    for region in regions {
+        line_numbers.extend(touched_lines(text, region));
+    }
+    let n_lines = text.measure::<LinesMetric>() + 1;
+    let lines = line_numbers
+        .into_iter()
+        .filter(|&line| line < n_lines)
+        .map(|line| {
+            let start = text.offset_of_line(line);
+            let text = text.lines(start..).next().map(String::from).unwrap_or_default();
+            Line { start, text }
+        })
+        .collect::<Vec<_>>();
+    if lines.iter().all(Line::is_blank) {
+        lines
+    } else {
+        lines.into_iter().filter(|line| !line.is_blank()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies the result of `f` to `text`, with a region for each pair of
+    /// offsets in `regions`.
+    fn toggle<F>(text: &str, regions: &[(usize, usize)], f: F) -> String
+    where
+        F: Fn(&Rope, &[SelRegion]) -> Option<RopeDelta>,
+    {
+        let text = Rope::from(text);
+        let regions = regions.iter().map(|&(s, e)| SelRegion::new(s, e)).collect::<Vec<_>>();
+        match f(&text, &regions) {
+            Some(delta) => String::from(delta.apply(&text)),
+            None => String::from(text),
+        }
+    }
+
+    fn line(text: &str, regions: &[(usize, usize)]) -> String {
+        toggle(text, regions, |text, regions| toggle_line_comment(text, regions, "//"))
+    }
+
+    fn lua(text: &str, regions: &[(usize, usize)]) -> String {
+        toggle(text, regions, |text, regions| toggle_line_comment(text, regions, "--"))
+    }
+
+    fn batch(text: &str, regions: &[(usize, usize)]) -> String {
+        toggle(text, regions, |text, regions| toggle_line_comment(text, regions, "REM "))
+    }
+
+    fn block(text: &str, regions: &[(usize, usize)]) -> String {
+        toggle(text, regions, |text, regions| toggle_block_comment(text, regions, "/*", "*/"))
+    }
+
+    #[test]
+    fn line_comments() {
+        let text = "fn f() {\n    a();\n\n  b();\n}\n";
+        // aligned at the smallest indentation, skipping blank lines
+        assert_eq!(line(text, &[(13, 22)]), "fn f() {\n  //   a();\n\n  // b();\n}\n");
+        assert_eq!(line("  //   a();\n\n  // b();\n", &[(0, 20)]), "    a();\n\n  b();\n");
+        // partially commented lines are commented
+        assert_eq!(line("// a\nb\n", &[(0, 6)]), "// // a\n// b\n");
+        // a token without a following space
+        assert_eq!(line("  //a\n", &[(3, 3)]), "  a\n");
+        // the line after the end of a selection ending at its start
+        assert_eq!(line("a\nb\n", &[(0, 2)]), "// a\nb\n");
+        // separate regions
+        assert_eq!(line("a\nb\nc", &[(0, 0), (5, 5)]), "// a\nb\n// c");
+        // blank lines
+        assert_eq!(line("", &[(0, 0)]), "// ");
+        assert_eq!(line("a\r\n\r\nb", &[(0, 6)]), "// a\r\n\r\n// b");
+    }
+
+    #[test]
+    fn multi_character_tokens() {
+        assert_eq!(lua("a = 1\n  b()\n", &[(0, 9)]), "-- a = 1\n--   b()\n");
+        assert_eq!(lua("-- a = 1\n--   b()\n", &[(0, 12)]), "a = 1\n  b()\n");
+        assert_eq!(lua("--a\n", &[(0, 0)]), "a\n");
+        // only part of the token
+        assert_eq!(lua("- a\n", &[(0, 0)]), "-- - a\n");
+
+        assert_eq!(batch("echo a\n", &[(0, 0)]), "REM echo a\n");
+        assert_eq!(batch("REM echo a\nREM\n", &[(0, 12)]), "echo a\n\n");
+        // the token must be followed by a separator
+        assert_eq!(batch("REMOVE\n", &[(0, 0)]), "REM REMOVE\n");
+    }
+
+    #[test]
+    fn block_comments() {
+        let text = "  a\n    b  \n\n";
+        assert_eq!(block(text, &[(0, 12)]), "  /* a */\n  /*   b   */\n\n");
+        assert_eq!(block("  /* a */\n  /*   b   */\n\n", &[(0, 12)]), "  a\n    b  \n\n");
+        assert_eq!(block("/*a*/ \n", &[(0, 0)]), "a \n");
+        assert_eq!(block("/* */", &[(0, 0)]), "");
+        // partially commented lines are commented
+        assert_eq!(block("/* a */\nb", &[(0, 9)]), "/* /* a */ */\n/* b */");
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Completion of the identifier before the cursor with the identifiers in
+//! the open buffers.
+
+use std::cmp::{min, Reverse};
+use std::collections::HashMap;
+
+use xi_rope::{LinesMetric, Rope, RopeDelta};
+
+use tabs::{BufferId, ViewId};
+use word_boundaries::WordCursor;
+
+/// The most completions that are offered.
+const MAX_COMPLETIONS: usize = 50;
+
+/// The distance, in bytes, around the cursor in which identifiers are
+/// ranked by how close they are to it.
+const PROXIMITY_RANGE: usize = 10_000;
+
+/// The source of the completions found in the open buffers.
+pub const BUFFER_SOURCE: &str = "buffer";
+
+/// A completion of the identifier before the cursor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompletionItem {
+    /// The text that replaces the identifier.
+    pub label: String,
+    /// Where the completion comes from, so that completions from other
+    /// sources, such as plugins, can be told apart when they are merged.
+    pub source: String,
+}
+
+/// The identifiers of the open buffers, and the completions last offered in
+/// each view, which can then be accepted.
+#[derive(Debug, Default)]
+pub struct Completions {
+    /// The number of times each identifier occurs in each buffer.
+    words: HashMap<BufferId, HashMap<String, usize>>,
+    /// The id of the request for the completions last offered in each
+    /// view, and the completions.
+    lists: HashMap<ViewId, (usize, Vec<CompletionItem>)>,
+}
+
+impl Completions {
+    pub fn new() -> Completions {
+        Completions::default()
+    }
+
+    /// Indexes the identifiers of a buffer.
+    pub fn index_buffer(&mut self, buffer_id: BufferId, text: &Rope) {
+        let mut words = HashMap::new();
+        for (_, word) in identifiers(text, 0, text.len()) {
+            *words.entry(word).or_insert(0) += 1;
+        }
+        self.words.insert(buffer_id, words);
+    }
+
+    pub fn remove_buffer(&mut self, buffer_id: BufferId) {
+        self.words.remove(&buffer_id);
+    }
+
+    pub fn remove_view(&mut self, view_id: ViewId) {
+        self.lists.remove(&view_id);
+    }
+
+    /// Updates the index of a buffer for an edit, by indexing the edited
+    /// lines again.
+    pub fn update(
+        &mut self,
+        buffer_id: BufferId,
+        last_text: &Rope,
+        text: &Rope,
+        delta: &RopeDelta,
+    ) {
+        let words = match self.words.get_mut(&buffer_id) {
+            Some(words) => words,
+            None => return self.index_buffer(buffer_id, text),
+        };
+        let (iv, new_len) = delta.summary();
+        let (start, end) = line_bounds(last_text, iv.start(), iv.end());
+        for (_, word) in identifiers(last_text, start, end) {
+            let count = words.get(&word).map(|count| count - 1).unwrap_or(0);
+            if count == 0 {
+                words.remove(&word);
+            } else {
+                words.insert(word, count);
+            }
+        }
+        let (start, end) = line_bounds(text, iv.start(), iv.start() + new_len);
+        for (_, word) in identifiers(text, start, end) {
+            *words.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    /// Returns the completions of the identifier before `offset` in the
+    /// buffer's text, best first. They are ranked by how well the identifier
+    /// matches them, then by how close they are to `offset`, and then by
+    /// whether they are in this buffer.
+    pub fn complete(
+        &mut self,
+        buffer_id: BufferId,
+        text: &Rope,
+        offset: usize,
+    ) -> Vec<CompletionItem> {
+        if !self.words.contains_key(&buffer_id) {
+            self.index_buffer(buffer_id, text);
+        }
+        let start = prefix_start(text, offset);
+        let prefix = text.slice_to_cow(start..offset);
+        let line_end = line_bounds(text, offset, offset).1;
+        let after = text.slice_to_cow(offset..line_end);
+        let len: usize = after.chars().take_while(|&c| is_word_char(c)).map(char::len_utf8).sum();
+        let current_word = text.slice_to_cow(start..offset + len);
+
+        let mut distances = HashMap::new();
+        let near = identifiers(
+            text,
+            offset.saturating_sub(PROXIMITY_RANGE),
+            min(offset + PROXIMITY_RANGE, text.len()),
+        );
+        for (pos, word) in near.into_iter().filter(|&(pos, _)| pos != start) {
+            let distance = if pos < offset { offset - pos } else { pos - offset };
+            let min_distance = distances.entry(word).or_insert(distance);
+            *min_distance = min(*min_distance, distance);
+        }
+
+        let mut candidates = HashMap::new();
+        for (&id, words) in &self.words {
+            for (word, &count) in words {
+                // the word being typed doesn't complete itself
+                if id == buffer_id && word == &current_word && count == 1 {
+                    continue;
+                }
+                if word == &prefix || candidates.contains_key(word.as_str()) {
+                    continue;
+                }
+                if let Some(score) = fuzzy_score(&prefix, word) {
+                    let distance = distances.get(word).cloned().unwrap_or(usize::max_value());
+                    let key = (Reverse(score), distance, id != buffer_id, word.len());
+                    candidates.insert(word.as_str(), key);
+                }
+            }
+        }
+        let mut candidates = candidates.into_iter().collect::<Vec<_>>();
+        candidates.sort_by(|a, b| (a.1, a.0).cmp(&(b.1, b.0)));
+        candidates
+            .into_iter()
+            .take(MAX_COMPLETIONS)
+            .map(|(word, _)| CompletionItem {
+                label: word.to_owned(),
+                source: BUFFER_SOURCE.into(),
+            })
+            .collect()
+    }
+
+    /// Sets the completions offered in a view.
+    pub fn set_list(&mut self, view_id: ViewId, request_id: usize, items: Vec<CompletionItem>) {
+        self.lists.insert(view_id, (request_id, items));
+    }
+
+    /// Returns the completion at `index` of those offered in a view for the
+    /// request `request_id`, if it is the last request, forgetting the
+    /// offered completions.
+    pub fn take_item(
+        &mut self,
+        view_id: ViewId,
+        request_id: usize,
+        index: usize,
+    ) -> Option<CompletionItem> {
+        match self.lists.remove(&view_id) {
+            Some((id, mut items)) if id == request_id && index < items.len() => {
+                Some(items.swap_remove(index))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_alphabetic() => (),
+        _ => return false,
+    }
+    word.len() > 1 && chars.all(is_word_char)
+}
+
+/// Returns the identifiers between `start` and `end`, along with
+/// their offsets. They are the words between the word boundaries that are
+/// made up of identifier characters.
+fn identifiers(text: &Rope, start: usize, end: usize) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut cursor = WordCursor::new(text, start);
+    while let Some(word_end) = cursor.next_boundary() {
+        if word_end > end {
+            break;
+        }
+        let word_start = WordCursor::new(text, word_end).prev_boundary().unwrap_or(0);
+        if word_start < start {
+            continue;
+        }
+        let word = text.slice_to_cow(word_start..word_end);
+        if is_identifier(&word) {
+            words.push((word_start, word.into_owned()));
+        }
+    }
+    words
+}
+
+/// Returns the start of the first line touched by the range, and the end of
+/// the last, including its newline.
+fn line_bounds(text: &Rope, start: usize, end: usize) -> (usize, usize) {
+    let start = text.offset_of_line(text.line_of_offset(start));
+    let last_line = text.line_of_offset(end);
+    let end = if last_line < text.measure::<LinesMetric>() {
+        text.offset_of_line(last_line + 1)
+    } else {
+        text.len()
+    };
+    (start, end)
+}
+
+/// Returns the start of the identifier characters just before `offset`.
+pub fn prefix_start(text: &Rope, offset: usize) -> usize {
+    let line_start = text.offset_of_line(text.line_of_offset(offset));
+    let before = text.slice_to_cow(line_start..offset);
+    let len: usize =
+        before.chars().rev().take_while(|&c| is_word_char(c)).map(char::len_utf8).sum();
+    offset - len
+}
+
+/// Scores how well `pattern` matches `candidate`, if the candidate starts
+/// with the pattern's first character and contains the rest in order,
+/// ignoring case. Matches of consecutive characters, of characters that
+/// start a part of the candidate, such as the `B` of `fooBar` or the `b` of
+/// `foo_bar`, and of characters of the same case score higher.
+fn fuzzy_score(pattern: &str, candidate: &str) -> Option<usize> {
+    let chars = candidate.chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    for (i, p) in pattern.chars().enumerate() {
+        let same = |c: char| c.to_lowercase().eq(p.to_lowercase());
+        let found = (next..chars.len()).find(|&j| same(chars[j]))?;
+        if i == 0 && found != 0 {
+            return None;
+        }
+        score += 1;
+        if found > 0 && found == next {
+            score += 4;
+        }
+        let prev = if found > 0 { Some(chars[found - 1]) } else { None };
+        match prev {
+            None | Some('_') => score += 2,
+            Some(prev) if prev.is_lowercase() && chars[found].is_uppercase() => score += 2,
+            _ => (),
+        }
+        if chars[found] == p {
+            score += 1;
+        }
+        next = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xi_rope::{DeltaBuilder, Interval};
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn find_identifiers() {
+        let text = Rope::from("fn foo_bar(x: u32) -> Baz2 {\n    2fast.élan\n}");
+        let words = identifiers(&text, 0, text.len());
+        let words = words.iter().map(|&(pos, ref word)| (pos, word.as_str())).collect::<Vec<_>>();
+        assert_eq!(words, vec![(0, "fn"), (3, "foo_bar"), (14, "u32"), (22, "Baz2"), (39, "élan")]);
+        assert_eq!(prefix_start(&text, 9), 3);
+        assert_eq!(prefix_start(&text, 11), 11);
+    }
+
+    #[test]
+    fn fuzzy_scores() {
+        assert!(fuzzy_score("fb", "foo_bar").unwrap() > fuzzy_score("fb", "fobar").unwrap());
+        assert!(fuzzy_score("foo", "foo_bar").unwrap() > fuzzy_score("foo", "f_o_o").unwrap());
+        assert!(fuzzy_score("fB", "fooBar").unwrap() > fuzzy_score("fB", "foobar").unwrap());
+        assert_eq!(fuzzy_score("ob", "foobar"), None);
+        assert_eq!(fuzzy_score("fz", "foobar"), None);
+        assert!(fuzzy_score("", "foobar").is_some());
+    }
+
+    #[test]
+    fn incremental_index() {
+        let text = Rope::from("alpha beta\ngamma beta");
+        let mut completions = Completions::new();
+        let buffer_id = BufferId(1);
+        completions.index_buffer(buffer_id, &text);
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.replace(Interval::new(11, 16), Rope::from("lt\ndelta"));
+        let delta = builder.build();
+        let new_text = delta.apply(&text);
+        completions.update(buffer_id, &text, &new_text, &delta);
+        let mut expected = Completions::new();
+        expected.index_buffer(buffer_id, &new_text);
+        assert_eq!(completions.words, expected.words);
+        assert_eq!(completions.words[&buffer_id]["beta"], 2);
+        assert!(!completions.words[&buffer_id].contains_key("gamma"));
+    }
+
+    #[test]
+    fn ranking() {
+        let mut completions = Completions::new();
+        completions.index_buffer(BufferId(2), &Rope::from("fooOther fabulous"));
+        let text = Rope::from("format foo_bar\nfoo fo\nfob");
+        let items = completions.complete(BufferId(1), &text, 21);
+        assert_eq!(labels(&items), vec!["fob", "foo", "foo_bar", "format", "fooOther", "fabulous"]);
+        assert_eq!(items[0].source, BUFFER_SOURCE);
+
+        // `fob` is closer, but `foo_bar` matches better
+        let text = Rope::from("format foo_bar\nfoo fb\nfob");
+        let items = completions.complete(BufferId(1), &text, 21);
+        assert_eq!(labels(&items), vec!["foo_bar", "fob", "fabulous"]);
+
+        completions.set_list(ViewId(1), 3, items);
+        assert_eq!(completions.take_item(ViewId(1), 2, 0), None);
+        completions.set_list(ViewId(1), 3, vec![]);
+        assert_eq!(completions.take_item(ViewId(1), 3, 0), None);
+    }
+}
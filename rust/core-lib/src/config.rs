@@ -23,8 +23,12 @@ use std::sync::Arc;
 use serde::de::Deserialize;
 use serde_json::{self, Value};
 use toml;
+use xi_rope::Rope;
 
-use syntax::{LanguageId, Languages};
+use config_schema::{ConfigSchema, ValidationError};
+use editorconfig::EditorConfig;
+use file::CharacterEncoding;
+use syntax::{self, LanguageDefinition, LanguageId, Languages};
 use tabs::{BufferId, ViewId};
 
 /// Loads the included base config settings.
@@ -60,6 +64,9 @@ fn load_base_config() -> Table {
 /// A map of config keys to settings
 pub type Table = serde_json::Map<String, Value>;
 
+/// The name of the directory containing a project's config files.
+pub const PROJECT_CONFIG_DIR: &str = ".xi";
+
 /// A `ConfigDomain` describes a level or category of user settings.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -68,11 +75,20 @@ pub enum ConfigDomain {
     General,
     /// The overrides for a particular syntax.
     Language(LanguageId),
+    /// The settings for the files in a project, loaded from the project's
+    /// config directory. If `language` is present, these settings only
+    /// apply to files in that language.
+    #[serde(skip_deserializing)]
+    Project { root: PathBuf, language: Option<LanguageId> },
     /// The user overrides for a particular buffer
     UserOverride(BufferId),
     /// The system's overrides for a particular buffer. Only used internally.
     #[serde(skip_deserializing)]
     SysOverride(BufferId),
+    /// The settings from `.editorconfig` files for a particular buffer.
+    /// Only used internally.
+    #[serde(skip_deserializing)]
+    EditorConfig(BufferId),
 }
 
 /// The external RPC sends `ViewId`s, which we convert to `BufferId`s
@@ -114,11 +130,15 @@ pub struct ConfigPair {
 }
 
 /// The language associated with a given buffer; this is always detected
-/// but can also be manually set by the user.
+/// but can also be set by the buffer's modelines, or manually by the user.
 #[derive(Debug, Clone)]
 struct LanguageTag {
     detected: LanguageId,
+    modeline: Option<LanguageId>,
     user: Option<LanguageId>,
+    /// The first line of the buffer, if the language was not detected
+    /// from the buffer's path. This is checked again when it changes.
+    first_line: Option<String>,
 }
 
 #[derive(Debug)]
@@ -131,6 +151,15 @@ pub struct ConfigManager {
     buffer_tags: HashMap<BufferId, LanguageTag>,
     /// The configs for any open buffers
     buffer_configs: HashMap<BufferId, BufferConfig>,
+    /// The schema of the known config keys.
+    schema: ConfigSchema,
+    /// Errors from loading config files, and the keys dropped from config
+    /// tables for not matching the schema, which have not been reported.
+    errors: Vec<ValidationError>,
+    /// The directories searched for each buffer's `.editorconfig` files.
+    editorconfig_dirs: HashMap<BufferId, Vec<PathBuf>>,
+    /// The root directory of the project containing each buffer, if any.
+    buffer_projects: HashMap<BufferId, PathBuf>,
     /// If using file-based config, this is the base config directory
     /// (perhaps `$HOME/.config/xi`, by default).
     config_dir: Option<PathBuf>,
@@ -170,6 +199,15 @@ pub struct BufferItems {
     pub word_wrap: bool,
     pub autodetect_whitespace: bool,
     pub surrounding_pairs: Vec<(String, String)>,
+    pub bracket_pairs: Vec<(String, String)>,
+    pub auto_close_pairs: bool,
+    pub save_hardlinks_in_place: bool,
+    pub recovery_interval: u64,
+    pub trim_trailing_whitespace: bool,
+    pub insert_final_newline: bool,
+    pub default_encoding: CharacterEncoding,
+    pub modal_editing: bool,
+    pub subword_separators: String,
 }
 
 pub type BufferConfig = Config<BufferItems>;
@@ -225,12 +263,17 @@ impl ConfigPair {
 impl ConfigManager {
     pub fn new(config_dir: Option<PathBuf>, extras_dir: Option<PathBuf>) -> Self {
         let base = load_base_config();
+        let schema = ConfigSchema::load(&base);
         let mut defaults = HashMap::new();
         defaults.insert(ConfigDomain::General, ConfigPair::with_base(base));
         ConfigManager {
             configs: defaults,
             buffer_tags: HashMap::new(),
             buffer_configs: HashMap::new(),
+            schema,
+            errors: Vec::new(),
+            editorconfig_dirs: HashMap::new(),
+            buffer_projects: HashMap::new(),
             languages: Languages::default(),
             config_dir,
             extras_dir,
@@ -258,8 +301,8 @@ impl ConfigManager {
     }
 
     /// Adds a new buffer to the config manager, and returns the initial config
-    /// `Table` for that buffer. The `path` argument is used to determine
-    /// the buffer's default language.
+    /// `Table` for that buffer. The `path` argument and the first line of
+    /// `text` are used to determine the buffer's default language.
     ///
     /// # Note: The caller is responsible for ensuring the config manager is
     /// notified every time a buffer is added or removed.
@@ -267,30 +310,145 @@ impl ConfigManager {
     /// # Panics:
     ///
     /// Panics if `id` already exists.
-    pub(crate) fn add_buffer(&mut self, id: BufferId, path: Option<&Path>) -> Table {
-        let lang = path.and_then(|p| self.language_for_path(p)).unwrap_or_default();
-        let lang_tag = LanguageTag::new(lang);
+    pub(crate) fn add_buffer(&mut self, id: BufferId, path: Option<&Path>, text: &Rope) -> Table {
+        let (lang, first_line) = self.detect_language(path, text);
+        let lang_tag = LanguageTag::new(lang, first_line);
         assert!(self.buffer_tags.insert(id, lang_tag).is_none());
+        if let Some(path) = path {
+            self.set_buffer_project(id, self.project_root_for_path(path));
+            self.load_editorconfig(id, path);
+        }
         self.update_buffer_config(id).expect("new buffer must always have config")
     }
 
-    /// Updates the default language for the given buffer.
+    /// Updates the default language, the project and the `.editorconfig`
+    /// settings for the given buffer.
     ///
     /// # Panics:
     ///
     /// Panics if `id` does not exist.
-    pub(crate) fn update_buffer_path(&mut self, id: BufferId, path: &Path) -> Option<Table> {
+    pub(crate) fn update_buffer_path(
+        &mut self,
+        id: BufferId,
+        path: &Path,
+        text: &Rope,
+    ) -> Option<Table> {
         assert!(self.buffer_tags.contains_key(&id));
-        let lang = self.language_for_path(path).unwrap_or_default();
-        let has_changed = self.buffer_tags.get_mut(&id).map(|tag| tag.set_detected(lang)).unwrap();
+        let (lang, first_line) = self.detect_language(Some(path), text);
+        self.buffer_tags.get_mut(&id).map(|tag| tag.set_detected(lang, first_line)).unwrap();
+        self.set_buffer_project(id, self.project_root_for_path(path));
+        self.load_editorconfig(id, path);
+        self.update_buffer_config(id)
+    }
 
-        if has_changed {
+    /// Detects the default language of the given buffer again if it was not
+    /// detected from the buffer's path, and the first line of `text`, the
+    /// buffer's contents, has changed since it was last detected.
+    pub(crate) fn update_first_line(&mut self, id: BufferId, text: &Rope) -> Option<Table> {
+        let first_line = match self.buffer_tags.get(&id).and_then(|tag| tag.first_line.as_ref()) {
+            Some(prev) if *prev != syntax::first_line(text) => syntax::first_line(text),
+            _ => return None,
+        };
+        let lang = self.languages.language_for_first_line(text).map(|lang| lang.name.clone());
+        let first_line = Some(first_line.into_owned());
+        let tag = self.buffer_tags.get_mut(&id).unwrap();
+        if tag.set_detected(lang.unwrap_or_default(), first_line) {
             self.update_buffer_config(id)
         } else {
             None
         }
     }
 
+    /// Detects the language of a buffer from its path, or failing that, from
+    /// the first line of its contents. Returns the language, along with
+    /// the first line if it was used.
+    fn detect_language(&self, path: Option<&Path>, text: &Rope) -> (LanguageId, Option<String>) {
+        if let Some(lang) = path.and_then(|p| self.language_for_path(p)) {
+            return (lang, None);
+        }
+        let lang = self.languages.language_for_first_line(text).map(|lang| lang.name.clone());
+        (lang.unwrap_or_default(), Some(syntax::first_line(text).into_owned()))
+    }
+
+    /// Reloads the `.editorconfig` settings for the given buffer, backed by
+    /// the file at `path`, after an `.editorconfig` file has changed.
+    pub(crate) fn update_editorconfig(&mut self, id: BufferId, path: &Path) -> Option<Table> {
+        self.load_editorconfig(id, path);
+        self.update_buffer_config(id)
+    }
+
+    /// Returns the directories where changes to `.editorconfig` files can
+    /// affect the settings of this buffer.
+    pub(crate) fn get_editorconfig_dirs(&self, id: BufferId) -> &[PathBuf] {
+        self.editorconfig_dirs.get(&id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Returns the root directory of the project containing this buffer.
+    pub(crate) fn get_buffer_project(&self, id: BufferId) -> Option<&Path> {
+        self.buffer_projects.get(&id).map(PathBuf::as_path)
+    }
+
+    /// Returns the root of the innermost project containing the file at
+    /// `path`, which is the closest directory with a config subdirectory.
+    fn project_root_for_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        path.ancestors()
+            .skip(1)
+            .find(|dir| {
+                let config_dir = dir.join(PROJECT_CONFIG_DIR);
+                config_dir.is_dir() && self.config_dir.as_ref() != Some(&config_dir)
+            })
+            .map(Path::to_owned)
+    }
+
+    /// Sets the project containing the given buffer, loading the project's
+    /// configs if it is the first buffer in that project, and dropping the
+    /// configs of its previous project if it was the last.
+    fn set_buffer_project(&mut self, id: BufferId, root: Option<PathBuf>) {
+        let prev_root = match root {
+            Some(ref root) => self.buffer_projects.insert(id, root.clone()),
+            None => self.buffer_projects.remove(&id),
+        };
+        if prev_root == root {
+            return;
+        }
+
+        if let Some(prev_root) = prev_root {
+            if !self.buffer_projects.values().any(|r| r == &prev_root) {
+                self.configs.retain(|domain, _| match domain {
+                    ConfigDomain::Project { root, .. } => root != &prev_root,
+                    _ => true,
+                });
+            }
+        }
+
+        if let Some(root) = root {
+            if self.buffer_projects.values().filter(|r| **r == root).count() == 1 {
+                let languages = self.languages.iter().map(|l| Some(l.name.clone()));
+                let domains = Some(None)
+                    .into_iter()
+                    .chain(languages)
+                    .map(|language| ConfigDomain::Project { root: root.clone(), language })
+                    .collect::<Vec<_>>();
+                self.load_user_config_files(domains);
+            }
+        }
+    }
+
+    fn load_editorconfig(&mut self, id: BufferId, path: &Path) {
+        let EditorConfig { table, dirs } = EditorConfig::for_path(path);
+        let domain = ConfigDomain::EditorConfig(id);
+        if table.is_empty() {
+            self.configs.remove(&domain);
+        } else {
+            self.configs
+                .entry(domain)
+                .or_insert_with(|| ConfigPair::with_base(None))
+                .set_table(table);
+        }
+        self.editorconfig_dirs.insert(id, dirs);
+    }
+
     /// Instructs the `ConfigManager` to stop tracking a given buffer.
     ///
     /// # Panics:
@@ -299,9 +457,35 @@ impl ConfigManager {
     pub(crate) fn remove_buffer(&mut self, id: BufferId) {
         self.buffer_tags.remove(&id).expect("remove key must exist");
         self.buffer_configs.remove(&id);
+        self.configs.remove(&ConfigDomain::EditorConfig(id));
+        self.editorconfig_dirs.remove(&id);
+        self.set_buffer_project(id, None);
         // TODO: remove any overrides
     }
 
+    /// Sets the language named by the given buffer's modelines, such as
+    /// `python` in `vim: ft=python`. This takes precedence over the detected
+    /// language, but not over a language chosen by the user.
+    pub(crate) fn set_modeline_language(&mut self, id: BufferId, mode: &str) -> Option<Table> {
+        let lang = match self.languages.language_for_mode(mode) {
+            Some(lang) => lang.name.clone(),
+            None => {
+                info!("no language for modeline mode {:?}", mode);
+                return None;
+            }
+        };
+        let has_changed = self
+            .buffer_tags
+            .get_mut(&id)
+            .map(|tag| tag.set_modeline(Some(lang)))
+            .expect("buffer must exist");
+        if has_changed {
+            self.update_buffer_config(id)
+        } else {
+            None
+        }
+    }
+
     /// Sets a specific language for the given buffer. This is used if the
     /// user selects a specific language in the frontend, for instance.
     pub(crate) fn override_language(
@@ -349,10 +533,18 @@ impl ConfigManager {
         let mut configs = Vec::new();
 
         configs.push(self.configs.get(&ConfigDomain::General));
-        if let Some(s) = lang {
-            configs.push(self.configs.get(&s.into()))
+        if let Some(ref s) = lang {
+            configs.push(self.configs.get(&s.clone().into()))
         };
+        if let Some(root) = self.buffer_projects.get(&id) {
+            let domain = |language| ConfigDomain::Project { root: root.clone(), language };
+            configs.push(self.configs.get(&domain(None)));
+            if lang.is_some() {
+                configs.push(self.configs.get(&domain(lang.clone())));
+            }
+        }
         configs.push(self.configs.get(&ConfigDomain::SysOverride(id)));
+        configs.push(self.configs.get(&ConfigDomain::EditorConfig(id)));
         configs.push(self.configs.get(&ConfigDomain::UserOverride(id)));
 
         let configs = configs
@@ -385,6 +577,28 @@ impl ConfigManager {
         self.buffer_tags.get(&id).map(LanguageTag::resolve).unwrap()
     }
 
+    /// Returns the definition of the buffer's language, if it is known.
+    pub(crate) fn get_buffer_language_definition(
+        &self,
+        id: BufferId,
+    ) -> Option<Arc<LanguageDefinition>> {
+        self.languages.language_for_name(self.get_buffer_language(id))
+    }
+
+    /// Returns the language the user has chosen for this buffer, if any.
+    pub(crate) fn get_language_override(&self, id: BufferId) -> Option<LanguageId> {
+        self.buffer_tags.get(&id).and_then(|tag| tag.user.clone())
+    }
+
+    /// Returns the settings the user has changed for this buffer alone, if any.
+    pub(crate) fn get_user_override(&self, id: BufferId) -> Option<Table> {
+        self.configs
+            .get(&ConfigDomain::UserOverride(id))
+            .and_then(|pair| pair.user.as_ref())
+            .filter(|table| !table.is_empty())
+            .map(|table| table.as_ref().clone())
+    }
+
     /// Set the available `LanguageDefinition`s. Overrides any previous values.
     pub fn set_languages(&mut self, languages: Languages) {
         // remove base configs for any removed languages
@@ -403,11 +617,16 @@ impl ConfigManager {
                 .entry(domain.clone())
                 .and_modify(|c| *c = c.new_with_base(default_config.clone()))
                 .or_insert_with(|| ConfigPair::with_base(default_config));
-            if let Some(table) = self.load_user_config_file(&domain) {
-                // we can't report this error because we don't have a
-                // handle to the peer :|
-                let _ = self.set_user_config(domain, table);
-            }
+            self.load_user_config_files(vec![domain]);
+
+            let mut roots = self.buffer_projects.values().cloned().collect::<Vec<_>>();
+            roots.sort();
+            roots.dedup();
+            let project_domains = roots
+                .into_iter()
+                .map(|root| ConfigDomain::Project { root, language: Some(language.name.clone()) })
+                .collect();
+            self.load_user_config_files(project_domains);
         }
         //FIXME these changes are happening silently, which won't work once
         //languages can by dynamically changed
@@ -415,25 +634,42 @@ impl ConfigManager {
         self.update_all_buffer_configs();
     }
 
-    fn load_user_config_file(&self, domain: &ConfigDomain) -> Option<Table> {
-        let path = self
-            .config_dir
-            .as_ref()
-            .map(|p| p.join(domain.file_stem()).with_extension("xiconfig"))?;
-
-        if !path.exists() {
-            return None;
-        }
-
-        match try_load_from_file(&path) {
-            Ok(t) => Some(t),
-            Err(e) => {
+    /// Loads the config files for the given domains. Any errors are kept
+    /// until they are retrieved with `take_errors`.
+    fn load_user_config_files(&mut self, domains: Vec<ConfigDomain>) {
+        for domain in domains {
+            let path = match self.user_config_file_path(&domain) {
+                Some(path) => path,
+                None => continue,
+            };
+            let result = try_load_from_file(&path)
+                .and_then(|table| self.set_user_config_from(domain, table, Some(&path)));
+            if let Err(e) = result {
                 error!("Error loading config: {:?}", e);
-                None
+                self.errors.extend(e.into_validation_errors(Some(&path)));
             }
         }
     }
 
+    /// Returns the path of the config file for the given domain, if it exists.
+    fn user_config_file_path(&self, domain: &ConfigDomain) -> Option<PathBuf> {
+        let dir = match domain {
+            ConfigDomain::Project { root, .. } => root.join(PROJECT_CONFIG_DIR),
+            _ => self.config_dir.clone()?,
+        };
+        Some(dir.join(domain.file_stem()).with_extension("xiconfig")).filter(|p| p.exists())
+    }
+
+    /// Returns, and forgets, the errors from loading config files and the
+    /// keys dropped from config tables since this was last called.
+    pub(crate) fn take_errors(&mut self) -> Vec<ValidationError> {
+        ::std::mem::replace(&mut self.errors, Vec::new())
+    }
+
+    pub(crate) fn get_config_schema(&self) -> &ConfigSchema {
+        &self.schema
+    }
+
     pub fn language_for_path(&self, path: &Path) -> Option<LanguageId> {
         self.languages.language_for_path(path).map(|lang| lang.name.clone())
     }
@@ -441,12 +677,27 @@ impl ConfigManager {
     /// Sets the config for the given domain, removing any existing config.
     /// Returns a `Vec` of individual buffer config changes that result from
     /// this update, or a `ConfigError` if `config` is poorly formed.
+    ///
+    /// Keys that are unknown, or whose values don't match the schema, are
+    /// dropped, and the rest of the table is applied. An error for each
+    /// dropped key is kept until it is retrieved with `take_errors`.
     pub fn set_user_config(
         &mut self,
         domain: ConfigDomain,
         config: Table,
     ) -> Result<Vec<(BufferId, Table)>, ConfigError> {
-        self.check_table(&config)?;
+        self.set_user_config_from(domain, config, None)
+    }
+
+    /// Like `set_user_config`, for a table loaded from the config file at
+    /// `path`, which is included in the errors for any dropped keys.
+    pub(crate) fn set_user_config_from(
+        &mut self,
+        domain: ConfigDomain,
+        mut config: Table,
+        path: Option<&Path>,
+    ) -> Result<Vec<(BufferId, Table)>, ConfigError> {
+        self.check_table(&mut config, path)?;
         self.configs
             .entry(domain.clone())
             .or_insert_with(|| ConfigPair::with_base(None))
@@ -485,6 +736,33 @@ impl ConfigManager {
         if path.extension().map(|e| e != "xiconfig").unwrap_or(true) {
             return None;
         }
+        let project_root = path
+            .parent()
+            .filter(|dir| dir.ends_with(PROJECT_CONFIG_DIR))
+            .filter(|dir| self.config_dir.as_ref().map(|d| d.as_path() != *dir).unwrap_or(true))
+            .and_then(Path::parent);
+        let domain = self.global_domain_for_path(path);
+        let root = match project_root {
+            Some(root) => root,
+            None => return domain,
+        };
+        // only the projects of open buffers have configs
+        if !self.buffer_projects.values().any(|r| r == root) {
+            return None;
+        }
+        match domain {
+            Some(ConfigDomain::General) => {
+                Some(ConfigDomain::Project { root: root.to_owned(), language: None })
+            }
+            Some(ConfigDomain::Language(lang)) => {
+                Some(ConfigDomain::Project { root: root.to_owned(), language: Some(lang) })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the domain of a config file in the user's config directory.
+    fn global_domain_for_path(&self, path: &Path) -> Option<ConfigDomain> {
         match path.file_stem().and_then(|s| s.to_str()) {
             Some("preferences") => Some(ConfigDomain::General),
             Some(name) if self.languages.language_for_name(&name).is_some() => {
@@ -497,7 +775,15 @@ impl ConfigManager {
         }
     }
 
-    fn check_table(&self, table: &Table) -> Result<(), ConfigError> {
+    /// Removes the keys of `table` that don't match the schema, keeping an
+    /// error for each of them, and checks the rest.
+    fn check_table(&mut self, table: &mut Table, path: Option<&Path>) -> Result<(), ConfigError> {
+        for error in self.schema.validate(table) {
+            if let Some(ref key) = error.key {
+                table.remove(key);
+            }
+            self.errors.push(ValidationError { path: path.map(Path::to_owned), ..error });
+        }
         let defaults = self
             .configs
             .get(&ConfigDomain::General)
@@ -515,6 +801,17 @@ impl ConfigManager {
         Ok(())
     }
 
+    /// Path to the directory inside the config directory where recovery
+    /// files are kept. The directory is created when it is first needed.
+    pub(crate) fn get_recovery_dir(&self) -> Option<PathBuf> {
+        self.config_dir.as_ref().map(|p| p.join("recovery"))
+    }
+
+    /// Path of the file the editing session is saved to by default.
+    pub(crate) fn get_session_path(&self) -> Option<PathBuf> {
+        self.config_dir.as_ref().map(|p| p.join("session.json"))
+    }
+
     /// Path to themes sub directory inside config directory.
     /// Creates one if not present.
     pub(crate) fn get_themes_dir(&self) -> Option<PathBuf> {
@@ -530,6 +827,18 @@ impl ConfigManager {
         }
         None
     }
+
+    /// Path to the snippets sub directory inside the config directory,
+    /// which holds a snippets file for each language.
+    /// Creates one if not present.
+    pub(crate) fn get_snippets_dir(&self) -> Option<PathBuf> {
+        let snippets_dir = self.config_dir.as_ref().map(|p| p.join("snippets"))?;
+        if snippets_dir.exists() || fs::DirBuilder::new().create(&snippets_dir).is_ok() {
+            Some(snippets_dir)
+        } else {
+            None
+        }
+    }
 }
 
 impl TableStack {
@@ -609,25 +918,38 @@ impl ConfigDomain {
         match self {
             ConfigDomain::General => "preferences",
             ConfigDomain::Language(lang) => lang.as_ref(),
-            ConfigDomain::UserOverride(_) | ConfigDomain::SysOverride(_) => "we don't have files",
+            ConfigDomain::Project { language: Some(lang), .. } => lang.as_ref(),
+            ConfigDomain::Project { language: None, .. } => "preferences",
+            ConfigDomain::UserOverride(_)
+            | ConfigDomain::SysOverride(_)
+            | ConfigDomain::EditorConfig(_) => "we don't have files",
         }
     }
 }
 
 impl LanguageTag {
-    fn new(detected: LanguageId) -> Self {
-        LanguageTag { detected, user: None }
+    fn new(detected: LanguageId, first_line: Option<String>) -> Self {
+        LanguageTag { detected, modeline: None, user: None, first_line }
     }
 
     fn resolve(&self) -> LanguageId {
-        self.user.as_ref().unwrap_or(&self.detected).clone()
+        self.user.as_ref().or_else(|| self.modeline.as_ref()).unwrap_or(&self.detected).clone()
+    }
+
+    /// Set the language from the buffer's modelines. Returns `true` if this
+    /// changes the resolved language.
+    fn set_modeline(&mut self, modeline: Option<LanguageId>) -> bool {
+        let before = self.resolve();
+        self.modeline = modeline;
+        before != self.resolve()
     }
 
     /// Set the detected language. Returns `true` if this changes the resolved
     /// language.
-    fn set_detected(&mut self, detected: LanguageId) -> bool {
+    fn set_detected(&mut self, detected: LanguageId, first_line: Option<String>) -> bool {
         let before = self.resolve();
         self.detected = detected;
+        self.first_line = first_line;
         before != self.resolve()
     }
 
@@ -683,6 +1005,20 @@ impl Error for ConfigError {
     }
 }
 
+impl ConfigError {
+    /// Converts this error into the errors reported to the client, where
+    /// `path` is the config file being loaded, if any.
+    pub(crate) fn into_validation_errors(self, path: Option<&Path>) -> Vec<ValidationError> {
+        let path = path.map(Path::to_owned);
+        match self {
+            ConfigError::Parse(path, e) => {
+                vec![ValidationError { path: Some(path), key: None, message: e.to_string() }]
+            }
+            other => vec![ValidationError { path, key: None, message: other.to_string() }],
+        }
+    }
+}
+
 impl From<io::Error> for ConfigError {
     fn from(src: io::Error) -> ConfigError {
         ConfigError::Io(src)
@@ -751,6 +1087,7 @@ fn from_toml_value(value: toml::Value) -> Value {
 mod tests {
     use super::*;
     use syntax::LanguageDefinition;
+    use test_fixtures::TempDir;
 
     #[test]
     fn test_overrides() {
@@ -772,9 +1109,9 @@ mod tests {
         let changes = json!({"tab_size": 67}).as_object().unwrap().to_owned();
         manager.set_user_config(ConfigDomain::SysOverride(buf_id_3), changes).unwrap();
 
-        manager.add_buffer(buf_id_1, None);
-        manager.add_buffer(buf_id_2, Some(Path::new("file.rs")));
-        manager.add_buffer(buf_id_3, Some(Path::new("file2.rs")));
+        manager.add_buffer(buf_id_1, None, &Rope::from(""));
+        manager.add_buffer(buf_id_2, Some(Path::new("file.rs")), &Rope::from(""));
+        manager.add_buffer(buf_id_3, Some(Path::new("file2.rs")), &Rope::from(""));
 
         // system override
         let config = manager.get_buffer_config(buf_id_1).to_owned();
@@ -826,7 +1163,7 @@ translate_tabs_to_spaces = true
     fn test_updating_in_place() {
         let mut manager = ConfigManager::new(None, None);
         let buf_id = BufferId(1);
-        manager.add_buffer(buf_id, None);
+        manager.add_buffer(buf_id, None, &Rope::from(""));
         assert_eq!(manager.get_buffer_config(buf_id).items.font_size, 14.);
         let changes = json!({"font_size": 69, "font_face": "nice"}).as_object().unwrap().to_owned();
         let table = manager.table_for_update(ConfigDomain::General, changes);
@@ -854,7 +1191,7 @@ translate_tabs_to_spaces = true
         assert_eq!(manager.languages.iter().count(), 1);
 
         let buf_id = BufferId(1);
-        manager.add_buffer(buf_id, Some(Path::new("file.rs")));
+        manager.add_buffer(buf_id, Some(Path::new("file.rs")), &Rope::from(""));
 
         let config = manager.get_buffer_config(buf_id).to_owned();
         assert_eq!(config.source.0.len(), 2);
@@ -894,6 +1231,188 @@ translate_tabs_to_spaces = true
         assert_eq!(config.items.font_size, 14.);
     }
 
+    #[test]
+    fn editorconfig_overrides() {
+        let temp_dir = TempDir::new("config");
+        let dir = temp_dir.path();
+        fs::write(dir.join(".editorconfig"), "root = true\n[*.rs]\nindent_size = 2\n").unwrap();
+        let path = dir.join("file.rs");
+        fs::write(&path, "").unwrap();
+
+        let rust_config = json!({"tab_size": 31, "font_size": 20}).as_object().unwrap().to_owned();
+        let mut manager = ConfigManager::new(None, None);
+        manager.set_languages(Languages::new(&[rust_lang_def(None)]));
+        manager.set_user_config(LanguageId::from("Rust").into(), rust_config).unwrap();
+        let buf_id = BufferId(1);
+        let changes = json!({"tab_size": 8}).as_object().unwrap().to_owned();
+        manager.set_user_config(ConfigDomain::SysOverride(buf_id), changes).unwrap();
+
+        // editorconfig trumps language and system settings
+        manager.add_buffer(buf_id, Some(&path), &Rope::from(""));
+        assert_eq!(manager.get_buffer_config(buf_id).items.tab_size, 2);
+        assert_eq!(manager.get_buffer_config(buf_id).items.font_size, 20.);
+
+        // user override trumps editorconfig
+        let changes = json!({"tab_size": 85}).as_object().unwrap().to_owned();
+        manager.set_user_config(ConfigDomain::UserOverride(buf_id), changes).unwrap();
+        assert_eq!(manager.get_buffer_config(buf_id).items.tab_size, 85);
+        manager.set_user_config(ConfigDomain::UserOverride(buf_id), Table::new()).unwrap();
+
+        fs::write(dir.join(".editorconfig"), "root = true\n[*.rs]\nindent_size = 3\n").unwrap();
+        let changes = manager.update_editorconfig(buf_id, &path).unwrap();
+        assert_eq!(changes.get("tab_size"), Some(&3.into()));
+        assert!(manager.get_editorconfig_dirs(buf_id).contains(&fs::canonicalize(dir).unwrap()));
+
+        fs::remove_file(dir.join(".editorconfig")).unwrap();
+        manager.update_editorconfig(buf_id, &path);
+        assert_eq!(manager.get_buffer_config(buf_id).items.tab_size, 8);
+    }
+
+    #[test]
+    fn project_overrides() {
+        let temp_dir = TempDir::new("project");
+        fs::create_dir_all(temp_dir.path().join(PROJECT_CONFIG_DIR)).unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        let project_dir = root.join(PROJECT_CONFIG_DIR);
+        fs::write(project_dir.join("preferences.xiconfig"), "tab_size = 5\nfont_size = 30")
+            .unwrap();
+        fs::write(project_dir.join("Rust.xiconfig"), "tab_size = 6").unwrap();
+
+        let user_config =
+            json!({"tab_size": 42, "font_face": "nice"}).as_object().unwrap().to_owned();
+        let rust_config = json!({"tab_size": 31}).as_object().unwrap().to_owned();
+        let mut manager = ConfigManager::new(None, None);
+        manager.set_languages(Languages::new(&[rust_lang_def(None)]));
+        manager.set_user_config(ConfigDomain::General, user_config).unwrap();
+        manager.set_user_config(LanguageId::from("Rust").into(), rust_config).unwrap();
+
+        let buf_id_1 = BufferId(1); // rust, in the project
+        let buf_id_2 = BufferId(2); // no language, in the project
+        let buf_id_3 = BufferId(3); // rust, outside the project
+        manager.add_buffer(buf_id_1, Some(&root.join("src/main.rs")), &Rope::from(""));
+        manager.add_buffer(buf_id_2, Some(&root.join("notes.txt")), &Rope::from(""));
+        manager.add_buffer(buf_id_3, Some(Path::new("/no/such/file.rs")), &Rope::from(""));
+        assert_eq!(manager.get_buffer_project(buf_id_1), Some(root.as_path()));
+
+        // project language config trumps project, language and general configs
+        let config = manager.get_buffer_config(buf_id_1);
+        assert_eq!(config.items.tab_size, 6);
+        assert_eq!(config.items.font_size, 30.);
+        assert_eq!(config.items.font_face, "nice");
+        // project config trumps general config
+        assert_eq!(manager.get_buffer_config(buf_id_2).items.tab_size, 5);
+        assert_eq!(manager.get_buffer_config(buf_id_3).items.tab_size, 31);
+
+        // system override trumps project configs
+        let changes = json!({"tab_size": 67}).as_object().unwrap().to_owned();
+        manager.set_user_config(ConfigDomain::SysOverride(buf_id_1), changes).unwrap();
+        assert_eq!(manager.get_buffer_config(buf_id_1).items.tab_size, 67);
+
+        // project config files are reloaded like the user's
+        let path = project_dir.join("preferences.xiconfig");
+        let domain = manager.domain_for_path(&path).unwrap();
+        assert_eq!(domain, ConfigDomain::Project { root: root.clone(), language: None });
+        let changes = manager.set_user_config(domain, Table::new()).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(manager.get_buffer_config(buf_id_2).items.tab_size, 42);
+
+        // project configs are dropped with the last buffer in the project
+        manager.remove_buffer(buf_id_1);
+        manager.remove_buffer(buf_id_2);
+        assert_eq!(manager.domain_for_path(&path), None);
+        assert!(manager.configs.keys().all(|d| match d {
+            ConfigDomain::Project { .. } => false,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn invalid_config_files() {
+        let temp_dir = TempDir::new("invalid-config");
+        fs::create_dir_all(temp_dir.path().join(PROJECT_CONFIG_DIR)).unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        let project_dir = root.join(PROJECT_CONFIG_DIR);
+        fs::write(project_dir.join("preferences.xiconfig"), "tab_size = 5\nword_wrap = 1").unwrap();
+        fs::write(project_dir.join("Rust.xiconfig"), "tab_size = ").unwrap();
+
+        let mut manager = ConfigManager::new(None, None);
+        manager.set_languages(Languages::new(&[rust_lang_def(None)]));
+        let buf_id = BufferId(1);
+        manager.add_buffer(buf_id, Some(&root.join("main.rs")), &Rope::from(""));
+
+        // invalid keys are dropped, and the rest of their tables applied,
+        // while tables that can't be parsed are ignored
+        assert_eq!(manager.get_buffer_config(buf_id).items.tab_size, 5);
+        assert!(!manager.get_buffer_config(buf_id).items.word_wrap);
+        let mut errors = manager.take_errors();
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, Some(project_dir.join("Rust.xiconfig")));
+        assert_eq!(errors[0].key, None);
+        assert_eq!(errors[1].path, Some(project_dir.join("preferences.xiconfig")));
+        assert_eq!(errors[1].key.as_ref().map(String::as_str), Some("word_wrap"));
+        assert!(manager.take_errors().is_empty());
+
+        let table = json!({"line_ending": "\r", "tab_size": 3}).as_object().unwrap().to_owned();
+        manager.set_user_config(ConfigDomain::General, table).unwrap();
+        let errors = manager.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, None);
+        assert_eq!(errors[0].key.as_ref().map(String::as_str), Some("line_ending"));
+        manager.add_buffer(BufferId(2), None, &Rope::from(""));
+        assert_eq!(manager.get_buffer_config(BufferId(2)).items.tab_size, 3);
+    }
+
+    #[test]
+    fn detect_from_first_line() {
+        let mut lang_def = rust_lang_def(None);
+        lang_def.first_line_match = Some("^#!.*rust-script".into());
+        let rust_config = json!({"tab_size": 31}).as_object().unwrap().to_owned();
+        let mut manager = ConfigManager::new(None, None);
+        manager.set_languages(Languages::new(&[lang_def]));
+        manager.set_user_config(LanguageId::from("Rust").into(), rust_config).unwrap();
+
+        let buf_id_1 = BufferId(1); // unsaved
+        let buf_id_2 = BufferId(2); // unknown extension
+        manager.add_buffer(buf_id_1, None, &Rope::from("#!/usr/bin/env rust-script\n"));
+        manager.add_buffer(buf_id_2, Some(Path::new("a.txt")), &Rope::from("#! rust-script"));
+        assert_eq!(manager.get_buffer_language(buf_id_1), "Rust".into());
+        assert_eq!(manager.get_buffer_language(buf_id_2), "Rust".into());
+        manager.update_buffer_path(buf_id_2, Path::new("a.rs"), &Rope::from(""));
+
+        // changes to other lines are ignored
+        let text = Rope::from("#!/usr/bin/env rust-script\nfn main() {}\n");
+        assert_eq!(manager.update_first_line(buf_id_1, &text), None);
+        let changes = manager.update_first_line(buf_id_1, &Rope::from("#!/bin/sh\n")).unwrap();
+        assert_eq!(changes.get("tab_size"), Some(&json!(4)));
+        assert_eq!(manager.get_buffer_language(buf_id_1), LanguageId::default());
+
+        // languages detected from the path don't change
+        assert_eq!(manager.update_first_line(buf_id_2, &Rope::from("#!/bin/sh\n")), None);
+        assert_eq!(manager.get_buffer_language(buf_id_2), "Rust".into());
+    }
+
+    #[test]
+    fn modeline_language() {
+        let mut manager = ConfigManager::new(None, None);
+        let toml_def = LanguageDefinition::simple("TOML", &["toml"], "source.toml", None);
+        manager.set_languages(Languages::new(&[rust_lang_def(None), toml_def]));
+        let buf_id = BufferId(1);
+        manager.add_buffer(buf_id, Some(Path::new("Cargo.toml")), &Rope::from(""));
+
+        assert_eq!(manager.set_modeline_language(buf_id, "text"), None);
+        manager.set_modeline_language(buf_id, "rust");
+        assert_eq!(manager.get_buffer_language(buf_id), "Rust".into());
+        // the modeline language survives the language being detected again
+        manager.update_buffer_path(buf_id, Path::new("Other.toml"), &Rope::from(""));
+        assert_eq!(manager.get_buffer_language(buf_id), "Rust".into());
+        // the user's choice trumps the modeline
+        manager.override_language(buf_id, "TOML".into());
+        manager.set_modeline_language(buf_id, "rs");
+        assert_eq!(manager.get_buffer_language(buf_id), "TOML".into());
+    }
+
     fn rust_lang_def<T: Into<Option<Table>>>(defaults: T) -> LanguageDefinition {
         LanguageDefinition::simple("Rust", &["rs"], "source.rust", defaults.into())
     }
@@ -0,0 +1,194 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The schema of the known config keys, which is used to validate config
+//! tables, and which clients can use to present the settings to the user.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde_json::{self, Value};
+
+use config::{table_from_toml_str, Table};
+
+/// The type of the value of a config key.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    Bool,
+    Integer,
+    Number,
+    String,
+    StringList,
+    StringPairs,
+}
+
+/// The description of a single config key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeySchema {
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub default: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<Value>,
+    pub description: String,
+}
+
+/// The schemas of all known config keys.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSchema(BTreeMap<String, KeySchema>);
+
+/// A problem with a config key, or, if `key` is absent, with a whole
+/// config file. These are reported to the client.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationError {
+    /// The config file containing the key, if it was loaded from a file.
+    pub path: Option<PathBuf>,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl ConfigSchema {
+    /// Loads the included schema, with the default value of each key from
+    /// `defaults`.
+    pub(crate) fn load(defaults: &Table) -> ConfigSchema {
+        let table = table_from_toml_str(include_str!("../assets/config_schema.toml"))
+            .expect("config schema must load");
+        let mut keys: BTreeMap<String, KeySchema> =
+            serde_json::from_value(table.into()).expect("config schema must be valid");
+        for (key, schema) in keys.iter_mut() {
+            schema.default = defaults.get(key).cloned().unwrap_or(Value::Null);
+        }
+        ConfigSchema(keys)
+    }
+
+    /// Checks each key of `table` against the schema, returning an error
+    /// for each key that is unknown, or whose value is invalid. `null`
+    /// values, which clear a key, are always valid.
+    pub fn validate(&self, table: &Table) -> Vec<ValidationError> {
+        table
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .filter_map(|(key, value)| {
+                let message = match self.0.get(key) {
+                    Some(schema) => schema.check(value).err()?,
+                    None => "unknown config key".to_owned(),
+                };
+                Some(ValidationError { path: None, key: Some(key.to_owned()), message })
+            })
+            .collect()
+    }
+}
+
+impl KeySchema {
+    /// Returns a message describing the problem if `value` is not valid for
+    /// this key.
+    fn check(&self, value: &Value) -> Result<(), String> {
+        let type_matches = match self.value_type {
+            ValueType::Bool => value.is_boolean(),
+            ValueType::Integer => value.is_u64() || value.is_i64(),
+            ValueType::Number => value.is_number(),
+            ValueType::String => value.is_string(),
+            ValueType::StringList => is_array_of(value, Value::is_string),
+            ValueType::StringPairs => is_array_of(value, |pair| {
+                is_array_of(pair, Value::is_string) && pair.as_array().unwrap().len() == 2
+            }),
+        };
+        if !type_matches {
+            return Err(format!("expected {}, found {}", self.value_type.description(), value));
+        }
+
+        let out_of_range = value.as_f64().map_or(false, |n| {
+            self.min.map_or(false, |min| n < min) || self.max.map_or(false, |max| n > max)
+        });
+        if out_of_range {
+            let range = match (self.min, self.max) {
+                (Some(min), Some(max)) => format!("from {} to {}", min, max),
+                (Some(min), None) => format!("of at least {}", min),
+                (None, _) => format!("of at most {}", self.max.unwrap()),
+            };
+            return Err(format!("expected a value {}, found {}", range, value));
+        }
+
+        if !self.allowed.is_empty() && !self.allowed.contains(value) {
+            let allowed = self.allowed.iter().map(Value::to_string).collect::<Vec<_>>();
+            return Err(format!("expected one of {}, found {}", allowed.join(", "), value));
+        }
+        Ok(())
+    }
+}
+
+impl ValueType {
+    fn description(self) -> &'static str {
+        match self {
+            ValueType::Bool => "a boolean",
+            ValueType::Integer => "an integer",
+            ValueType::Number => "a number",
+            ValueType::String => "a string",
+            ValueType::StringList => "a list of strings",
+            ValueType::StringPairs => "a list of pairs of strings",
+        }
+    }
+}
+
+fn is_array_of<F: Fn(&Value) -> bool>(value: &Value, f: F) -> bool {
+    value.as_array().map_or(false, |items| items.iter().all(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{BufferItems, ConfigManager};
+
+    fn schema() -> ConfigSchema {
+        ConfigManager::new(None, None).get_config_schema().clone()
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let schema = schema();
+        let defaults =
+            schema.0.iter().map(|(k, s)| (k.clone(), s.default.clone())).collect::<Table>();
+        assert_eq!(schema.validate(&defaults), Vec::new());
+        // every buffer setting has a schema
+        let _: BufferItems = serde_json::from_value(defaults.into()).unwrap();
+    }
+
+    #[test]
+    fn validate() {
+        let schema = schema();
+        let table = json!({
+            "tab_size": 0,
+            "font_size": 12.5,
+            "line_ending": "\r",
+            "word_wrap": "yes",
+            "surrounding_pairs": [["(", ")"], ["<"]],
+            "auto_indent": null,
+            "tab_sise": 4,
+        });
+        let errors = schema.validate(table.as_object().unwrap());
+        let keys = errors.iter().map(|e| e.key.clone().unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["line_ending", "surrounding_pairs", "tab_sise", "tab_size", "word_wrap"]
+        );
+        assert_eq!(errors[3].message, "expected a value of at least 1, found 0");
+        assert_eq!(errors[4].message, "expected a boolean, found \"yes\"");
+    }
+}
@@ -18,6 +18,7 @@
 //! This simplifies code elsewhere, and makes it easier to route events to
 //! the editor or view as appropriate.
 
+use modal::TextObject;
 use movement::Movement;
 use rpc::{
     EditNotification, FindQuery, GestureType, LineRange, MouseAction, Position, SelectionModifier,
@@ -33,6 +34,7 @@ pub(crate) enum ViewEvent {
     Scroll(LineRange),
     AddSelectionAbove,
     AddSelectionBelow,
+    ColumnSelect(Movement),
     Click(MouseAction),
     Drag(MouseAction),
     Gesture { line: u64, col: u64, ty: GestureType },
@@ -48,12 +50,24 @@ pub(crate) enum ViewEvent {
     Replace { chars: String, preserve_case: bool },
     SelectionForReplace,
     SelectionIntoLines,
+    ColumnSelectionIntoLines,
+    SelectMatchingBrackets,
+    ExpandSelection,
+    ShrinkSelection,
+    SelectTextObject { object: TextObject, count: usize },
+    NextTabStop,
+    PrevTabStop,
+    Fold,
+    Unfold,
+    ToggleFold,
+    UnfoldAll,
 }
 
 /// Events that modify the buffer
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum BufferEvent {
     Delete { movement: Movement, kill: bool },
+    DeleteTextObject(TextObject),
     Backspace,
     Transpose,
     Undo,
@@ -65,14 +79,27 @@ pub(crate) enum BufferEvent {
     Outdent,
     Insert(String),
     Paste(String),
+    PasteRegister(String),
     InsertNewline,
     InsertTab,
     Yank,
+    YankPop,
+    CopyToKillRing,
     ReplaceNext,
     ReplaceAll,
     DuplicateLine,
     IncreaseNumber,
     DecreaseNumber,
+    ToggleComment,
+    ToggleBlockComment,
+    SortLines,
+    SortLinesCaseInsensitive,
+    SortLinesNatural,
+    UniqueLines,
+    ReverseLines,
+    JoinLines,
+    MoveLinesUp,
+    MoveLinesDown,
 }
 
 /// An event that needs special handling
@@ -87,6 +114,10 @@ pub(crate) enum SpecialEvent {
     ToggleRecording(Option<String>),
     PlayRecording(String),
     ClearRecording(String),
+    InsertSnippet(String),
+    ExpandSnippet,
+    RequestCompletions { request_id: usize },
+    AcceptCompletion { request_id: usize, index: usize },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -121,7 +152,9 @@ impl From<EditNotification> for EventDomain {
         match src {
             Insert { chars } =>
                 BufferEvent::Insert(chars).into(),
-            Paste { chars } =>
+            Paste { register: Some(register), .. } =>
+                BufferEvent::PasteRegister(register).into(),
+            Paste { chars, .. } =>
                 BufferEvent::Paste(chars).into(),
             DeleteForward =>
                 BufferEvent::Delete {
@@ -140,6 +173,16 @@ impl From<EditNotification> for EventDomain {
                     movement: Movement::LeftWord,
                     kill: false
                 }.into(),
+            DeleteSubwordForward =>
+                BufferEvent::Delete {
+                    movement: Movement::RightSubword,
+                    kill: false
+                }.into(),
+            DeleteSubwordBackward =>
+                BufferEvent::Delete {
+                    movement: Movement::LeftSubword,
+                    kill: false
+                }.into(),
             DeleteToEndOfParagraph =>
                 BufferEvent::Delete {
                     movement: Movement::EndOfParagraphKill,
@@ -178,6 +221,14 @@ impl From<EditNotification> for EventDomain {
                 ViewEvent::Move(Movement::RightWord).into(),
             MoveWordRightAndModifySelection =>
                 ViewEvent::ModifySelection(Movement::RightWord).into(),
+            MoveSubwordLeft =>
+                ViewEvent::Move(Movement::LeftSubword).into(),
+            MoveSubwordLeftAndModifySelection =>
+                ViewEvent::ModifySelection(Movement::LeftSubword).into(),
+            MoveSubwordRight =>
+                ViewEvent::Move(Movement::RightSubword).into(),
+            MoveSubwordRightAndModifySelection =>
+                ViewEvent::ModifySelection(Movement::RightSubword).into(),
             MoveToBeginningOfParagraph =>
                 ViewEvent::Move(Movement::StartOfParagraph).into(),
             MoveToBeginningOfParagraphAndModifySelection =>
@@ -202,6 +253,10 @@ impl From<EditNotification> for EventDomain {
                 ViewEvent::Move(Movement::EndOfDocument).into(),
             MoveToEndOfDocumentAndModifySelection =>
                 ViewEvent::ModifySelection(Movement::EndOfDocument).into(),
+            MoveToMatchingBracket =>
+                ViewEvent::Move(Movement::MatchingBracket).into(),
+            MoveToMatchingBracketAndModifySelection =>
+                ViewEvent::ModifySelection(Movement::MatchingBracket).into(),
             ScrollPageUp =>
                 ViewEvent::Move(Movement::UpPage).into(),
             PageUpAndModifySelection =>
@@ -213,11 +268,16 @@ impl From<EditNotification> for EventDomain {
             SelectAll => ViewEvent::SelectAll.into(),
             AddSelectionAbove => ViewEvent::AddSelectionAbove.into(),
             AddSelectionBelow => ViewEvent::AddSelectionBelow.into(),
+            ColumnSelectUp => ViewEvent::ColumnSelect(Movement::Up).into(),
+            ColumnSelectDown => ViewEvent::ColumnSelect(Movement::Down).into(),
+            ColumnSelectLeft => ViewEvent::ColumnSelect(Movement::Left).into(),
+            ColumnSelectRight => ViewEvent::ColumnSelect(Movement::Right).into(),
             Scroll(range) => ViewEvent::Scroll(range).into(),
             Resize(size) => SpecialEvent::Resize(size).into(),
             GotoLine { line } => ViewEvent::GotoLine { line }.into(),
             RequestLines(range) => SpecialEvent::RequestLines(range).into(),
             Yank => BufferEvent::Yank.into(),
+            YankPop => BufferEvent::YankPop.into(),
             Transpose => BufferEvent::Transpose.into(),
             Click(action) => ViewEvent::Click(action).into(),
             Drag(action) => ViewEvent::Drag(action).into(),
@@ -254,9 +314,42 @@ impl From<EditNotification> for EventDomain {
             RequestHover { request_id, position } =>
                 SpecialEvent::RequestHover { request_id, position }.into(),
             SelectionIntoLines => ViewEvent::SelectionIntoLines.into(),
+            ColumnSelectionIntoLines => ViewEvent::ColumnSelectionIntoLines.into(),
+            SelectMatchingBrackets => ViewEvent::SelectMatchingBrackets.into(),
+            ExpandSelection => ViewEvent::ExpandSelection.into(),
+            ShrinkSelection => ViewEvent::ShrinkSelection.into(),
+            SelectTextObject { object, inner } =>
+                ViewEvent::SelectTextObject {
+                    object: TextObject::new(object, inner),
+                    count: 1
+                }.into(),
+            DeleteTextObject { object, inner } =>
+                BufferEvent::DeleteTextObject(TextObject::new(object, inner)).into(),
+            Fold => ViewEvent::Fold.into(),
+            Unfold => ViewEvent::Unfold.into(),
+            ToggleFold => ViewEvent::ToggleFold.into(),
+            UnfoldAll => ViewEvent::UnfoldAll.into(),
             DuplicateLine => BufferEvent::DuplicateLine.into(),
             IncreaseNumber => BufferEvent::IncreaseNumber.into(),
             DecreaseNumber => BufferEvent::DecreaseNumber.into(),
+            ToggleComment => BufferEvent::ToggleComment.into(),
+            ToggleBlockComment => BufferEvent::ToggleBlockComment.into(),
+            SortLines => BufferEvent::SortLines.into(),
+            SortLinesCaseInsensitive => BufferEvent::SortLinesCaseInsensitive.into(),
+            SortLinesNatural => BufferEvent::SortLinesNatural.into(),
+            UniqueLines => BufferEvent::UniqueLines.into(),
+            ReverseLines => BufferEvent::ReverseLines.into(),
+            JoinLines => BufferEvent::JoinLines.into(),
+            MoveLinesUp => BufferEvent::MoveLinesUp.into(),
+            MoveLinesDown => BufferEvent::MoveLinesDown.into(),
+            InsertSnippet { snippet } => SpecialEvent::InsertSnippet(snippet).into(),
+            ExpandSnippet => SpecialEvent::ExpandSnippet.into(),
+            RequestCompletions { request_id } =>
+                SpecialEvent::RequestCompletions { request_id }.into(),
+            AcceptCompletion { request_id, index } =>
+                SpecialEvent::AcceptCompletion { request_id, index }.into(),
+            NextTabStop => ViewEvent::NextTabStop.into(),
+            PrevTabStop => ViewEvent::PrevTabStop.into(),
             ToggleRecording { recording_name } => SpecialEvent::ToggleRecording(recording_name).into(),
             PlayRecording { recording_name } => SpecialEvent::PlayRecording(recording_name).into(),
             ClearRecording { recording_name } => SpecialEvent::ClearRecording(recording_name).into(),
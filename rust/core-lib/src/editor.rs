@@ -14,28 +14,36 @@
 
 use std::borrow::{Borrow, Cow};
 use std::cmp::min;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
 
 use serde_json::Value;
 
-use xi_rope::diff::{Diff, LineHashDiff};
 use xi_rope::engine::{Engine, RevId, RevToken};
 use xi_rope::rope::count_newlines;
 use xi_rope::spans::SpansBuilder;
 use xi_rope::{Cursor, DeltaBuilder, Interval, LinesMetric, Rope, RopeDelta, Transformer};
 use xi_trace::{trace_block, trace_payload};
 
+use comment::{toggle_block_comment, toggle_line_comment};
+use completion;
 use config::BufferItems;
 use edit_types::BufferEvent;
 use event_context::MAX_SIZE_LIMIT;
+use kill_ring::{Kill, KillRing};
 use layers::Layers;
+use line_ops::{self, dedup_lines, natural_cmp};
+use merge::{compute_delta, three_way_merge};
+use modal::{text_object_region, TextObject};
 use movement::{region_movement, Movement};
 use plugins::rpc::{GetDataResponse, PluginEdit, ScopeSpan, TextUnit};
 use plugins::PluginId;
 use rpc::SelectionModifier;
 use selection::{InsertDrift, SelRegion, Selection};
+use snippet::{self, Snippet, SnippetSession};
 use styles::ThemeStyleMap;
-use view::{Replace, View};
+use syntax::LanguageDefinition;
+use view::View;
 use word_boundaries::WordCursor;
 
 #[cfg(not(feature = "ledger"))]
@@ -63,6 +71,9 @@ pub struct Editor {
     last_rev_id: RevId,
     /// The revision of the last save.
     pristine_rev_id: RevId,
+    /// The contents of the file when it was last loaded or saved, or when
+    /// changes made to it on disk were last merged into the buffer.
+    disk_text: Rope,
     undo_group_id: usize,
     /// Undo groups that may still be toggled
     live_undos: Vec<usize>,
@@ -79,6 +90,8 @@ pub struct Editor {
     last_edit_type: EditType,
 
     revs_in_flight: usize,
+    /// Whether a timer is scheduled to write this buffer's recovery file.
+    pending_recovery: bool,
 
     /// Used only on Fuchsia for syncing
     #[allow(dead_code)]
@@ -102,6 +115,7 @@ impl Editor {
         let last_rev_id = engine.get_head_rev_id();
 
         Editor {
+            disk_text: buffer.clone(),
             text: buffer,
             engine,
             last_rev_id,
@@ -119,6 +133,7 @@ impl Editor {
             this_edit_type: EditType::Other,
             layers: Layers::default(),
             revs_in_flight: 0,
+            pending_recovery: false,
             sync_store: None,
             last_synced_rev: last_rev_id,
         }
@@ -155,12 +170,21 @@ impl Editor {
 
     pub(crate) fn set_pristine(&mut self) {
         self.pristine_rev_id = self.engine.get_head_rev_id();
+        self.disk_text = self.text.clone();
     }
 
     pub(crate) fn is_pristine(&self) -> bool {
         self.engine.is_equivalent_revision(self.pristine_rev_id, self.engine.get_head_rev_id())
     }
 
+    pub(crate) fn set_has_pending_recovery(&mut self, pending: bool) {
+        self.pending_recovery = pending
+    }
+
+    pub(crate) fn has_pending_recovery(&self) -> bool {
+        self.pending_recovery
+    }
+
     /// Set whether or not edits are forced into the same undo group rather than being split by
     /// their EditType.
     ///
@@ -174,11 +198,70 @@ impl Editor {
     /// Sets this Editor's contents to `text`, preserving undo state and cursor
     /// position when possible.
     pub fn reload(&mut self, text: Rope) {
-        let delta = LineHashDiff::compute_delta(self.get_buffer(), &text);
-        self.add_delta(delta);
+        self.replace_contents(text);
         self.set_pristine();
     }
 
+    /// Replaces this Editor's contents with `text`, as a separate undo group.
+    pub(crate) fn replace_contents(&mut self, text: Rope) {
+        let delta = compute_delta(self.get_buffer(), &text);
+        self.this_edit_type = EditType::Other;
+        self.add_delta(delta);
+    }
+
+    /// Merges changes made to the file on disk into a buffer with unsaved
+    /// edits, as a separate undo group. Returns the regions of the buffer
+    /// where the changes on disk conflict with the unsaved edits.
+    pub(crate) fn merge_disk_changes(&mut self, text: Rope) -> Vec<Interval> {
+        let (merged, conflicts) = three_way_merge(&self.disk_text, &self.text, &text);
+        self.disk_text = text;
+        self.replace_contents(merged);
+        conflicts
+    }
+
+    /// Returns the edits that prepare the buffer to be saved, removing
+    /// trailing whitespace from every line if `trim_whitespace` is `true`,
+    /// and appending `final_newline`, if present, to a buffer that doesn't
+    /// end with a line ending.
+    pub(crate) fn save_edits(
+        &self,
+        trim_whitespace: bool,
+        final_newline: Option<&str>,
+    ) -> Option<RopeDelta> {
+        let mut builder = DeltaBuilder::new(self.text.len());
+        if trim_whitespace {
+            let mut offset = 0;
+            for line in self.text.lines_raw(..) {
+                let content = line.trim_end_matches(&['\n', '\r'][..]);
+                let trimmed = content.trim_end();
+                if trimmed.len() < content.len() {
+                    builder.delete(Interval::new(offset + trimmed.len(), offset + content.len()));
+                }
+                offset += line.len();
+            }
+        }
+
+        let len = self.text.len();
+        if let Some(newline) = final_newline {
+            if len > 0 && self.text.byte_at(len - 1) != b'\n' {
+                builder.replace(Interval::new(len, len), Rope::from(newline));
+            }
+        }
+
+        if builder.is_empty() {
+            None
+        } else {
+            Some(builder.build())
+        }
+    }
+
+    /// Applies the edits returned by `save_edits`, which form a separate
+    /// undo group.
+    pub(crate) fn apply_save_edits(&mut self, delta: RopeDelta) {
+        self.this_edit_type = EditType::Other;
+        self.add_delta(delta);
+    }
+
     // each outstanding plugin edit represents a rev_in_flight.
     pub fn increment_revs_in_flight(&mut self) {
         self.revs_in_flight += 1;
@@ -369,8 +452,15 @@ impl Editor {
         // could be improved by implementing a "backspace" movement instead.
         let mut builder = DeltaBuilder::new(self.text.len());
         for region in view.sel_regions() {
-            let start = offset_for_delete_backwards(&view, &region, &self.text, &config);
-            let iv = Interval::new(start, region.max());
+            let empty_pair = if config.auto_close_pairs && region.is_caret() {
+                self.empty_pair_at(config, region.end)
+            } else {
+                None
+            };
+            let iv = empty_pair.unwrap_or_else(|| {
+                let start = offset_for_delete_backwards(&view, &region, &self.text, &config);
+                Interval::new(start, region.max())
+            });
             if !iv.is_empty() {
                 builder.delete(iv);
             }
@@ -391,25 +481,44 @@ impl Editor {
     fn delete_by_movement(
         &mut self,
         view: &View,
+        config: &BufferItems,
         movement: Movement,
         save: bool,
-        kill_ring: &mut Rope,
+        kill_ring: &mut KillRing,
     ) {
         // We compute deletions as a selection because the merge logic
         // is convenient. Another possibility would be to make the delta
         // builder able to handle overlapping deletions (with union semantics).
+        let separators = &config.subword_separators;
         let mut deletions = Selection::new();
+        let mut backward = false;
         for &r in view.sel_regions() {
             if r.is_caret() {
-                let new_region = region_movement(movement, r, view, &self.text, true);
+                let new_region = region_movement(movement, r, view, &self.text, separators, true);
+                backward |= new_region.end < new_region.start;
                 deletions.add_region(new_region);
             } else {
                 deletions.add_region(r);
             }
         }
-        if save {
-            let saved = self.extract_sel_regions(&deletions).unwrap_or_default();
-            *kill_ring = Rope::from(saved);
+        if save && deletions.iter().any(|r| !r.is_caret()) {
+            // text killed backwards goes before the text of a previous kill
+            kill_ring.kill(Kill::new(self.sel_region_texts(&deletions)), backward);
+        }
+        self.delete_sel_regions(&deletions);
+    }
+
+    /// Deletes the text object around each region in the selection, saving
+    /// it into the kill ring. Regions without one are left alone.
+    fn delete_text_object(&mut self, view: &View, object: TextObject, kill_ring: &mut KillRing) {
+        let mut deletions = Selection::new();
+        for &r in view.sel_regions() {
+            if let Some(region) = text_object_region(&self.text, r, object, 1) {
+                deletions.add_region(region);
+            }
+        }
+        if deletions.iter().any(|r| !r.is_caret()) {
+            kill_ring.kill(Kill::new(self.sel_region_texts(&deletions)), false);
         }
         self.delete_sel_regions(&deletions);
     }
@@ -448,6 +557,26 @@ impl Editor {
         saved
     }
 
+    /// Returns the text of each of the given regions.
+    fn sel_region_texts(&self, sel_regions: &[SelRegion]) -> Vec<String> {
+        sel_regions.iter().map(|region| self.text.slice_to_cow(region).into_owned()).collect()
+    }
+
+    /// Returns the text of each non-caret selection region.
+    fn selected_texts(&self, view: &View) -> Vec<String> {
+        let regions = view.sel_regions().iter().filter(|r| !r.is_caret());
+        regions.map(|region| self.text.slice_to_cow(region).into_owned()).collect()
+    }
+
+    /// Replaces each selection region with the corresponding text.
+    fn insert_texts(&mut self, view: &View, texts: Vec<String>) {
+        let mut builder = DeltaBuilder::new(self.text.len());
+        for (region, text) in view.sel_regions().iter().zip(texts) {
+            builder.replace(Interval::new(region.min(), region.max()), text.into());
+        }
+        self.add_delta(builder.build());
+    }
+
     fn insert_newline(&mut self, view: &View, config: &BufferItems) {
         self.this_edit_type = EditType::InsertNewline;
         self.insert(view, &config.line_ending);
@@ -546,18 +675,100 @@ impl Editor {
         tab_text
     }
 
-    fn do_insert(&mut self, view: &View, config: &BufferItems, chars: &str) {
+    fn do_insert(&mut self, view: &mut View, config: &BufferItems, chars: &str) {
         let pair_search = config.surrounding_pairs.iter().find(|pair| pair.0 == chars);
         let caret_exists = view.sel_regions().iter().any(|region| region.is_caret());
+        let is_pair_char =
+            config.surrounding_pairs.iter().any(|pair| pair.0 == chars || pair.1 == chars);
         if let (Some(pair), false) = (pair_search, caret_exists) {
             self.this_edit_type = EditType::Surround;
             self.surround(view, pair.0.to_string(), pair.1.to_string());
+        } else if config.auto_close_pairs && is_pair_char {
+            self.this_edit_type = EditType::InsertChars;
+            self.insert_pair_char(view, config, chars);
         } else {
             self.this_edit_type = EditType::InsertChars;
             self.insert(view, chars);
         }
     }
 
+    /// Inserts `chars`, a character of one of the `surrounding_pairs`, at each
+    /// selection region. At a caret, a closing character that was inserted
+    /// along with its opening character is typed over, and an opening
+    /// character is inserted along with its closing character if the caret
+    /// is followed by whitespace, a closing character or the end of the text.
+    fn insert_pair_char(&mut self, view: &mut View, config: &BufferItems, chars: &str) {
+        let mut builder = DeltaBuilder::new(self.text.len());
+        let mut sel = Selection::new();
+        let mut closers = Vec::new();
+        let mut typed_over = Vec::new();
+        // the change in length of the text before the current region
+        let mut shift = 0isize;
+        for &region in view.sel_regions() {
+            let offset = region.min();
+            let new_offset = (offset as isize + shift) as usize;
+            if region.is_caret() && view.is_auto_closer(offset) && self.text_at(offset, chars) {
+                typed_over.push(offset);
+                sel.add_region(SelRegion::caret(new_offset + chars.len()));
+                continue;
+            }
+            let pair = config.surrounding_pairs.iter().find(|pair| pair.0 == chars);
+            let inserted = match pair {
+                Some(pair) if region.is_caret() && self.can_close_pair(config, offset, pair) => {
+                    closers.push(new_offset + chars.len());
+                    format!("{}{}", pair.0, pair.1)
+                }
+                _ => chars.to_owned(),
+            };
+            builder.replace(Interval::new(offset, region.max()), Rope::from(&inserted));
+            sel.add_region(SelRegion::caret(new_offset + chars.len()));
+            shift += inserted.len() as isize - (region.max() - offset) as isize;
+        }
+        for offset in typed_over {
+            view.remove_auto_closer(offset);
+        }
+
+        if builder.is_empty() {
+            view.set_selection(&self.text, sel);
+        } else {
+            view.set_auto_close_result(sel, closers);
+            self.add_delta(builder.build());
+        }
+    }
+
+    /// Returns `true` if the text at `offset` starts with `s`.
+    fn text_at(&self, offset: usize, s: &str) -> bool {
+        let end = offset + s.len();
+        end <= self.text.len() && self.text.slice_to_cow(offset..end) == s
+    }
+
+    /// Returns `true` if the closing character of `pair` should be inserted
+    /// when its opening character is typed at `offset`.
+    fn can_close_pair(&self, config: &BufferItems, offset: usize, pair: &(String, String)) -> bool {
+        let next = Cursor::new(&self.text, offset).next_codepoint();
+        let next_closes = next.map_or(true, |c| {
+            c.is_whitespace()
+                || config.surrounding_pairs.iter().any(|p| p.0 != p.1 && p.1.starts_with(c))
+        });
+        // a quote directly after a word is more likely an apostrophe
+        let prev = Cursor::new(&self.text, offset).prev_codepoint();
+        let after_word = pair.0 == pair.1 && prev.map_or(false, char::is_alphanumeric);
+        next_closes && !after_word
+    }
+
+    /// Returns the interval of the empty pair around `offset`, if any.
+    fn empty_pair_at(&self, config: &BufferItems, offset: usize) -> Option<Interval> {
+        config
+            .surrounding_pairs
+            .iter()
+            .find(|pair| {
+                offset >= pair.0.len()
+                    && self.text_at(offset - pair.0.len(), &pair.0)
+                    && self.text_at(offset, &pair.1)
+            })
+            .map(|pair| Interval::new(offset - pair.0.len(), offset + pair.1.len()))
+    }
+
     fn do_paste(&mut self, view: &View, chars: &str) {
         if view.sel_regions().len() == 1 || view.sel_regions().len() != count_lines(chars) {
             self.insert(view, chars);
@@ -571,13 +782,29 @@ impl Editor {
         }
     }
 
-    pub(crate) fn do_cut(&mut self, view: &mut View) -> Value {
-        let result = self.do_copy(view);
+    pub(crate) fn do_cut(
+        &mut self,
+        view: &mut View,
+        kill_ring: &mut KillRing,
+        register: Option<String>,
+    ) -> Value {
+        let result = self.do_copy(view, kill_ring, register);
         self.delete_sel_regions(&view.sel_regions());
         result
     }
 
-    pub(crate) fn do_copy(&self, view: &View) -> Value {
+    /// Returns the selected text, also saving it in `register`, if given,
+    /// with the text of each region kept apart.
+    pub(crate) fn do_copy(
+        &self,
+        view: &View,
+        kill_ring: &mut KillRing,
+        register: Option<String>,
+    ) -> Value {
+        let texts = self.selected_texts(view);
+        if let (Some(register), false) = (register, texts.is_empty()) {
+            kill_ring.set_register(register, Kill::new(texts));
+        }
         if let Some(val) = self.extract_sel_regions(view.sel_regions()) {
             Value::String(val.into_owned())
         } else {
@@ -657,16 +884,57 @@ impl Editor {
         }
     }
 
-    fn yank(&mut self, view: &View, kill_ring: &mut Rope) {
-        // TODO: if there are multiple cursors and the number of newlines
-        // is one less than the number of cursors, split and distribute one
-        // line per cursor.
-        self.insert(view, kill_ring.clone());
+    fn yank(&mut self, view: &View, kill_ring: &mut KillRing) {
+        let texts = match kill_ring.latest() {
+            Some(kill) => kill.texts_for(view.sel_regions().len()),
+            None => return,
+        };
+        kill_ring.set_yanked(texts.iter().map(String::len).collect());
+        self.this_edit_type = EditType::Other;
+        self.insert_texts(view, texts);
+    }
+
+    /// Replaces the text inserted by the last yank, or yank pop, with the
+    /// kill before the one it inserted.
+    fn yank_pop(&mut self, view: &View, kill_ring: &mut KillRing) {
+        let regions = view.sel_regions();
+        let (texts, lens) = match kill_ring.yank_pop() {
+            Some((kill, lens)) => (kill.texts_for(regions.len()), lens),
+            None => return,
+        };
+        if lens.len() != regions.len() || regions.iter().zip(&lens).any(|(r, &len)| r.max() < len) {
+            kill_ring.end_sequence();
+            return;
+        }
+        let mut builder = DeltaBuilder::new(self.text.len());
+        for ((region, len), text) in regions.iter().zip(lens).zip(&texts) {
+            let end = region.max();
+            builder.replace(Interval::new(end - len, end), Rope::from(text));
+        }
+        kill_ring.set_popped(texts.iter().map(String::len).collect());
+        self.this_edit_type = EditType::Other;
+        self.add_delta(builder.build());
+    }
+
+    fn paste_register(&mut self, view: &View, kill_ring: &KillRing, register: &str) {
+        let texts = match kill_ring.register(register) {
+            Some(kill) => kill.texts_for(view.sel_regions().len()),
+            None => return,
+        };
+        self.this_edit_type = EditType::Other;
+        self.insert_texts(view, texts);
+    }
+
+    /// Saves the selected text in the kill ring, without deleting it.
+    fn copy_to_kill_ring(&self, view: &View, kill_ring: &mut KillRing) {
+        let texts = self.selected_texts(view);
+        if !texts.is_empty() {
+            kill_ring.push(Kill::new(texts));
+        }
     }
 
     fn replace(&mut self, view: &mut View, replace_all: bool) {
-        if let Some(Replace { chars, .. }) = view.get_replace() {
-            // todo: implement preserve case
+        if let Some(replace) = view.get_replace() {
             // store old selection because in case nothing is found the selection will be preserved
             let mut old_selection = Selection::new();
             for &region in view.sel_regions() {
@@ -680,10 +948,18 @@ impl Editor {
                 view.do_find_next(&self.text, false, true, true, &SelectionModifier::Set);
             }
 
-            match last_selection_region(view.sel_regions()) {
-                Some(_) => self.insert(view, chars),
-                None => return,
-            };
+            if last_selection_region(view.sel_regions()).is_none() {
+                return;
+            }
+
+            // each match gets its own replacement, as it may refer to capture groups
+            let mut builder = DeltaBuilder::new(self.text.len());
+            for region in view.sel_regions() {
+                let iv = Interval::new(region.min(), region.max());
+                let replacement = view.replacement_for(&self.text, region, &replace);
+                builder.replace(iv, Rope::from(replacement));
+            }
+            self.add_delta(builder.build());
         }
     }
 
@@ -817,16 +1093,130 @@ impl Editor {
         self.add_delta(builder.build());
     }
 
+    /// Comments or uncomments the lines touched by the selection, with the
+    /// line comment token of `language`, or its block comment tokens if
+    /// `block` is `true`. Either kind is used if the other is missing.
+    fn toggle_comment(&mut self, view: &View, language: Option<&LanguageDefinition>, block: bool) {
+        let language = match language {
+            Some(language) => language,
+            None => return,
+        };
+        let regions = view.sel_regions();
+        let delta = match (block, &language.line_comment, &language.block_comment) {
+            (false, Some(token), _) | (true, Some(token), None) => {
+                toggle_line_comment(&self.text, regions, token)
+            }
+            (_, _, Some((open, close))) => toggle_block_comment(&self.text, regions, open, close),
+            (_, None, None) => None,
+        };
+        if let Some(delta) = delta {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    /// Replaces the lines touched by the selection, or all lines if the
+    /// selection is only carets, with the result of `f`, and selects them.
+    fn transform_lines<F>(&mut self, view: &mut View, f: F)
+    where
+        F: Fn(&mut Vec<String>),
+    {
+        if let Some((delta, sel)) = line_ops::transform_lines(&self.text, view.sel_regions(), f) {
+            view.set_pending_selection(sel);
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    fn join_lines(&mut self, view: &View) {
+        if let Some(delta) = line_ops::join_lines(&self.text, view.sel_regions()) {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    /// Moves the lines touched by the selection up or down by one line,
+    /// along with the selection.
+    fn move_lines(&mut self, view: &mut View, up: bool) {
+        if let Some((delta, sel)) = line_ops::move_lines(&self.text, view.sel_regions(), up) {
+            view.set_pending_selection(sel);
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    /// Inserts the snippet in place of each selection region, or of `prefix`
+    /// where it is before a caret, indenting its lines like the line it is
+    /// inserted in, and starts filling it in.
+    pub(crate) fn insert_snippet(
+        &mut self,
+        view: &mut View,
+        config: &BufferItems,
+        snippet: &Snippet,
+        prefix: &str,
+        path: Option<&Path>,
+    ) {
+        let tab = self.get_tab_text(config, None);
+        let mut builder = DeltaBuilder::new(self.text.len());
+        let mut tab_stops = BTreeMap::new();
+        // the lengths of the text inserted and removed before each region
+        let (mut inserted, mut removed) = (0, 0);
+        for &region in view.sel_regions() {
+            let line_start = self.text.offset_of_line(self.text.line_of_offset(region.min()));
+            let before = self.text.slice_to_cow(line_start..region.min());
+            let start = if region.is_caret() && before.ends_with(prefix) {
+                region.min() - prefix.len()
+            } else {
+                region.min()
+            };
+            let indent = before.len() - before.trim_start_matches(|c| c == ' ' || c == '\t').len();
+            let newline = format!("{}{}", config.line_ending, &before[..indent]);
+            let expansion = snippet
+                .expand(&newline, tab, |name| snippet::variable(name, &self.text, region, path));
+            let new_start = start + inserted - removed;
+            for (index, ranges) in expansion.tab_stops {
+                let tab_stop = tab_stops.entry(index).or_insert_with(Vec::new);
+                tab_stop.extend(ranges.into_iter().map(|r| r.start + new_start..r.end + new_start));
+            }
+            inserted += expansion.text.len();
+            removed += region.max() - start;
+            builder.replace(Interval::new(start, region.max()), expansion.text.into());
+        }
+        self.this_edit_type = EditType::Other;
+        self.add_delta(builder.build());
+        view.start_snippet(SnippetSession::new(tab_stops));
+    }
+
+    /// Replaces the identifier characters before each caret, and each
+    /// selected region, with a completion.
+    pub(crate) fn insert_completion(&mut self, view: &View, completion: &str) {
+        let mut builder = DeltaBuilder::new(self.text.len());
+        for region in view.sel_regions() {
+            let start = if region.is_caret() {
+                completion::prefix_start(&self.text, region.end)
+            } else {
+                region.min()
+            };
+            builder.replace(Interval::new(start, region.max()), completion.into());
+        }
+        self.this_edit_type = EditType::Other;
+        self.add_delta(builder.build());
+    }
+
     pub(crate) fn do_edit(
         &mut self,
         view: &mut View,
-        kill_ring: &mut Rope,
+        kill_ring: &mut KillRing,
         config: &BufferItems,
+        language: Option<&LanguageDefinition>,
         cmd: BufferEvent,
     ) {
         use self::BufferEvent::*;
         match cmd {
-            Delete { movement, kill } => self.delete_by_movement(view, movement, kill, kill_ring),
+            Delete { movement, kill } => {
+                self.delete_by_movement(view, config, movement, kill, kill_ring)
+            }
+            DeleteTextObject(object) => self.delete_text_object(view, object, kill_ring),
             Backspace => self.delete_backward(view, config),
             Transpose => self.do_transpose(view),
             Undo => self.do_undo(),
@@ -840,12 +1230,29 @@ impl Editor {
             InsertTab => self.insert_tab(view, config),
             Insert(chars) => self.do_insert(view, config, &chars),
             Paste(chars) => self.do_paste(view, &chars),
+            PasteRegister(register) => self.paste_register(view, kill_ring, &register),
             Yank => self.yank(view, kill_ring),
+            YankPop => self.yank_pop(view, kill_ring),
+            CopyToKillRing => self.copy_to_kill_ring(view, kill_ring),
             ReplaceNext => self.replace(view, false),
             ReplaceAll => self.replace(view, true),
             DuplicateLine => self.duplicate_line(view, config),
             IncreaseNumber => self.change_number(view, |s| s.checked_add(1)),
             DecreaseNumber => self.change_number(view, |s| s.checked_sub(1)),
+            ToggleComment => self.toggle_comment(view, language, false),
+            ToggleBlockComment => self.toggle_comment(view, language, true),
+            SortLines => self.transform_lines(view, |lines| lines.sort()),
+            SortLinesCaseInsensitive => {
+                self.transform_lines(view, |lines| lines.sort_by_key(|line| line.to_lowercase()))
+            }
+            SortLinesNatural => {
+                self.transform_lines(view, |lines| lines.sort_by(|a, b| natural_cmp(a, b)))
+            }
+            UniqueLines => self.transform_lines(view, dedup_lines),
+            ReverseLines => self.transform_lines(view, |lines| lines.reverse()),
+            JoinLines => self.join_lines(view),
+            MoveLinesUp => self.move_lines(view, true),
+            MoveLinesDown => self.move_lines(view, false),
         }
     }
 
@@ -888,6 +1295,7 @@ impl Editor {
         let iv = Interval::new(start, end_offset);
         self.layers.update_layer(plugin, iv, spans);
         view.invalidate_styles(&self.text, start, end_offset);
+        view.scopes_changed();
     }
 
     pub(crate) fn get_rev(&self, rev: RevToken) -> Option<Cow<Rope>> {
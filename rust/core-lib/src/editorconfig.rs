@@ -0,0 +1,449 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for [EditorConfig](https://editorconfig.org) files.
+//!
+//! The `.editorconfig` files in the directories containing a file are
+//! applied from the outermost to the innermost, stopping at a file with
+//! `root = true`. The supported properties are mapped onto config keys.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_json::Value;
+
+use config::Table;
+
+pub const EDITORCONFIG_FILE_NAME: &str = ".editorconfig";
+
+/// The settings from the `.editorconfig` files that apply to a file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EditorConfig {
+    /// The settings, as config keys and values.
+    pub table: Table,
+    /// The directories of the `.editorconfig` files that were read, from
+    /// the innermost outwards. Changing or removing any of these files can
+    /// change the settings.
+    pub dirs: Vec<PathBuf>,
+}
+
+/// A parsed `.editorconfig` file.
+#[derive(Debug, Default)]
+struct EditorConfigFile {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    glob: String,
+    properties: Vec<(String, String)>,
+}
+
+/// A section glob, converted to a regular expression. The capture groups
+/// of the expression are the numeric ranges of the glob, in order.
+struct Glob {
+    regex: Regex,
+    ranges: Vec<(i64, i64)>,
+}
+
+impl EditorConfig {
+    /// Loads the settings for the file at `path`.
+    pub fn for_path(path: &Path) -> EditorConfig {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            let file_path = dir.join(EDITORCONFIG_FILE_NAME);
+            let contents = match fs::read_to_string(&file_path) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            dirs.push(dir.to_owned());
+            let file = EditorConfigFile::parse(&contents);
+            let is_root = file.root;
+            files.push((dir, file));
+            if is_root {
+                break;
+            }
+        }
+
+        let mut properties = HashMap::new();
+        for (dir, file) in files.iter().rev() {
+            file.apply(dir, &path, &mut properties);
+        }
+        EditorConfig { table: to_table(&properties), dirs }
+    }
+}
+
+impl EditorConfigFile {
+    fn parse(contents: &str) -> EditorConfigFile {
+        let mut file = EditorConfigFile::default();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let glob = line[1..line.len() - 1].to_owned();
+                file.sections.push(Section { glob, properties: Vec::new() });
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(idx) => (&line[..idx], &line[idx + 1..]),
+                None => continue,
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+            match file.sections.last_mut() {
+                Some(section) => section.properties.push((key, value)),
+                None if key == "root" => file.root = value == "true",
+                None => (),
+            }
+        }
+        file
+    }
+
+    /// Applies the properties of the sections matching `path` to
+    /// `properties`. Later sections take precedence.
+    fn apply(&self, dir: &Path, path: &Path, properties: &mut HashMap<String, String>) {
+        let relative = match path.strip_prefix(dir) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => return,
+        };
+        for section in &self.sections {
+            let matches = Glob::new(&section.glob).map(|g| g.is_match(&relative)).unwrap_or(false);
+            if !matches {
+                continue;
+            }
+            for (key, value) in &section.properties {
+                if value == "unset" {
+                    properties.remove(key);
+                } else {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Glob {
+    /// Converts a section name to a regular expression matching paths
+    /// relative to the directory of the `.editorconfig` file. Names
+    /// without a `/` match files in any subdirectory.
+    fn new(glob: &str) -> Option<Glob> {
+        let glob = if glob.contains('/') {
+            glob.trim_start_matches('/').to_owned()
+        } else {
+            format!("**/{}", glob)
+        };
+        let chars = glob.chars().collect::<Vec<_>>();
+        let mut ranges = Vec::new();
+        let pattern = format!("^{}$", convert_glob(&chars, &mut ranges));
+        match Regex::new(&pattern) {
+            Ok(regex) => Some(Glob { regex, ranges }),
+            Err(e) => {
+                warn!("invalid editorconfig section {:?}: {:?}", glob, e);
+                None
+            }
+        }
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        let captures = match self.regex.captures(path) {
+            Some(captures) => captures,
+            None => return false,
+        };
+        self.ranges.iter().enumerate().all(|(i, &(start, end))| {
+            captures
+                .get(i + 1)
+                .and_then(|m| m.as_str().parse::<i64>().ok())
+                .map(|n| start <= n && n <= end)
+                .unwrap_or(false)
+        })
+    }
+}
+
+/// Converts glob syntax to a regular expression. Numeric ranges become
+/// capture groups, and are added to `ranges`.
+fn convert_glob(chars: &[char], ranges: &mut Vec<(i64, i64)>) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    out.push_str("(?:.*/)?");
+                    i += 1;
+                } else {
+                    out.push_str(".*");
+                }
+                i += 1;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                push_literal(&mut out, chars[i]);
+            }
+            '[' => match chars[i + 1..].iter().position(|&c| c == ']' || c == '/') {
+                Some(len) if chars[i + 1 + len] == ']' && len > 0 => {
+                    let class = &chars[i + 1..i + 1 + len];
+                    out.push('[');
+                    for (j, &c) in class.iter().enumerate() {
+                        match c {
+                            '!' if j == 0 => out.push('^'),
+                            '-' if j > 0 && j + 1 < class.len() => out.push('-'),
+                            c if c.is_alphanumeric() => out.push(c),
+                            c => {
+                                out.push('\\');
+                                out.push(c);
+                            }
+                        }
+                    }
+                    out.push(']');
+                    i += len + 1;
+                }
+                _ => out.push_str("\\["),
+            },
+            '{' => match matching_brace(chars, i) {
+                Some(end) => {
+                    out.push_str(&convert_braces(&chars[i + 1..end], ranges));
+                    i = end;
+                }
+                None => out.push_str("\\{"),
+            },
+            c => push_literal(&mut out, c),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Converts the contents of a `{...}` group, which is either a list of
+/// alternatives or a numeric range.
+fn convert_braces(chars: &[char], ranges: &mut Vec<(i64, i64)>) -> String {
+    let contents = chars.iter().collect::<String>();
+    if let Some(range) = parse_range(&contents) {
+        ranges.push(range);
+        return "([+-]?[0-9]+)".into();
+    }
+
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&chars[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    alternatives.push(&chars[start..]);
+
+    if alternatives.len() == 1 {
+        format!("\\{{{}\\}}", convert_glob(chars, ranges))
+    } else {
+        let alternatives =
+            alternatives.iter().map(|alt| convert_glob(alt, ranges)).collect::<Vec<_>>();
+        format!("(?:{})", alternatives.join("|"))
+    }
+}
+
+fn parse_range(s: &str) -> Option<(i64, i64)> {
+    let idx = s.find("..")?;
+    let start = s[..idx].parse().ok()?;
+    let end = s[idx + 2..].parse().ok()?;
+    Some((start, end))
+}
+
+/// Returns the index of the `}` closing the `{` at `open`.
+fn matching_brace(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+fn push_literal(out: &mut String, c: char) {
+    let mut buf = [0; 4];
+    out.push_str(&::regex::escape(c.encode_utf8(&mut buf)));
+}
+
+/// Maps EditorConfig properties onto config keys. Unsupported properties,
+/// and unsupported values, are ignored.
+fn to_table(properties: &HashMap<String, String>) -> Table {
+    let mut table = Table::new();
+    let get = |key: &str| properties.get(key).map(String::as_str);
+
+    match get("indent_style") {
+        Some("tab") => insert(&mut table, "translate_tabs_to_spaces", false),
+        Some("space") => insert(&mut table, "translate_tabs_to_spaces", true),
+        _ => (),
+    }
+
+    // xi uses the same width for indentation and tabs
+    let indent_size = match get("indent_size") {
+        Some("tab") | None => get("tab_width"),
+        size => size,
+    };
+    if let Some(size) = indent_size.and_then(|s| s.parse::<usize>().ok()) {
+        insert(&mut table, "tab_size", size);
+    }
+
+    match get("end_of_line") {
+        Some("lf") => insert(&mut table, "line_ending", "\n"),
+        Some("crlf") => insert(&mut table, "line_ending", "\r\n"),
+        _ => (),
+    }
+
+    for key in &["trim_trailing_whitespace", "insert_final_newline"] {
+        match get(key) {
+            Some("true") => insert(&mut table, key, true),
+            Some("false") => insert(&mut table, key, false),
+            _ => (),
+        }
+    }
+
+    // `charset` only sets the encoding of new files: existing files keep the
+    // encoding they were opened with, rather than being reinterpreted.
+    let encoding = match get("charset") {
+        Some("utf-8") => Some("utf-8"),
+        Some("utf-8-bom") => Some("utf-8-bom"),
+        Some("utf-16le") => Some("utf-16le"),
+        Some("utf-16be") => Some("utf-16be"),
+        Some("latin1") => Some("iso-8859-1"),
+        _ => None,
+    };
+    if let Some(encoding) = encoding {
+        insert(&mut table, "default_encoding", encoding);
+    }
+    table
+}
+
+fn insert<V: Into<Value>>(table: &mut Table, key: &str, value: V) {
+    table.insert(key.to_owned(), value.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_fixtures::TempDir;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(matches("*", "a/b/file.rs"));
+        assert!(matches("*.rs", "a/b/file.rs"));
+        assert!(!matches("*.rs", "a/b/file.rsx"));
+        assert!(matches("src/*.rs", "src/file.rs"));
+        assert!(!matches("src/*.rs", "src/a/file.rs"));
+        assert!(!matches("src/*.rs", "a/src/file.rs"));
+        assert!(matches("/src/**.rs", "src/a/file.rs"));
+        assert!(matches("src/**/*.rs", "src/file.rs"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(matches("*.{js,ts}", "index.ts"));
+        assert!(!matches("*.{js,ts}", "index.rs"));
+        assert!(matches("{Makefile,*.mk}", "dir/rules.mk"));
+        assert!(matches("file.{rs}", "file.{rs}"));
+        assert!(matches("[Mm]akefile", "makefile"));
+        assert!(matches("*.[!o]", "file.c"));
+        assert!(!matches("*.[!o]", "file.o"));
+        assert!(matches("file{1..10}.txt", "file7.txt"));
+        assert!(!matches("file{1..10}.txt", "file11.txt"));
+        assert!(matches("a+b(c).txt", "a+b(c).txt"));
+    }
+
+    #[test]
+    fn properties() {
+        let contents = r#"
+# a comment
+root = true
+
+[*]
+indent_style = space
+indent_size = 2
+end_of_line = crlf
+charset = utf-8
+
+[*.go]
+indent_style = tab
+indent_size = tab
+tab_width = 8
+insert_final_newline = true
+
+[vendor/**]
+charset = unset
+"#;
+        let file = EditorConfigFile::parse(contents);
+        assert!(file.root);
+        assert_eq!(file.sections.len(), 3);
+
+        let dir = Path::new("/project");
+        let mut properties = HashMap::new();
+        file.apply(dir, Path::new("/project/vendor/main.go"), &mut properties);
+        let table = to_table(&properties);
+        assert_eq!(table.get("translate_tabs_to_spaces"), Some(&false.into()));
+        assert_eq!(table.get("tab_size"), Some(&8.into()));
+        assert_eq!(table.get("line_ending"), Some(&"\r\n".into()));
+        assert_eq!(table.get("insert_final_newline"), Some(&true.into()));
+        assert_eq!(table.get("default_encoding"), None);
+    }
+
+    #[test]
+    fn nested_files() {
+        let temp_dir = TempDir::new("editorconfig");
+        fs::create_dir_all(temp_dir.path().join("project/src")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("project/tests")).unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::write(root.join(".editorconfig"), "[*]\nindent_size = 3\n").unwrap();
+        fs::write(root.join("project/.editorconfig"), "root = true\n[*]\nindent_size = 2\n")
+            .unwrap();
+        fs::write(root.join("project/src/.editorconfig"), "[*.rs]\nend_of_line = lf\n").unwrap();
+
+        let config = EditorConfig::for_path(&root.join("project/src/main.rs"));
+        assert_eq!(config.table.get("tab_size"), Some(&2.into()));
+        assert_eq!(config.table.get("line_ending"), Some(&"\n".into()));
+        assert_eq!(config.dirs, vec![root.join("project/src"), root.join("project")]);
+
+        // directories without an `.editorconfig` file are left out
+        let config = EditorConfig::for_path(&root.join("project/tests/main.rs"));
+        assert_eq!(config.table.get("tab_size"), Some(&2.into()));
+        assert_eq!(config.dirs, vec![root.join("project")]);
+    }
+}
@@ -15,8 +15,10 @@
 //! A container for the state relevant to a single event.
 
 use std::cell::RefCell;
+use std::cmp::{max, min};
 use std::iter;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use serde_json::{self, Value};
@@ -30,18 +32,27 @@ use plugins::rpc::{
 };
 use rpc::{EditNotification, EditRequest, LineRange, Position as ClientPosition};
 
+use brackets::BracketMatcher;
 use config::{BufferItems, Table};
 use styles::ThemeStyleMap;
 
 use client::Client;
-use edit_types::{EventDomain, SpecialEvent};
+use completion::{self, Completions};
+use edit_types::{BufferEvent, EventDomain, SpecialEvent, ViewEvent};
 use editor::Editor;
 use file::FileInfo;
+use kill_ring::{Kill, KillRing};
+use modal::{
+    is_linewise, killed_lines, normal_mode_caret, visual_line_region, Command, InsertPosition,
+    Mode, Operator, Target, TextObject,
+};
+use movement::{region_movement, Movement};
 use plugins::Plugin;
 use recorder::Recorder;
-use selection::InsertDrift;
-use syntax::LanguageId;
-use tabs::{BufferId, PluginId, ViewId, RENDER_VIEW_IDLE_MASK};
+use selection::{InsertDrift, SelRegion, Selection};
+use snippet::{Snippet, Snippets};
+use syntax::{LanguageDefinition, LanguageId};
+use tabs::{BufferId, PluginId, ViewId, RECOVERY_BUFFER_IDLE_MASK, RENDER_VIEW_IDLE_MASK};
 use view::View;
 use width_cache::WidthCache;
 use WeakXiCore;
@@ -66,14 +77,17 @@ pub struct EventContext<'a> {
     pub(crate) info: Option<&'a FileInfo>,
     pub(crate) config: &'a BufferItems,
     pub(crate) recorder: &'a RefCell<Recorder>,
+    pub(crate) snippets: &'a Snippets,
+    pub(crate) completions: &'a RefCell<Completions>,
     pub(crate) language: LanguageId,
+    pub(crate) language_def: Option<Arc<LanguageDefinition>>,
     pub(crate) view: &'a RefCell<View>,
     pub(crate) siblings: Vec<&'a RefCell<View>>,
     pub(crate) plugins: Vec<&'a Plugin>,
     pub(crate) client: &'a Client,
     pub(crate) style_map: &'a RefCell<ThemeStyleMap>,
     pub(crate) width_cache: &'a RefCell<WidthCache>,
-    pub(crate) kill_ring: &'a RefCell<Rope>,
+    pub(crate) kill_ring: &'a RefCell<KillRing>,
     pub(crate) weak_core: &'a WeakXiCore,
 }
 
@@ -82,7 +96,7 @@ impl<'a> EventContext<'a> {
     /// common in edit actions that modify the text.
     pub(crate) fn with_editor<R, F>(&mut self, f: F) -> R
     where
-        F: FnOnce(&mut Editor, &mut View, &mut Rope, &BufferItems) -> R,
+        F: FnOnce(&mut Editor, &mut View, &mut KillRing, &BufferItems) -> R,
     {
         let mut editor = self.editor.borrow_mut();
         let mut view = self.view.borrow_mut();
@@ -109,36 +123,368 @@ impl<'a> EventContext<'a> {
     pub(crate) fn do_edit(&mut self, cmd: EditNotification) {
         let event: EventDomain = cmd.into();
 
-        {
-            // Handle recording-- clone every non-toggle and play event into the recording buffer
-            let mut recorder = self.recorder.borrow_mut();
-            match (recorder.is_recording(), &event) {
-                (_, EventDomain::Special(SpecialEvent::ToggleRecording(recording_name))) => {
-                    recorder.toggle_recording(recording_name.clone());
+        if self.config.modal_editing {
+            self.do_modal_edit(event);
+        } else {
+            self.record_event(&event);
+            self.dispatch_event(event);
+            self.after_edit("core");
+        }
+        self.render_if_needed();
+    }
+
+    fn record_event(&self, event: &EventDomain) {
+        // Handle recording-- clone every non-toggle and play event into the recording buffer
+        let mut recorder = self.recorder.borrow_mut();
+        match (recorder.is_recording(), event) {
+            (_, EventDomain::Special(SpecialEvent::ToggleRecording(recording_name))) => {
+                recorder.toggle_recording(recording_name.clone());
+            }
+            // Don't save special events
+            (true, EventDomain::Special(_)) => {
+                warn!("Special events cannot be recorded-- ignoring event {:?}", event)
+            }
+            (true, event) => recorder.record(event.clone()),
+            _ => {}
+        }
+    }
+
+    /// Handles an event while the modal input layer is enabled. In normal
+    /// and visual modes, inserted characters are read as commands.
+    fn do_modal_edit(&mut self, event: EventDomain) {
+        use self::EventDomain as E;
+        match (self.mode(), event) {
+            (Mode::Insert, E::View(ViewEvent::Cancel)) => {
+                self.finish_change();
+                self.set_mode(Mode::Normal);
+            }
+            (Mode::Insert, event) => self.dispatch_modal_event(event),
+            (_, E::Buffer(BufferEvent::Insert(chars))) => {
+                for (i, key) in chars.char_indices() {
+                    // the rest of the characters are inserted once a command
+                    // has entered insert mode
+                    if self.mode() == Mode::Insert {
+                        let rest = chars[i..].to_owned();
+                        self.dispatch_modal_event(BufferEvent::Insert(rest).into());
+                        break;
+                    }
+                    self.do_modal_key(key);
                 }
-                // Don't save special events
-                (true, EventDomain::Special(_)) => {
-                    warn!("Special events cannot be recorded-- ignoring event {:?}", event)
+            }
+            (_, E::Buffer(BufferEvent::InsertNewline)) => self.do_modal_key('j'),
+            (_, E::Buffer(BufferEvent::Backspace)) => self.do_modal_key('h'),
+            (_, E::Buffer(BufferEvent::InsertTab)) => (),
+            (mode, E::View(ViewEvent::Cancel)) => {
+                let had_keys = self.view.borrow_mut().modal_state().clear_keys();
+                if !had_keys {
+                    if mode != Mode::Normal {
+                        self.set_mode(Mode::Normal);
+                    }
+                    self.dispatch_modal_event(ViewEvent::Cancel.into());
                 }
-                (true, event) => recorder.record(event.clone()),
-                _ => {}
             }
+            (_, event) => self.dispatch_modal_event(event),
         }
+        self.clamp_normal_carets();
+    }
 
+    /// In normal mode, moves carets past the end of their lines back onto
+    /// the last character, as the cursor rests on a character.
+    fn clamp_normal_carets(&mut self) {
+        if self.mode() != Mode::Normal {
+            return;
+        }
+        self.with_view(|view, text| {
+            let mut selection = Selection::new();
+            let mut changed = false;
+            for &region in view.sel_regions() {
+                if region.is_caret() {
+                    let offset = normal_mode_caret(text, region.end);
+                    changed |= offset != region.end;
+                    selection.add_region(SelRegion::caret(offset).with_horiz(region.horiz));
+                } else {
+                    selection.add_region(region);
+                }
+            }
+            if changed {
+                view.set_selection(text, selection);
+            }
+        });
+    }
+
+    fn mode(&self) -> Mode {
+        self.view.borrow_mut().modal_state().mode()
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        if self.view.borrow_mut().modal_state().set_mode(mode) {
+            self.client.mode_changed(self.view_id, mode);
+        }
+    }
+
+    /// Records and dispatches an event of the modal input layer, committing
+    /// its edit so that the next event sees the result.
+    fn dispatch_modal_event(&mut self, event: EventDomain) {
+        self.record_event(&event);
+        match event {
+            EventDomain::Special(_) => (),
+            _ => self.view.borrow_mut().modal_state().record_change(&event),
+        }
         self.dispatch_event(event);
         self.after_edit("core");
-        self.render_if_needed();
+    }
+
+    /// Saves the change being recorded, if it edited the text, as the one
+    /// that `.` repeats.
+    fn finish_change(&mut self) {
+        let change = self.view.borrow_mut().modal_state().finish_change();
+        if let Some(change) = change {
+            let is_edit = |event: &EventDomain| match event {
+                EventDomain::Buffer(_) => true,
+                _ => false,
+            };
+            if change.iter().any(is_edit) {
+                self.recorder.borrow_mut().save_last_change(change);
+            }
+        }
+    }
+
+    fn do_modal_key(&mut self, key: char) {
+        let command = self.view.borrow_mut().modal_state().push_key(key);
+        if let Some((count, command)) = command {
+            if command.is_change() {
+                self.view.borrow_mut().modal_state().start_change();
+            }
+            self.do_modal_command(command, count);
+            // a change that enters insert mode ends when it is left
+            if self.mode() != Mode::Insert {
+                self.finish_change();
+            }
+            self.clamp_normal_carets();
+        }
+    }
+
+    fn do_modal_command(&mut self, command: Command, count: usize) {
+        use self::InsertPosition::*;
+        match command {
+            Command::Move(movement) => {
+                for _ in 0..self.motion_count(count) {
+                    self.do_modal_move(movement);
+                }
+            }
+            Command::GotoLine(line) => {
+                self.dispatch_modal_event(ViewEvent::GotoLine { line: line as u64 }.into())
+            }
+            Command::Operate(operator, target) => self.do_operator(operator, target, count),
+            Command::Insert(position) => {
+                let events: Vec<EventDomain> = match position {
+                    Cursor => vec![],
+                    Append => vec![ViewEvent::Move(Movement::Right).into()],
+                    LineStart => vec![ViewEvent::Move(Movement::StartOfParagraph).into()],
+                    LineEnd => vec![ViewEvent::Move(Movement::EndOfParagraph).into()],
+                    LineBelow => vec![
+                        ViewEvent::Move(Movement::EndOfParagraph).into(),
+                        BufferEvent::InsertNewline.into(),
+                    ],
+                    LineAbove => vec![
+                        ViewEvent::Move(Movement::StartOfParagraph).into(),
+                        BufferEvent::InsertNewline.into(),
+                        ViewEvent::Move(Movement::Up).into(),
+                    ],
+                };
+                events.into_iter().for_each(|event| self.dispatch_modal_event(event));
+                self.set_mode(Mode::Insert);
+            }
+            Command::Put { before } => self.do_put(before, count),
+            Command::JoinLines => {
+                // joining n lines takes n - 1 joins
+                for _ in 0..max(count, 2) - 1 {
+                    self.dispatch_modal_event(BufferEvent::JoinLines.into());
+                }
+                self.set_mode(Mode::Normal);
+            }
+            Command::Undo => {
+                for _ in 0..count {
+                    self.dispatch_modal_event(BufferEvent::Undo.into());
+                }
+            }
+            Command::Repeat => {
+                for _ in 0..count {
+                    self.play_recording(None);
+                }
+            }
+            Command::SelectObject(object) => {
+                self.dispatch_modal_event(ViewEvent::SelectTextObject { object, count }.into());
+                if self.mode() == Mode::VisualLine && object.is_linewise() {
+                    self.select_visual_lines();
+                } else {
+                    // other text objects select characters rather than whole lines
+                    self.set_mode(Mode::Visual);
+                }
+            }
+            Command::SetMode(mode) => self.set_visual_mode(mode),
+        }
+    }
+
+    /// Returns how many times to repeat a motion given `count`: no motion
+    /// needs to be repeated more often than there are characters.
+    fn motion_count(&self, count: usize) -> usize {
+        min(count, self.editor.borrow().get_buffer().len() + 1)
+    }
+
+    /// Moves the cursor, or in visual modes extends the selection.
+    fn do_modal_move(&mut self, movement: Movement) {
+        match self.mode() {
+            Mode::Visual => self.dispatch_modal_event(ViewEvent::ModifySelection(movement).into()),
+            Mode::VisualLine => self.with_editor(|ed, view, _, config| {
+                let text = ed.get_buffer();
+                let (anchor_line, head) = view.modal_state().line_selection();
+                let separators = &config.subword_separators;
+                let head = SelRegion::caret(head);
+                let head = region_movement(movement, head, view, text, separators, false).end;
+                view.modal_state().set_line_selection(anchor_line, head);
+                view.set_selection(text, visual_line_region(text, anchor_line, head));
+            }),
+            _ => self.dispatch_modal_event(ViewEvent::Move(movement).into()),
+        }
+    }
+
+    /// Switches between normal and visual modes.
+    fn set_visual_mode(&mut self, mode: Mode) {
+        self.with_view(|view, text| match mode {
+            Mode::VisualLine => {
+                let region = *view.sel_regions().last().unwrap();
+                let anchor_line = text.line_of_offset(region.start);
+                view.modal_state().set_line_selection(anchor_line, region.end);
+                view.set_selection(text, visual_line_region(text, anchor_line, region.end));
+            }
+            Mode::Normal => view.collapse_selections(text),
+            _ => (),
+        });
+        self.set_mode(mode);
+    }
+
+    /// Makes the lines of the last selection region the line selection of
+    /// visual line mode.
+    fn select_visual_lines(&mut self) {
+        self.with_view(|view, text| {
+            let region = *view.sel_regions().last().unwrap();
+            let anchor_line = text.line_of_offset(region.min());
+            let mut head_line = text.line_of_offset(region.max());
+            if region.max() > region.min() && text.offset_of_line(head_line) == region.max() {
+                head_line -= 1;
+            }
+            let head = text.offset_of_line(head_line);
+            view.modal_state().set_line_selection(anchor_line, head);
+            view.set_selection(text, visual_line_region(text, anchor_line, head));
+        });
+    }
+
+    /// Applies an operator to its target, then returns to normal mode, or
+    /// enters insert mode for a change.
+    fn do_operator(&mut self, operator: Operator, target: Target, count: usize) {
+        match target {
+            Target::Motion(movement) => {
+                // as in vi, an operator moving by words stops at the end of the line
+                let movement = match movement {
+                    Movement::NextWordStart => Movement::NextWordStartInLine,
+                    movement => movement,
+                };
+                for _ in 0..self.motion_count(count) {
+                    self.dispatch_modal_event(ViewEvent::ModifySelection(movement).into());
+                }
+                if is_linewise(movement) {
+                    let object = TextObject::Lines { inner: operator.takes_inner_lines() };
+                    let event = ViewEvent::SelectTextObject { object, count: 1 };
+                    self.dispatch_modal_event(event.into());
+                }
+            }
+            Target::Object(object) => {
+                self.dispatch_modal_event(ViewEvent::SelectTextObject { object, count }.into())
+            }
+            Target::Selection => (),
+        }
+
+        let has_selection = self.view.borrow().sel_regions().iter().any(|r| !r.is_caret());
+        let events: Vec<EventDomain> = match operator {
+            Operator::Delete | Operator::Change if has_selection => {
+                vec![BufferEvent::Delete { movement: Movement::Right, kill: true }.into()]
+            }
+            Operator::Yank if has_selection => vec![BufferEvent::CopyToKillRing.into()],
+            Operator::Indent => vec![BufferEvent::Indent.into()],
+            Operator::Outdent => vec![BufferEvent::Outdent.into()],
+            _ => vec![],
+        };
+        events.into_iter().for_each(|event| self.dispatch_modal_event(event));
+        if has_selection && operator != Operator::Delete && operator != Operator::Change {
+            // leave the cursor at the start of the text
+            self.dispatch_modal_event(ViewEvent::Move(Movement::Left).into());
+        }
+        let mode = if operator == Operator::Change { Mode::Insert } else { Mode::Normal };
+        self.set_mode(mode);
+    }
+
+    /// Inserts the contents of the kill ring `count` times after the cursor,
+    /// or before it. Killed lines are inserted below or above the cursor's
+    /// line.
+    fn do_put(&mut self, before: bool, count: usize) {
+        let killed = self.kill_ring.borrow().latest().map(Kill::text).unwrap_or_default();
+        if killed.is_empty() {
+            return;
+        }
+        let line_ending = &self.config.line_ending;
+        let events: Vec<EventDomain> = match killed_lines(&killed) {
+            Some(lines) => {
+                let lines = vec![lines; count].join(line_ending);
+                if before {
+                    vec![
+                        ViewEvent::Move(Movement::StartOfParagraph).into(),
+                        BufferEvent::Paste(lines + line_ending).into(),
+                    ]
+                } else {
+                    vec![
+                        ViewEvent::Move(Movement::EndOfParagraph).into(),
+                        BufferEvent::Paste(line_ending.to_owned() + &lines).into(),
+                    ]
+                }
+            }
+            None if before => vec![BufferEvent::Paste(killed.repeat(count)).into()],
+            None => vec![
+                ViewEvent::Move(Movement::Right).into(),
+                BufferEvent::Paste(killed.repeat(count)).into(),
+            ],
+        };
+        events.into_iter().for_each(|event| self.dispatch_modal_event(event));
     }
 
     fn dispatch_event(&mut self, event: EventDomain) {
         use self::EventDomain as E;
+        // a kill is added to the previous kill, and a yank can be popped,
+        // only if nothing else was done in between
+        match event {
+            E::Buffer(BufferEvent::Delete { kill: true, .. })
+            | E::Buffer(BufferEvent::YankPop)
+            | E::View(ViewEvent::Scroll(_))
+            | E::Special(_) => (),
+            _ => self.kill_ring.borrow_mut().end_sequence(),
+        }
         match event {
             E::View(cmd) => {
-                self.with_view(|view, text| view.do_edit(text, cmd));
+                if uses_brackets(&cmd) {
+                    self.find_all_brackets();
+                }
+                {
+                    let ed = self.editor.borrow();
+                    let mut view = self.view.borrow_mut();
+                    view.do_edit(ed.get_buffer(), ed.get_layers(), self.config, cmd);
+                }
                 self.editor.borrow_mut().update_edit_type();
             }
             E::Buffer(cmd) => {
-                self.with_editor(|ed, view, k_ring, conf| ed.do_edit(view, k_ring, conf, cmd))
+                let language = self.language_def.clone();
+                self.with_editor(|ed, view, k_ring, conf| {
+                    ed.do_edit(view, k_ring, conf, language.as_ref().map(|l| &**l), cmd)
+                })
             }
             E::Special(cmd) => self.do_special(cmd),
         }
@@ -168,46 +514,115 @@ impl<'a> EventContext<'a> {
             }
             SpecialEvent::ToggleRecording(_) => {}
             SpecialEvent::PlayRecording(recording_name) => {
-                let recorder = self.recorder.borrow();
-
-                let starting_revision = self.editor.borrow_mut().get_head_rev_token();
-
-                // Don't group with the previous action
-                self.editor.borrow_mut().update_edit_type();
-                self.editor.borrow_mut().calculate_undo_group();
-
-                // No matter what, our entire block must belong to the same undo group
-                self.editor.borrow_mut().set_force_undo_group(true);
-                recorder.play(&recording_name, |event| {
-                    self.dispatch_event(event.clone());
-
-                    let mut editor = self.editor.borrow_mut();
-                    let (delta, last_text, drift) = match editor.commit_delta() {
-                        Some(edit_info) => edit_info,
-                        None => return,
-                    };
-                    self.update_views(&editor, &delta, &last_text, drift);
-                });
-                self.editor.borrow_mut().set_force_undo_group(false);
-
-                // The action that follows the block must belong to a separate undo group
-                self.editor.borrow_mut().update_edit_type();
-
-                let delta = self.editor.borrow_mut().delta_rev_head(starting_revision);
-                self.update_plugins(&mut self.editor.borrow_mut(), delta, "core")
+                self.play_recording(Some(&recording_name))
             }
             SpecialEvent::ClearRecording(recording_name) => {
                 let mut recorder = self.recorder.borrow_mut();
                 recorder.clear(&recording_name);
             }
+            SpecialEvent::InsertSnippet(body) => self.do_insert_snippet(&Snippet::parse(&body), ""),
+            SpecialEvent::ExpandSnippet => self.do_expand_snippet(),
+            SpecialEvent::RequestCompletions { request_id } => {
+                self.do_request_completions(request_id)
+            }
+            SpecialEvent::AcceptCompletion { request_id, index } => {
+                self.do_accept_completion(request_id, index)
+            }
+        }
+    }
+
+    /// Plays the named recording or, without a name, the last change of the
+    /// modal input layer, as a single undo group.
+    fn play_recording(&mut self, recording_name: Option<&str>) {
+        let recorder = self.recorder.borrow();
+
+        let starting_revision = self.editor.borrow_mut().get_head_rev_token();
+
+        // Don't group with the previous action
+        self.editor.borrow_mut().update_edit_type();
+        self.editor.borrow_mut().calculate_undo_group();
+
+        // No matter what, our entire block must belong to the same undo group
+        self.editor.borrow_mut().set_force_undo_group(true);
+        let action = |event: &EventDomain| {
+            self.dispatch_event(event.clone());
+
+            let mut editor = self.editor.borrow_mut();
+            let (delta, last_text, drift) = match editor.commit_delta() {
+                Some(edit_info) => edit_info,
+                None => return,
+            };
+            self.update_views(&editor, &delta, &last_text, drift);
+        };
+        match recording_name {
+            Some(recording_name) => recorder.play(recording_name, action),
+            None => recorder.play_last_change(action),
+        }
+        self.editor.borrow_mut().set_force_undo_group(false);
+
+        // The action that follows the block must belong to a separate undo group
+        self.editor.borrow_mut().update_edit_type();
+
+        let delta = self.editor.borrow_mut().delta_rev_head(starting_revision);
+        self.update_plugins(&mut self.editor.borrow_mut(), delta, "core")
+    }
+
+    fn do_insert_snippet(&mut self, snippet: &Snippet, prefix: &str) {
+        self.kill_ring.borrow_mut().end_sequence();
+        let path = self.info.map(|info| info.path.as_path());
+        self.with_editor(|ed, view, _, config| {
+            ed.insert_snippet(view, config, snippet, prefix, path)
+        });
+    }
+
+    /// Replaces the prefix of one of the language's snippets before the
+    /// last cursor, and before any other cursors it is also before, with
+    /// the snippet.
+    fn do_expand_snippet(&mut self) {
+        let line = self.with_view(|view, text| {
+            let offset = view.sel_regions().last().unwrap().end;
+            let line_start = text.offset_of_line(text.line_of_offset(offset));
+            text.slice_to_cow(line_start..offset).into_owned()
+        });
+        let snippets = self.snippets;
+        if let Some(def) = snippets.find_prefix(&self.language, &line) {
+            self.do_insert_snippet(&Snippet::parse(&def.body), &def.prefix);
+        }
+    }
+
+    /// Sends the completions of the identifier before the last cursor.
+    fn do_request_completions(&mut self, request_id: usize) {
+        let ed = self.editor.borrow();
+        let text = ed.get_buffer();
+        let offset = self.view.borrow().sel_regions().last().unwrap().end;
+        let prefix = text.slice_to_cow(completion::prefix_start(text, offset)..offset);
+        let mut completions = self.completions.borrow_mut();
+        let items = completions.complete(self.buffer_id, text, offset);
+        self.client.completion_list(self.view_id, request_id, &prefix, &items);
+        completions.set_list(self.view_id, request_id, items);
+    }
+
+    fn do_accept_completion(&mut self, request_id: usize, index: usize) {
+        let item = self.completions.borrow_mut().take_item(self.view_id, request_id, index);
+        match item {
+            Some(item) => {
+                self.kill_ring.borrow_mut().end_sequence();
+                self.with_editor(|ed, view, _, _| ed.insert_completion(view, &item.label));
+            }
+            None => warn!("no completion {} for request {}", index, request_id),
         }
     }
 
     pub(crate) fn do_edit_sync(&mut self, cmd: EditRequest) -> Result<Value, RemoteError> {
         use self::EditRequest::*;
+        self.kill_ring.borrow_mut().end_sequence();
         let result = match cmd {
-            Cut => Ok(self.with_editor(|ed, view, _, _| ed.do_cut(view))),
-            Copy => Ok(self.with_editor(|ed, view, _, _| ed.do_copy(view))),
+            Cut { register } => {
+                Ok(self.with_editor(|ed, view, k_ring, _| ed.do_cut(view, k_ring, register)))
+            }
+            Copy { register } => {
+                Ok(self.with_editor(|ed, view, k_ring, _| ed.do_copy(view, k_ring, register)))
+            }
         };
         self.after_edit("core");
         self.render_if_needed();
@@ -266,6 +681,15 @@ impl<'a> EventContext<'a> {
         self.update_views(&ed, &delta, &last_text, drift);
         self.update_plugins(&mut ed, delta, author);
 
+        let recovery_interval = self.config.recovery_interval;
+        if recovery_interval > 0 && !ed.has_pending_recovery() {
+            let timeout = Instant::now() + Duration::from_secs(recovery_interval);
+            let buffer_id: usize = self.buffer_id.into();
+            let token = RECOVERY_BUFFER_IDLE_MASK | buffer_id;
+            self.client.schedule_timer(timeout, token);
+            ed.set_has_pending_recovery(true);
+        }
+
         //if we have no plugins we always render immediately.
         if !self.plugins.is_empty() {
             let mut view = self.view.borrow_mut();
@@ -280,6 +704,7 @@ impl<'a> EventContext<'a> {
     }
 
     fn update_views(&self, ed: &Editor, delta: &RopeDelta, last_text: &Rope, drift: InsertDrift) {
+        self.completions.borrow_mut().update(self.buffer_id, last_text, ed.get_buffer(), delta);
         let mut width_cache = self.width_cache.borrow_mut();
         let iter_views = iter::once(&self.view).chain(self.siblings.iter());
         iter_views.for_each(|view| {
@@ -351,6 +776,7 @@ impl<'a> EventContext<'a> {
     /// Flushes any changes in the views out to the frontend.
     fn render(&mut self) {
         let _t = trace_block("EventContext::render", &["core"]);
+        self.update_brackets();
         let ed = self.editor.borrow();
         //TODO: render other views
         self.view.borrow_mut().render_if_dirty(
@@ -384,16 +810,43 @@ impl<'a> EventContext<'a> {
 
         self.client.config_changed(self.view_id, config);
         self.client.language_changed(self.view_id, &self.language);
+        if let Some(info) = self.info {
+            self.client.encoding_changed(self.view_id, info.encoding);
+        }
+        self.completions
+            .borrow_mut()
+            .index_buffer(self.buffer_id, self.editor.borrow().get_buffer());
         self.update_wrap_state();
         self.render()
     }
 
-    pub(crate) fn after_save(&mut self, path: &Path) {
+    /// Returns the edits that are made when saving, such as removing
+    /// trailing whitespace, as configured for this buffer. They are only
+    /// applied, by `after_save`, once the edited text has been saved.
+    pub(crate) fn save_edits(&self) -> Option<RopeDelta> {
+        let config = self.config;
+        if !config.trim_trailing_whitespace && !config.insert_final_newline {
+            return None;
+        }
+        let final_newline =
+            if config.insert_final_newline { Some(config.line_ending.as_str()) } else { None };
+        self.editor.borrow().save_edits(config.trim_trailing_whitespace, final_newline)
+    }
+
+    pub(crate) fn after_save(&mut self, path: &Path, save_edits: Option<RopeDelta>) {
+        if let Some(delta) = save_edits {
+            self.with_editor(|ed, _, _, _| ed.apply_save_edits(delta));
+            self.after_edit("core");
+        }
+
         // notify plugins
         self.plugins.iter().for_each(|plugin| plugin.did_save(self.view_id, path));
 
         self.editor.borrow_mut().set_pristine();
         self.with_view(|view, text| view.set_dirty(text));
+        if let Some(info) = self.info {
+            self.client.encoding_changed(self.view_id, info.encoding);
+        }
         self.render()
     }
 
@@ -427,6 +880,46 @@ impl<'a> EventContext<'a> {
         self.render();
     }
 
+    /// Replaces the buffer's contents with those of its recovery file.
+    pub(crate) fn restore_recovery(&mut self, text: Rope) {
+        self.with_editor(|ed, _, _, _| ed.replace_contents(text));
+        self.after_edit("core");
+        self.render();
+    }
+
+    /// Merges changes made to the file on disk into the buffer, which has
+    /// unsaved edits. Returns the regions where the changes conflict.
+    pub(crate) fn merge_disk_changes(&mut self, text: Rope) -> Vec<Interval> {
+        let conflicts = self.with_editor(|ed, _, _, _| ed.merge_disk_changes(text));
+        self.after_edit("core");
+        self.render();
+        conflicts
+    }
+
+    /// Notifies the client that the file backing this view has changed on
+    /// disk, along with the lines of any regions where merging the changes
+    /// into the buffer's unsaved edits caused conflicts.
+    pub(crate) fn file_changed(&mut self, path: &Path, conflicts: &[Interval]) {
+        let conflicts = self.with_view(|view, text| {
+            conflicts
+                .iter()
+                .map(|iv| {
+                    let last = if iv.is_empty() { iv.end() } else { iv.end() - 1 };
+                    (view.line_of_offset(text, iv.start()), view.line_of_offset(text, last))
+                })
+                .collect::<Vec<_>>()
+        });
+        self.client.file_changed(self.view_id, path, &conflicts);
+    }
+
+    /// Replaces the buffer's contents with the file reloaded in a new encoding.
+    pub(crate) fn reopen(&mut self, text: Rope) {
+        self.reload(text);
+        if let Some(info) = self.info {
+            self.client.encoding_changed(self.view_id, info.encoding);
+        }
+    }
+
     pub(crate) fn plugin_info(&mut self) -> PluginBufferInfo {
         let ed = self.editor.borrow();
         let nb_lines = ed.get_buffer().measure::<LinesMetric>() + 1;
@@ -494,6 +987,24 @@ impl<'a> EventContext<'a> {
         self.render();
     }
 
+    /// Finds the bracket pairs around the visible carets, which are
+    /// highlighted.
+    fn update_brackets(&mut self) {
+        let ed = self.editor.borrow();
+        let text = ed.get_buffer();
+        let matcher = BracketMatcher::new(text, &self.config.bracket_pairs, ed.get_layers());
+        self.view.borrow_mut().update_brackets(text, &matcher);
+    }
+
+    /// Finds the bracket pairs around all the view's selection regions,
+    /// which bracket movements use.
+    fn find_all_brackets(&mut self) {
+        let ed = self.editor.borrow();
+        let text = ed.get_buffer();
+        let matcher = BracketMatcher::new(text, &self.config.bracket_pairs, ed.get_layers());
+        self.view.borrow_mut().find_all_brackets(text, &matcher);
+    }
+
     fn do_request_lines(&mut self, first: usize, last: usize) {
         let mut view = self.view.borrow_mut();
         let ed = self.editor.borrow();
@@ -534,11 +1045,22 @@ impl<'a> EventContext<'a> {
     }
 }
 
+/// Returns `true` if `cmd` needs the bracket pairs around the selection.
+fn uses_brackets(cmd: &ViewEvent) -> bool {
+    match *cmd {
+        ViewEvent::Move(Movement::MatchingBracket)
+        | ViewEvent::ModifySelection(Movement::MatchingBracket)
+        | ViewEvent::SelectMatchingBrackets => true,
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 #[cfg_attr(rustfmt, rustfmt_skip)]
 mod tests {
     use super::*;
-    use config::ConfigManager;
+    use config::{ConfigDomain, ConfigManager};
+    use syntax::Languages;
     use core::dummy_weak_core;
     use tabs::BufferId;
     use xi_rpc::test_utils::DummyPeer;
@@ -548,11 +1070,13 @@ mod tests {
         editor: RefCell<Editor>,
         client: Client,
         core_ref: WeakXiCore,
-        kill_ring: RefCell<Rope>,
+        kill_ring: RefCell<KillRing>,
         style_map: RefCell<ThemeStyleMap>,
         width_cache: RefCell<WidthCache>,
         config_manager: ConfigManager,
         recorder: RefCell<Recorder>,
+        snippets: Snippets,
+        completions: RefCell<Completions>,
     }
 
     impl ContextHarness {
@@ -560,17 +1084,20 @@ mod tests {
             let view_id = ViewId(1);
             let buffer_id = BufferId(2);
             let mut config_manager = ConfigManager::new(None, None);
-            config_manager.add_buffer(buffer_id, None);
+            config_manager.add_buffer(buffer_id, None, &Rope::from(s.as_ref()));
             let view = RefCell::new(View::new(view_id, buffer_id));
             let editor = RefCell::new(Editor::with_text(s));
             let client = Client::new(Box::new(DummyPeer));
             let core_ref = dummy_weak_core();
-            let kill_ring = RefCell::new(Rope::from(""));
+            let kill_ring = RefCell::new(KillRing::new());
             let style_map = RefCell::new(ThemeStyleMap::new(None));
             let width_cache = RefCell::new(WidthCache::new());
             let recorder = RefCell::new(Recorder::new());
+            let snippets = Snippets::new();
+            let completions = RefCell::new(Completions::new());
             ContextHarness { view, editor, client, core_ref, kill_ring,
-                             style_map, width_cache, config_manager, recorder }
+                             style_map, width_cache, config_manager, recorder,
+                             snippets, completions }
         }
 
         /// Renders the text and selections. cursors are represented with
@@ -593,11 +1120,21 @@ mod tests {
             text
         }
 
+        /// Applies `changes`, a JSON object of config keys and values, as
+        /// the user's overrides for the harness's buffer.
+        fn set_config(&mut self, changes: Value) {
+            let buffer_id = self.view.borrow().get_buffer_id();
+            let domain = ConfigDomain::UserOverride(buffer_id);
+            let changes = changes.as_object().unwrap().to_owned();
+            self.config_manager.set_user_config(domain, changes).unwrap();
+        }
+
         fn make_context<'a>(&'a self) -> EventContext<'a> {
             let view_id = ViewId(1);
             let buffer_id = self.view.borrow().get_buffer_id();
             let config = self.config_manager.get_buffer_config(buffer_id);
             let language = self.config_manager.get_buffer_language(buffer_id);
+            let language_def = self.config_manager.get_buffer_language_definition(buffer_id);
             EventContext {
                 view_id,
                 buffer_id,
@@ -605,10 +1142,13 @@ mod tests {
                 editor: &self.editor,
                 config: &config.items,
                 language,
+                language_def,
                 info: None,
                 siblings: Vec::new(),
                 plugins: Vec::new(),
                 recorder: &self.recorder,
+                snippets: &self.snippets,
+                completions: &self.completions,
                 client: &self.client,
                 kill_ring: &self.kill_ring,
                 style_map: &self.style_map,
@@ -1759,4 +2299,754 @@ mod tests {
         lines.\n\
         And lines |with very different length.");
     }
+
+    #[test]
+    fn fold_indentation_block() {
+        use rpc::GestureType::*;
+        let initial_text = "\
+        fn main() {\n\
+        \x20   let a = 1;\n\
+        \x20   let b = 2;\n\
+        }\n\
+        end";
+        let harness = ContextHarness::new(initial_text);
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 3, ty: PointSelect });
+        ctx.do_edit(EditNotification::Fold);
+        {
+            let view = harness.view.borrow();
+            let editor = harness.editor.borrow();
+            let text = editor.get_buffer();
+            // the block is hidden, joining its first and last lines
+            assert_eq!(view.line_of_offset(text, text.len()), 2);
+            assert_eq!(view.offset_of_line(text, 1), text.offset_of_line(3));
+            assert_eq!(view.offset_to_line_col(text, text.offset_of_line(3)), (1, 0));
+            assert_eq!(view.offset_to_line_col(text, text.offset_of_line(3) - 1), (0, 11));
+        }
+
+        // vertical movement skips the folded lines
+        ctx.do_edit(EditNotification::MoveDown);
+        assert_eq!(harness.debug_render(),"\
+        fn main() {\n\
+        \x20   let a = 1;\n\
+        \x20   let b = 2;\n\
+        }|\n\
+        end");
+        ctx.do_edit(EditNotification::MoveUp);
+        assert_eq!(harness.debug_render(),"\
+        fn |main() {\n\
+        \x20   let a = 1;\n\
+        \x20   let b = 2;\n\
+        }\n\
+        end");
+
+        // the end of the visual line is after the fold
+        ctx.do_edit(EditNotification::MoveToRightEndOfLine);
+        assert_eq!(harness.debug_render(),"\
+        fn main() {\n\
+        \x20   let a = 1;\n\
+        \x20   let b = 2;|\n\
+        }\n\
+        end");
+
+        // horizontal movement jumps over the fold
+        ctx.do_edit(EditNotification::MoveLeft);
+        assert_eq!(harness.debug_render(),"\
+        fn main() {|\n\
+        \x20   let a = 1;\n\
+        \x20   let b = 2;\n\
+        }\n\
+        end");
+        ctx.do_edit(EditNotification::MoveRight);
+        assert_eq!(harness.debug_render(),"\
+        fn main() {\n\
+        \x20   let a = 1;\n\
+        \x20   let b = 2;|\n\
+        }\n\
+        end");
+
+        ctx.do_edit(EditNotification::ToggleFold);
+        let view = harness.view.borrow();
+        let editor = harness.editor.borrow();
+        assert_eq!(view.line_of_offset(editor.get_buffer(), editor.get_buffer().len()), 4);
+    }
+
+    #[test]
+    fn fold_selection_across_edits() {
+        use rpc::GestureType::*;
+        let harness = ContextHarness::new("one\ntwo\nthree\nfour");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 2, col: 2, ty: RangeSelect });
+        ctx.do_edit(EditNotification::Fold);
+        assert_eq!(harness.debug_render(), "o|ne\ntwo\nthree\nfour");
+
+        // edits before the fold move it, and text typed at its start stays visible
+        ctx.do_edit(EditNotification::Insert { chars: "xx".into() });
+        assert_eq!(harness.debug_render(), "oxx|ne\ntwo\nthree\nfour");
+        {
+            let view = harness.view.borrow();
+            let editor = harness.editor.borrow();
+            let text = editor.get_buffer();
+            assert_eq!(view.line_of_offset(text, text.len()), 1);
+            // "oxx" + "ree"
+            assert_eq!(view.line_col_to_offset(text, 0, 4), 13);
+        }
+
+        ctx.do_edit(EditNotification::MoveRight);
+        assert_eq!(harness.debug_render(), "oxxne\ntwo\nth|ree\nfour");
+
+        ctx.do_edit(EditNotification::Unfold);
+        ctx.do_edit(EditNotification::MoveToBeginningOfDocument);
+        ctx.do_edit(EditNotification::MoveDown);
+        assert_eq!(harness.debug_render(), "oxxne\n|two\nthree\nfour");
+    }
+
+    #[test]
+    fn replace_regex_captures() {
+        let harness = ContextHarness::new("let a = foo(1, 2);\nlet b = foo(3, 4);");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Find {
+            chars: r"foo\((?P<x>\d), (\d)\)".into(),
+            case_sensitive: true,
+            regex: true,
+            whole_words: false,
+        });
+        ctx.do_edit(EditNotification::Replace { chars: "bar($2, ${x})".into(), preserve_case: false });
+        ctx.do_edit(EditNotification::ReplaceNext);
+        assert_eq!(harness.debug_render(), "let a = bar(2, 1)|;\nlet b = foo(3, 4);");
+        ctx.do_edit(EditNotification::ReplaceAll);
+        assert_eq!(harness.debug_render(), "let a = bar(2, 1);\nlet b = bar(4, 3)|;");
+
+        // word boundaries see the text around each match
+        let harness = ContextHarness::new("ab cd");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Find {
+            chars: r"\B(\w)".into(),
+            case_sensitive: true,
+            regex: true,
+            whole_words: false,
+        });
+        ctx.do_edit(EditNotification::Replace { chars: "[$1]".into(), preserve_case: false });
+        ctx.do_edit(EditNotification::ReplaceAll);
+        assert_eq!(harness.debug_render(), "a[b]| c[d]|");
+
+        // captures of a multiline regex
+        let harness = ContextHarness::new("a\nb\nc\nd");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Find {
+            chars: r"(\w)\n(\w)".into(),
+            case_sensitive: true,
+            regex: true,
+            whole_words: false,
+        });
+        ctx.do_edit(EditNotification::Replace { chars: "$2$1".into(), preserve_case: false });
+        ctx.do_edit(EditNotification::ReplaceAll);
+        assert_eq!(harness.debug_render(), "ba|\ndc|");
+    }
+
+    #[test]
+    fn replace_preserve_case() {
+        let harness = ContextHarness::new("foo Foo FOO fOo");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Find {
+            chars: "foo".into(),
+            case_sensitive: false,
+            regex: false,
+            whole_words: false,
+        });
+        ctx.do_edit(EditNotification::Replace { chars: "bar".into(), preserve_case: true });
+        ctx.do_edit(EditNotification::ReplaceAll);
+        assert_eq!(harness.debug_render(), "bar| Bar| BAR| bar|");
+
+        // literal replacement strings are not expanded for plain text searches
+        ctx.do_edit(EditNotification::Find {
+            chars: "bar".into(),
+            case_sensitive: true,
+            regex: false,
+            whole_words: false,
+        });
+        ctx.do_edit(EditNotification::Replace { chars: "$1".into(), preserve_case: false });
+        ctx.do_edit(EditNotification::ReplaceAll);
+        assert_eq!(harness.debug_render(), "$1| Bar BAR $1|");
+    }
+
+    #[test]
+    fn merge_disk_changes() {
+        let base = "the first line of the file, long enough to diff\nthe second line of the file, long enough to diff\n";
+        let harness = ContextHarness::new(base);
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Insert { chars: "// ".into() });
+        assert_eq!(harness.debug_render(), format!("// |{}", base));
+
+        let disk = base.replace("second", "2nd");
+        let conflicts = ctx.merge_disk_changes(Rope::from(disk.as_str()));
+        assert!(conflicts.is_empty());
+        assert_eq!(harness.debug_render(), format!("// |{}", disk));
+
+        // the merge is undone separately from the edits it was merged into
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(String::from(harness.editor.borrow().get_buffer()), format!("// {}", base));
+        ctx.do_edit(EditNotification::Redo);
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::Insert { chars: "mine".into() });
+
+        // changes on disk are merged against the contents last merged
+        let disk = format!("{}theirs", disk);
+        let conflicts = ctx.merge_disk_changes(Rope::from(disk.as_str()));
+        assert_eq!(conflicts.len(), 1);
+        let text = String::from(harness.editor.borrow().get_buffer());
+        assert!(text.starts_with(&format!("// {}", disk.replace("theirs", ""))));
+        let conflict = &text[conflicts[0].start()..conflicts[0].end()];
+        assert_eq!(conflict, "<<<<<<< buffer\nmine\n=======\ntheirs\n>>>>>>> disk\n");
+    }
+
+    #[test]
+    fn edits_before_save() {
+        let mut harness = ContextHarness::new("fn main() {  \n    \n}\t");
+        harness.set_config(json!({"trim_trailing_whitespace": true, "insert_final_newline": true}));
+        let mut ctx = harness.make_context();
+        let edits = ctx.save_edits().unwrap();
+        let saved = edits.apply(harness.editor.borrow().get_buffer());
+        assert_eq!(String::from(saved), "fn main() {\n\n}\n");
+        // the buffer only changes once it is saved
+        assert_eq!(harness.debug_render(), "|fn main() {  \n    \n}\t");
+        ctx.after_save(Path::new("main.rs"), Some(edits));
+        assert_eq!(harness.debug_render(), "|fn main() {\n\n}\n");
+
+        // the edits are undone separately
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(String::from(harness.editor.borrow().get_buffer()), "fn main() {  \n    \n}\t");
+    }
+
+    #[test]
+    fn matching_brackets() {
+        use rpc::GestureType::*;
+        let harness = ContextHarness::new("fn f(a: [u8; 2]) {\n    g(a[0]);\n}");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 4, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn f(a: [u8; 2]|) {\n    g(a[0]);\n}");
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn f|(a: [u8; 2]) {\n    g(a[0]);\n}");
+
+        // inside a pair, moves to its closing bracket
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 5, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn f(a: [u8; 2]) {\n    g(a[0]|);\n}");
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracketAndModifySelection);
+        assert_eq!(harness.debug_render(), "fn f(a: [u8; 2]) {\n [   g(a[0]);\n|]}");
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 10, ty: PointSelect });
+        ctx.do_edit(EditNotification::SelectMatchingBrackets);
+        assert_eq!(harness.debug_render(), "fn f(a: [[u8; 2]|]) {\n    g(a[0]);\n}");
+
+        // the pairs are found again after edits, and unmatched brackets
+        // are ignored
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::Insert { chars: "}".into() });
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn f(a: [u8; 2]) {\n    g(a[0]);\n}}|");
+        ctx.do_edit(EditNotification::MoveLeft);
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn f(a: [u8; 2]) {|\n    g(a[0]);\n}}");
+    }
+
+    #[test]
+    fn highlighted_brackets() {
+        use rpc::GestureType::*;
+        let harness = ContextHarness::new("(a)\n".repeat(30));
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 20, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 0, ty: ToggleSel });
+        ctx.do_edit(EditNotification::Scroll(LineRange { first: 0, last: 10 }));
+        // only the pairs around carets on the visible lines are highlighted
+        let brackets = harness.view.borrow().highlighted_brackets();
+        assert_eq!(brackets, vec![Interval::new(0, 1), Interval::new(2, 3)]);
+        ctx.do_edit(EditNotification::Scroll(LineRange { first: 15, last: 25 }));
+        let brackets = harness.view.borrow().highlighted_brackets();
+        assert_eq!(brackets, vec![Interval::new(80, 81), Interval::new(82, 83)]);
+
+        // bracket movements still move every caret
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        let carets = harness.view.borrow().sel_regions().iter().map(|r| r.end).collect::<Vec<_>>();
+        assert_eq!(carets, vec![2, 82]);
+    }
+
+    #[test]
+    fn auto_close_pairs() {
+        let mut harness = ContextHarness::new("");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Insert { chars: "(".into() });
+        assert_eq!(harness.debug_render(), "(|");
+        ctx.do_edit(EditNotification::DeleteBackward);
+
+        // the default pairs include parentheses
+        harness.set_config(json!({"auto_close_pairs": true}));
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Insert { chars: "(".into() });
+        assert_eq!(harness.debug_render(), "(|)");
+        ctx.do_edit(EditNotification::Insert { chars: "a".into() });
+        ctx.do_edit(EditNotification::Insert { chars: ")".into() });
+        assert_eq!(harness.debug_render(), "(a)|");
+        // closers that were not inserted automatically are not typed over
+        ctx.do_edit(EditNotification::MoveLeft);
+        ctx.do_edit(EditNotification::Insert { chars: ")".into() });
+        assert_eq!(harness.debug_render(), "(a)|)");
+        // pairs are only closed before whitespace and closers
+        ctx.do_edit(EditNotification::MoveToBeginningOfDocument);
+        ctx.do_edit(EditNotification::Insert { chars: "[".into() });
+        assert_eq!(harness.debug_render(), "[|(a))");
+
+        // quotes after words are not closed
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::Insert { chars: " don".into() });
+        ctx.do_edit(EditNotification::Insert { chars: "'".into() });
+        ctx.do_edit(EditNotification::Insert { chars: " ".into() });
+        ctx.do_edit(EditNotification::Insert { chars: "\"".into() });
+        assert_eq!(harness.debug_render(), "[(a)) don' \"|\"");
+        ctx.do_edit(EditNotification::Insert { chars: "\"".into() });
+        assert_eq!(harness.debug_render(), "[(a)) don' \"\"|");
+    }
+
+    #[test]
+    fn auto_close_pairs_multiple_cursors() {
+        use rpc::GestureType::*;
+        let mut harness = ContextHarness::new("foo\nbar x");
+        harness.set_config(json!({"auto_close_pairs": true}));
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 3, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 3, ty: ToggleSel });
+        ctx.do_edit(EditNotification::Insert { chars: "{".into() });
+        assert_eq!(harness.debug_render(), "foo{|}\nbar{|} x");
+        ctx.do_edit(EditNotification::Insert { chars: "[".into() });
+        ctx.do_edit(EditNotification::Insert { chars: "]".into() });
+        assert_eq!(harness.debug_render(), "foo{[]|}\nbar{[]|} x");
+        ctx.do_edit(EditNotification::Insert { chars: "}".into() });
+        assert_eq!(harness.debug_render(), "foo{[]}|\nbar{[]}| x");
+
+        // backspace deletes empty pairs
+        ctx.do_edit(EditNotification::MoveLeft);
+        ctx.do_edit(EditNotification::MoveLeft);
+        assert_eq!(harness.debug_render(), "foo{[|]}\nbar{[|]} x");
+        ctx.do_edit(EditNotification::DeleteBackward);
+        assert_eq!(harness.debug_render(), "foo{|}\nbar{|} x");
+        ctx.do_edit(EditNotification::DeleteBackward);
+        assert_eq!(harness.debug_render(), "foo|\nbar| x");
+    }
+
+    #[test]
+    fn toggle_comment() {
+        let mut harness = ContextHarness::new("fn f() {\n    a();\n\n  b();\n}");
+        let mut rust = LanguageDefinition::simple("Rust", &["rs"], "source.rust", None);
+        rust.line_comment = Some("//".into());
+        rust.block_comment = Some(("/*".into(), "*/".into()));
+        harness.config_manager.set_languages(Languages::new(&[rust]));
+        let mut ctx = harness.make_context();
+        // plain text has no comment tokens
+        ctx.do_edit(EditNotification::ToggleComment);
+        assert_eq!(harness.debug_render(), "|fn f() {\n    a();\n\n  b();\n}");
+
+        harness.config_manager.override_language(BufferId(2), "Rust".into());
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::MoveDown);
+        ctx.do_edit(EditNotification::MoveDownAndModifySelection);
+        ctx.do_edit(EditNotification::MoveDownAndModifySelection);
+        ctx.do_edit(EditNotification::MoveToRightEndOfLineAndModifySelection);
+        ctx.do_edit(EditNotification::ToggleComment);
+        assert_eq!(harness.debug_render(), "fn f() {\n[  //   a();\n\n  // b();|]\n}");
+        ctx.do_edit(EditNotification::ToggleComment);
+        assert_eq!(harness.debug_render(), "fn f() {\n[    a();\n\n  b();|]\n}");
+        ctx.do_edit(EditNotification::ToggleBlockComment);
+        assert_eq!(harness.debug_render(), "fn f() {\n[  /*   a(); */\n\n  /* b(); */|]\n}");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "fn f() {\n[    a();\n\n  b();|]\n}");
+    }
+
+    #[test]
+    fn line_commands() {
+        let harness = ContextHarness::new("b\nc\na\nd");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::SortLines);
+        assert_eq!(harness.debug_render(), "[a\nb\nc\nd|]");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "b\nc\na\nd|");
+
+        ctx.do_edit(EditNotification::MoveToBeginningOfDocument);
+        ctx.do_edit(EditNotification::AddSelectionBelow);
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "a\n|b\n|c\nd");
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "a\nd\n|b\n|c");
+        // blocks at the end of the text don't move
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "a\nd\n|b\n|c");
+        ctx.do_edit(EditNotification::JoinLines);
+        assert_eq!(harness.debug_render(), "a\nd\n|b |c");
+
+        // line commands can be recorded
+        let recording_name = String::new();
+        ctx.do_edit(EditNotification::ToggleRecording { recording_name: Some(recording_name.clone()) });
+        ctx.do_edit(EditNotification::MoveLinesUp);
+        ctx.do_edit(EditNotification::ToggleRecording { recording_name: Some(recording_name.clone()) });
+        assert_eq!(harness.debug_render(), "a\n|b |c\nd");
+        ctx.do_edit(EditNotification::PlayRecording { recording_name });
+        assert_eq!(harness.debug_render(), "|b |c\na\nd");
+    }
+
+    #[test]
+    fn column_selection() {
+        use rpc::GestureType::*;
+        use rpc::MouseAction;
+        let harness = ContextHarness::new("abcd\nab\n\tcdef");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: ColumnSelect });
+        assert_eq!(harness.debug_render(), "a|bcd\nab\n\tcdef");
+        let drag = MouseAction { line: 2, column: 3, flags: 0, click_count: None };
+        ctx.do_edit(EditNotification::Drag(drag));
+        assert_eq!(harness.debug_render(), "a[bcd|]\na[b|]\n[\tcd|]ef");
+        ctx.do_edit(EditNotification::ColumnSelectLeft);
+        ctx.do_edit(EditNotification::ColumnSelectUp);
+        assert_eq!(harness.debug_render(), "a[bcd|]\na[b|]\n\tcdef");
+        ctx.do_edit(EditNotification::Insert { chars: "x".into() });
+        assert_eq!(harness.debug_render(), "ax|\nax|\n\tcdef");
+        ctx.do_edit(EditNotification::Paste { chars: "1\n2".into(), register: None });
+        assert_eq!(harness.debug_render(), "ax1|\nax2|\n\tcdef");
+
+        // keyboard column selection starts from the last region
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::ColumnSelectDown);
+        ctx.do_edit(EditNotification::ColumnSelectDown);
+        ctx.do_edit(EditNotification::ColumnSelectRight);
+        assert_eq!(harness.debug_render(), "a[x|]1\na[x|]2\n|\tcdef");
+
+        ctx.do_edit(EditNotification::MoveToBeginningOfDocument);
+        ctx.do_edit(EditNotification::MoveDownAndModifySelection);
+        ctx.do_edit(EditNotification::MoveRightAndModifySelection);
+        ctx.do_edit(EditNotification::ColumnSelectionIntoLines);
+        assert_eq!(harness.debug_render(), "[a|]x1\n[a|]x2\n\tcdef");
+    }
+
+    #[test]
+    fn column_drag_over_short_lines() {
+        use rpc::GestureType::*;
+        use rpc::MouseAction;
+        let harness = ContextHarness::new("abcdef\nab\nabcd");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: ColumnSelect });
+        // the block keeps the column of the pointer past the end of a line
+        let drag = MouseAction { line: 1, column: 5, flags: 0, click_count: None };
+        ctx.do_edit(EditNotification::Drag(drag));
+        assert_eq!(harness.debug_render(), "a[bcde|]f\na[b|]\nabcd");
+        let drag = MouseAction { line: 2, column: 5, flags: 0, click_count: None };
+        ctx.do_edit(EditNotification::Drag(drag));
+        assert_eq!(harness.debug_render(), "a[bcde|]f\na[b|]\na[bcd|]");
+        let drag = MouseAction { line: 1, column: 3, flags: 0, click_count: None };
+        ctx.do_edit(EditNotification::Drag(drag));
+        assert_eq!(harness.debug_render(), "a[bc|]def\na[b|]\nabcd");
+    }
+
+    #[test]
+    fn modal_editing() {
+        let mut harness = ContextHarness::new("one two\nthree\nfour");
+        harness.set_config(json!({"modal_editing": true}));
+        let mut ctx = harness.make_context();
+        let keys = |ctx: &mut EventContext, keys: &str| {
+            ctx.do_edit(EditNotification::Insert { chars: keys.into() });
+        };
+        keys(&mut ctx, "w");
+        assert_eq!(harness.debug_render(), "one |two\nthree\nfour");
+        // an operator moving by words stops at the end of the line
+        keys(&mut ctx, "dw");
+        assert_eq!(harness.debug_render(), "one| \nthree\nfour");
+        keys(&mut ctx, "u");
+        assert_eq!(harness.debug_render(), "one| two\nthree\nfour");
+
+        // counts, linewise operators and putting killed lines
+        keys(&mut ctx, "gg2dd");
+        assert_eq!(harness.debug_render(), "|four");
+        keys(&mut ctx, "p");
+        assert_eq!(harness.debug_render(), "four\none two\nthre|e");
+        keys(&mut ctx, "ggyyP");
+        assert_eq!(harness.debug_render(), "four\n|four\none two\nthree");
+
+        // insert mode, and repeating the change with `.`
+        keys(&mut ctx, "Afoo");
+        assert_eq!(harness.debug_render(), "four\nfourfoo|\none two\nthree");
+        ctx.do_edit(EditNotification::CancelOperation);
+        keys(&mut ctx, "j.");
+        assert_eq!(harness.debug_render(), "four\nfourfoo\none twofo|o\nthree");
+        keys(&mut ctx, "ciwbar");
+        ctx.do_edit(EditNotification::CancelOperation);
+        assert_eq!(harness.debug_render(), "four\nfourfoo\none ba|r\nthree");
+        keys(&mut ctx, "gg.");
+        assert_eq!(harness.debug_render(), "ba|r\nfourfoo\none bar\nthree");
+
+        // visual modes
+        keys(&mut ctx, "j0vllll");
+        assert_eq!(harness.debug_render(), "bar\n[four|]foo\none bar\nthree");
+        keys(&mut ctx, "d");
+        assert_eq!(harness.debug_render(), "bar\n|foo\none bar\nthree");
+        keys(&mut ctx, "Vj>");
+        assert_eq!(harness.debug_render(), "bar\n    |foo\n    one bar\nthree");
+        keys(&mut ctx, "Vkx");
+        assert_eq!(harness.debug_render(), "|    one bar\nthree");
+
+        // words are deleted or changed along with the whitespace after them
+        keys(&mut ctx, "dw");
+        assert_eq!(harness.debug_render(), "|one bar\nthree");
+        keys(&mut ctx, "cwx");
+        ctx.do_edit(EditNotification::CancelOperation);
+        assert_eq!(harness.debug_render(), "x|bar\nthree");
+
+        // the cursor rests on the last character of a line, so `$x` deletes
+        // that character rather than the line break
+        keys(&mut ctx, "$x");
+        assert_eq!(harness.debug_render(), "xb|a\nthree");
+        keys(&mut ctx, "j");
+        assert_eq!(harness.debug_render(), "xba\nth|ree");
+        keys(&mut ctx, ">>$^");
+        assert_eq!(harness.debug_render(), "xba\n    |three");
+
+        // huge counts are clamped instead of overflowing
+        keys(&mut ctx, "99999999999999999999d99999999999w");
+        assert_eq!(harness.debug_render(), "xba\n   | ");
+        keys(&mut ctx, "u");
+        assert_eq!(harness.debug_render(), "xba\n   | three");
+    }
+
+    #[test]
+    fn kill_ring() {
+        use rpc::GestureType::*;
+        let harness = ContextHarness::new("one\ntwo\nthree");
+        let mut ctx = harness.make_context();
+        // consecutive kills are added to the same kill
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        ctx.do_edit(EditNotification::MoveDown);
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        assert_eq!(harness.debug_render(), "two\n|");
+        ctx.do_edit(EditNotification::Yank);
+        assert_eq!(harness.debug_render(), "two\nthree|");
+
+        // yank pop replaces the yanked text with earlier kills
+        ctx.do_edit(EditNotification::YankPop);
+        assert_eq!(harness.debug_render(), "two\none\n|");
+        ctx.do_edit(EditNotification::YankPop);
+        assert_eq!(harness.debug_render(), "two\nthree|");
+        ctx.do_edit(EditNotification::MoveLeft);
+        ctx.do_edit(EditNotification::YankPop);
+        assert_eq!(harness.debug_render(), "two\nthre|e");
+
+        // registers keep the text of each region
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 0, ty: WordSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 0, ty: MultiWordSelect });
+        let register = Some("a".to_owned());
+        let result = ctx.do_edit_sync(EditRequest::Cut { register: register.clone() });
+        assert_eq!(result, Ok(json!("two\nthree")));
+        assert_eq!(harness.debug_render(), "|\n|");
+        ctx.do_edit(EditNotification::Paste { chars: String::new(), register: register.clone() });
+        assert_eq!(harness.debug_render(), "two|\nthree|");
+        ctx.do_edit(EditNotification::CancelOperation);
+        ctx.do_edit(EditNotification::Paste { chars: String::new(), register });
+        assert_eq!(harness.debug_render(), "twotwo\nthree|\nthree");
+    }
+
+    #[test]
+    fn snippets() {
+        use rpc::GestureType::*;
+        use snippet::SnippetDefinition;
+        use std::collections::BTreeMap;
+        let mut harness = ContextHarness::new("  \n  ");
+        let language = harness.config_manager.get_buffer_language(BufferId(2));
+        let body = "for ${1:x} in $2 {\n\t$1$0\n}".to_owned();
+        let mut defs = BTreeMap::new();
+        defs.insert("for".to_owned(), SnippetDefinition {
+            prefix: "for".to_owned(), body, description: String::new() });
+        harness.snippets.set_language(language, defs);
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 2, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 2, ty: ToggleSel });
+        ctx.do_edit(EditNotification::Insert { chars: "for".to_owned() });
+
+        // the snippet is expanded at each cursor, selecting linked tab stops
+        ctx.do_edit(EditNotification::ExpandSnippet);
+        assert_eq!(harness.debug_render(), "  for [x|] in  {\n      [x|]\n  }\n  for [x|] in  {\n      [x|]\n  }");
+        ctx.do_edit(EditNotification::Insert { chars: "i".to_owned() });
+        ctx.do_edit(EditNotification::NextTabStop);
+        ctx.do_edit(EditNotification::Insert { chars: "v".to_owned() });
+        assert_eq!(harness.debug_render(), "  for i in v| {\n      i\n  }\n  for i in v| {\n      i\n  }");
+        ctx.do_edit(EditNotification::PrevTabStop);
+        assert_eq!(harness.debug_render(), "  for [i|] in v {\n      [i|]\n  }\n  for [i|] in v {\n      [i|]\n  }");
+        ctx.do_edit(EditNotification::NextTabStop);
+        ctx.do_edit(EditNotification::NextTabStop);
+        assert_eq!(harness.debug_render(), "  for i in v {\n      i|\n  }\n  for i in v {\n      i|\n  }");
+        // the final tab stop finishes the snippet
+        ctx.do_edit(EditNotification::PrevTabStop);
+        assert_eq!(harness.debug_render(), "  for i in v {\n      i|\n  }\n  for i in v {\n      i|\n  }");
+
+        // variables, and cancelling
+        let harness = ContextHarness::new("one two");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 4, ty: WordSelect });
+        let snippet = "f(${1:$TM_SELECTED_TEXT})$0".to_owned();
+        ctx.do_edit(EditNotification::InsertSnippet { snippet });
+        assert_eq!(harness.debug_render(), "one f([two|])");
+        ctx.do_edit(EditNotification::CancelOperation);
+        ctx.do_edit(EditNotification::NextTabStop);
+        assert_eq!(harness.debug_render(), "one f(two|)");
+    }
+
+    #[test]
+    fn completions() {
+        let harness = ContextHarness::new("total tomato\n");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::Insert { chars: "to".to_owned() });
+        ctx.do_edit(EditNotification::RequestCompletions { request_id: 1 });
+        // completions of an earlier request can't be accepted
+        ctx.do_edit(EditNotification::AcceptCompletion { request_id: 0, index: 0 });
+        assert_eq!(harness.debug_render(), "total tomato\nto|");
+        ctx.do_edit(EditNotification::RequestCompletions { request_id: 1 });
+        ctx.do_edit(EditNotification::AcceptCompletion { request_id: 1, index: 0 });
+        assert_eq!(harness.debug_render(), "total tomato\ntomato|");
+        ctx.do_edit(EditNotification::AcceptCompletion { request_id: 1, index: 0 });
+        assert_eq!(harness.debug_render(), "total tomato\ntomato|");
+
+        ctx.do_edit(EditNotification::Insert { chars: " t".to_owned() });
+        ctx.do_edit(EditNotification::RequestCompletions { request_id: 2 });
+        ctx.do_edit(EditNotification::AcceptCompletion { request_id: 2, index: 1 });
+        assert_eq!(harness.debug_render(), "total tomato\ntomato total|");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "total tomato\ntomato t|");
+    }
+
+    #[test]
+    fn subword_movement() {
+        let mut harness = ContextHarness::new("parseHttpRequest MAX_SIZE\nutf8");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::MoveSubwordRight);
+        assert_eq!(harness.debug_render(), "parse|HttpRequest MAX_SIZE\nutf8");
+        ctx.do_edit(EditNotification::MoveSubwordRightAndModifySelection);
+        assert_eq!(harness.debug_render(), "parse[Http|]Request MAX_SIZE\nutf8");
+        ctx.do_edit(EditNotification::MoveSubwordRight);
+        ctx.do_edit(EditNotification::MoveSubwordRight);
+        assert_eq!(harness.debug_render(), "parseHttpRequest MAX|_SIZE\nutf8");
+        ctx.do_edit(EditNotification::DeleteSubwordForward);
+        assert_eq!(harness.debug_render(), "parseHttpRequest MAX|\nutf8");
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::DeleteSubwordBackward);
+        assert_eq!(harness.debug_render(), "parseHttpRequest MAX\nutf|");
+        ctx.do_edit(EditNotification::MoveSubwordLeft);
+        ctx.do_edit(EditNotification::MoveSubwordLeft);
+        assert_eq!(harness.debug_render(), "parseHttpRequest MAX|\nutf");
+        ctx.do_edit(EditNotification::MoveSubwordLeft);
+        assert_eq!(harness.debug_render(), "parseHttpRequest |MAX\nutf");
+
+        // the separators are configured for each language
+        harness.set_config(json!({"subword_separators": "_-"}));
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Insert { chars: "font-size ".to_owned() });
+        ctx.do_edit(EditNotification::MoveSubwordLeft);
+        assert_eq!(harness.debug_render(), "parseHttpRequest font-|size MAX\nutf");
+    }
+
+    #[test]
+    fn expand_selection() {
+        use rpc::GestureType::*;
+        let harness = ContextHarness::new("f(one, two)\ng(three)");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 3, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 3, ty: ToggleSel });
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "f([one|], two)\ng([three|])");
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "f([one, two|])\ng[(three)|]");
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "f[(one, two)|]\n[g(three)|]");
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "f([one, two|])\ng[(three)|]");
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "f(o|ne, two)\ng(t|hree)");
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "f(o|ne, two)\ng(t|hree)");
+
+        // regions that meet are merged, and shrinking restores them
+        ctx.do_edit(EditNotification::ExpandSelection);
+        ctx.do_edit(EditNotification::ExpandSelection);
+        ctx.do_edit(EditNotification::ExpandSelection);
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "[f(one, two)\ng(three)|]");
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "f[(one, two)|]\n[g(three)|]");
+
+        // the selections are forgotten when the selection changes
+        ctx.do_edit(EditNotification::MoveLeft);
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "f|(one, two)\n|g(three)");
+    }
+
+    #[test]
+    fn text_objects() {
+        use rpc::GestureType::*;
+        use rpc::TextObjectKind::*;
+        let mut harness = ContextHarness::new("f(one, two)\ng(three)");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 3, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 3, ty: ToggleSel });
+        ctx.do_edit(EditNotification::SelectTextObject { object: Parentheses, inner: true });
+        assert_eq!(harness.debug_render(), "f([one, two|])\ng([three|])");
+        ctx.do_edit(EditNotification::DeleteTextObject { object: Parentheses, inner: false });
+        assert_eq!(harness.debug_render(), "f|\ng|");
+        ctx.do_edit(EditNotification::Yank);
+        assert_eq!(harness.debug_render(), "f(one, two)|\ng(three)|");
+
+        // a region without an enclosing pair is left alone
+        let mut harness = ContextHarness::new("f(one) two");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 3, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 8, ty: ToggleSel });
+        ctx.do_edit(EditNotification::DeleteTextObject { object: Parentheses, inner: false });
+        assert_eq!(harness.debug_render(), "f| t|wo");
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveRightAndModifySelection);
+        ctx.do_edit(EditNotification::DeleteTextObject { object: Parentheses, inner: false });
+        assert_eq!(harness.debug_render(), "f[ |]two");
+
+        // text objects in the modal input layer
+        let mut harness = ContextHarness::new("call(a, \"b c\")\n  x\n  y\nz");
+        harness.set_config(json!({"modal_editing": true}));
+        let mut ctx = harness.make_context();
+        let keys = |ctx: &mut EventContext, keys: &str| {
+            ctx.do_edit(EditNotification::Insert { chars: keys.into() });
+        };
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 10, ty: PointSelect });
+        keys(&mut ctx, "ci\"q");
+        ctx.do_edit(EditNotification::CancelOperation);
+        assert_eq!(harness.debug_render(), "call(a, \"q|\")\n  x\n  y\nz");
+        keys(&mut ctx, "da(");
+        assert_eq!(harness.debug_render(), "cal|l\n  x\n  y\nz");
+        keys(&mut ctx, "jvii");
+        assert_eq!(harness.debug_render(), "call\n[  x\n  y\n|]z");
+        keys(&mut ctx, "d");
+        assert_eq!(harness.debug_render(), "call\n|z");
+
+        // line-based objects keep visual line mode
+        let mut harness = ContextHarness::new("call\n  x\n  y\nz");
+        harness.set_config(json!({"modal_editing": true}));
+        let mut ctx = harness.make_context();
+        keys(&mut ctx, "jVii");
+        assert_eq!(harness.debug_render(), "call\n[  x\n  y\n|]z");
+        assert_eq!(harness.view.borrow_mut().modal_state().mode(), Mode::VisualLine);
+        keys(&mut ctx, "j");
+        assert_eq!(harness.debug_render(), "call\n[  x\n  y\nz|]");
+        keys(&mut ctx, "iw");
+        assert_eq!(harness.debug_render(), "call\n  x\n  y\n[z|]");
+        assert_eq!(harness.view.borrow_mut().modal_state().mode(), Mode::Visual);
+    }
 }
@@ -0,0 +1,224 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Expansion of selection regions to the syntactic units around them.
+
+use std::cmp::max;
+
+use syntect::parsing::Scope;
+use xi_rope::{Interval, LinesMetric, Rope};
+
+use brackets::BracketMatcher;
+use fold::{end_of_line, line_indent};
+use layers::Layers;
+use selection::SelRegion;
+use word_boundaries::WordCursor;
+
+/// Returns the smallest unit enclosing `region` that is larger than it,
+/// among the word, the scopes of the syntax layers and the contents of the
+/// strings among them, the contents of the brackets and the brackets, the
+/// lines, the indentation block and the document. The whole document is
+/// returned as it is.
+pub fn expand_region(
+    text: &Rope,
+    layers: &Layers,
+    matcher: &BracketMatcher,
+    region: SelRegion,
+) -> SelRegion {
+    let iv = Interval::new(region.min(), region.max());
+    let mut candidates = Vec::new();
+
+    let (start, end) = WordCursor::new(text, iv.start()).select_word();
+    candidates.push(Interval::new(start, end));
+
+    let string = Scope::new("string").unwrap();
+    for (extent, scope) in layers.enclosing_scopes(iv) {
+        candidates.push(extent);
+        if string.is_prefix_of(scope) {
+            candidates.extend(string_contents(text, extent));
+        }
+    }
+
+    if !matcher.is_empty() {
+        let in_string = layers.is_string_or_comment(iv.start());
+        candidates.extend(enclosing_brackets(matcher, iv, in_string));
+        if in_string {
+            candidates.extend(enclosing_brackets(matcher, iv, false));
+        }
+    }
+
+    let first_line = text.line_of_offset(iv.start());
+    let last_line = text.line_of_offset(iv.end());
+    candidates.push(Interval::new(text.offset_of_line(first_line), end_of_line(text, last_line)));
+    candidates.extend(indent_block(text, iv));
+    candidates.push(Interval::new(0, text.len()));
+
+    candidates
+        .into_iter()
+        .filter(|c| c.start() <= iv.start() && c.end() >= iv.end() && *c != iv)
+        .min_by_key(Interval::size)
+        .map_or(region, |c| SelRegion::new(c.start(), c.end()))
+}
+
+/// Returns the contents and the whole of the innermost pair of brackets
+/// whose contents enclose `iv`.
+fn enclosing_brackets(matcher: &BracketMatcher, iv: Interval, in_string: bool) -> Vec<Interval> {
+    let mut pair = matcher.enclosing_pair(iv.start(), in_string);
+    while let Some(p) = pair {
+        if p.close.start() >= iv.end() {
+            let contents = Interval::new(p.open.end(), p.close.start());
+            return vec![contents, Interval::new(p.open.start(), p.close.end())];
+        }
+        pair = matcher.enclosing_pair(p.open.start(), in_string);
+    }
+    Vec::new()
+}
+
+/// Returns the text of a string between its quotes.
+fn string_contents(text: &Rope, extent: Interval) -> Option<Interval> {
+    let s = text.slice_to_cow(extent);
+    let quote = s.chars().next()?;
+    let is_quote = quote == '"' || quote == '\'' || quote == '`';
+    if is_quote && s.len() > 1 && s.ends_with(quote) {
+        Some(Interval::new(extent.start() + 1, extent.end() - 1))
+    } else {
+        None
+    }
+}
+
+/// Returns the block of lines around `iv` that are indented at least as much
+/// as the least indented of its lines, without blank lines at its ends, or,
+/// if that is no larger than `iv`, the block at the next lower indentation.
+pub fn indent_block(text: &Rope, iv: Interval) -> Option<Interval> {
+    let n_lines = text.measure::<LinesMetric>() + 1;
+    let first = text.line_of_offset(iv.start());
+    let last = text.line_of_offset(iv.end());
+    let mut level = (first..last + 1).filter_map(|line| line_indent(text, line)).min()?;
+    loop {
+        let in_block = |line| line_indent(text, line).map_or(true, |indent| indent >= level);
+        let (mut start, mut end) = (first, last);
+        while start > 0 && in_block(start - 1) {
+            start -= 1;
+        }
+        while end + 1 < n_lines && in_block(end + 1) {
+            end += 1;
+        }
+        while start < first && line_indent(text, start).is_none() {
+            start += 1;
+        }
+        while end > last && line_indent(text, end).is_none() {
+            end -= 1;
+        }
+        let block = Interval::new(text.offset_of_line(start), end_of_line(text, end));
+        if block.start() < iv.start() || block.end() > iv.end() {
+            return Some(block);
+        }
+        let above = (0..start).rev().filter_map(|line| line_indent(text, line)).next();
+        let below = (end + 1..n_lines).filter_map(|line| line_indent(text, line)).next();
+        level = match (above, below) {
+            (Some(above), Some(below)) => max(above, below),
+            (Some(indent), None) | (None, Some(indent)) => indent,
+            (None, None) => return None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugins::PluginPid;
+    use styles::ThemeStyleMap;
+    use xi_rope::spans::SpansBuilder;
+    use xi_rope::RopeDelta;
+
+    fn pairs() -> Vec<(String, String)> {
+        vec![("(".to_owned(), ")".to_owned()), ("{".to_owned(), "}".to_owned())]
+    }
+
+    /// Expands the region repeatedly, returning the text of each expansion.
+    fn expansions(text: &str, layers: &Layers, start: usize, end: usize) -> Vec<String> {
+        let rope = Rope::from(text);
+        let pairs = pairs();
+        let matcher = BracketMatcher::new(&rope, &pairs, layers);
+        let mut region = SelRegion::new(start, end);
+        let mut result = Vec::new();
+        loop {
+            let expanded = expand_region(&rope, layers, &matcher, region);
+            if expanded == region {
+                return result;
+            }
+            result.push(text[expanded.min()..expanded.max()].to_owned());
+            region = expanded;
+        }
+    }
+
+    #[test]
+    fn expand_through_brackets_and_blocks() {
+        let layers = Layers::default();
+        let text = "fn f() {\n    if x {\n        g(ab, c);\n        h();\n    }\n}\n";
+        assert_eq!(
+            expansions(text, &layers, 31, 31),
+            vec![
+                "ab",
+                "ab, c",
+                "(ab, c)",
+                "        g(ab, c);",
+                "        g(ab, c);\n        h();",
+                "\n        g(ab, c);\n        h();\n    ",
+                "{\n        g(ab, c);\n        h();\n    }",
+                "    if x {\n        g(ab, c);\n        h();\n    }",
+                "\n    if x {\n        g(ab, c);\n        h();\n    }\n",
+                "{\n    if x {\n        g(ab, c);\n        h();\n    }\n}",
+                "fn f() {\n    if x {\n        g(ab, c);\n        h();\n    }\n}",
+                text,
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_through_scopes() {
+        let text = "f(\"a (b) c\", d)";
+        let style_map = ThemeStyleMap::new(None);
+        let mut layers = Layers::default();
+        let pid = PluginPid(1);
+        let scopes = vec![
+            vec!["source.rust".to_owned()],
+            vec!["source.rust".to_owned(), "string.quoted.double".to_owned()],
+        ];
+        layers.add_scopes(pid, scopes, &style_map);
+        let mut sb = SpansBuilder::new(text.len());
+        sb.add_span(Interval::new(0, 2), 0);
+        sb.add_span(Interval::new(2, 11), 1);
+        sb.add_span(Interval::new(11, text.len()), 0);
+        layers.update_all(&RopeDelta::simple_edit(Interval::new(0, 0), Rope::from(text), 0));
+        layers.update_layer(pid, Interval::new(0, text.len()), sb.build());
+
+        assert_eq!(
+            expansions(text, &layers, 6, 6),
+            vec!["b", "(b)", "a (b) c", "\"a (b) c\"", "\"a (b) c\", d", "(\"a (b) c\", d)", text]
+        );
+    }
+
+    #[test]
+    fn indent_blocks() {
+        let text = Rope::from("a\n  b\n\n  c\n    d\ne\n");
+        let block = |start, end| {
+            indent_block(&text, Interval::new(start, end))
+                .map(|iv| text.slice_to_cow(iv).into_owned())
+        };
+        assert_eq!(block(4, 4), Some("  b\n\n  c\n    d".to_owned()));
+        assert_eq!(block(2, 16), Some("a\n  b\n\n  c\n    d\ne".to_owned()));
+        assert_eq!(block(0, 18), None);
+    }
+}
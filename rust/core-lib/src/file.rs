@@ -14,10 +14,13 @@
 
 //! Interactions with the file system.
 
+use std::cmp::min;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fmt;
-use std::fs::{self, File};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::hash::Hasher;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str;
@@ -34,6 +37,30 @@ use tabs::OPEN_FILE_EVENT_TOKEN;
 use watcher::FileWatcher;
 
 const UTF8_BOM: &str = "\u{feff}";
+const UTF16_LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16_BE_BOM: &[u8] = &[0xfe, 0xff];
+
+/// The number of bytes inspected when guessing whether a file without a
+/// byte order mark is UTF-16.
+const UTF16_SNIFF_LEN: usize = 4096;
+
+/// The number of names tried when creating the temporary file for a save.
+const MAX_TEMP_FILE_ATTEMPTS: usize = 100;
+
+/// The characters for bytes 0x80 to 0x9F in Windows-1252; the rest of the
+/// code page is identical to Latin-1. Bytes that are undefined in
+/// Windows-1252 are `None`.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const WINDOWS_1252_HIGH: [Option<char>; 32] = [
+    Some('\u{20ac}'), None, Some('\u{201a}'), Some('\u{0192}'),
+    Some('\u{201e}'), Some('\u{2026}'), Some('\u{2020}'), Some('\u{2021}'),
+    Some('\u{02c6}'), Some('\u{2030}'), Some('\u{0160}'), Some('\u{2039}'),
+    Some('\u{0152}'), None, Some('\u{017d}'), None,
+    None, Some('\u{2018}'), Some('\u{2019}'), Some('\u{201c}'),
+    Some('\u{201d}'), Some('\u{2022}'), Some('\u{2013}'), Some('\u{2014}'),
+    Some('\u{02dc}'), Some('\u{2122}'), Some('\u{0161}'), Some('\u{203a}'),
+    Some('\u{0153}'), None, Some('\u{017e}'), Some('\u{0178}'),
+];
 
 /// Tracks all state related to open files.
 pub struct FileManager {
@@ -49,6 +76,8 @@ pub struct FileInfo {
     pub encoding: CharacterEncoding,
     pub path: PathBuf,
     pub mod_time: Option<SystemTime>,
+    /// A hash of the file's contents when it was last loaded or saved.
+    pub content_hash: u64,
     pub has_changed: bool,
 }
 
@@ -56,12 +85,28 @@ pub enum FileError {
     Io(io::Error, PathBuf),
     UnknownEncoding(PathBuf),
     HasChanged(PathBuf),
+    NotOpen,
+    SaveFailed(io::Error, PathBuf),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum CharacterEncoding {
+    #[serde(rename = "utf-8")]
     Utf8,
+    #[serde(rename = "utf-8-bom")]
     Utf8WithBom,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16le-bom")]
+    Utf16LeWithBom,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "utf-16be-bom")]
+    Utf16BeWithBom,
+    #[serde(rename = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "windows-1252")]
+    Windows1252,
 }
 
 impl FileManager {
@@ -88,25 +133,36 @@ impl FileManager {
         self.open_files.get(path).cloned()
     }
 
-    /// Returns `true` if this file is open and has changed on disk.
-    /// This state is stashed.
+    /// Returns `true` if this file is open and its contents have changed on
+    /// disk since it was last loaded or saved. If only the modification time
+    /// has changed, as when a file is touched, the file is not considered
+    /// changed. This state is stashed.
     pub fn check_file(&mut self, path: &Path, id: BufferId) -> bool {
         if let Some(info) = self.file_info.get_mut(&id) {
             let mod_t = get_mod_time(path);
             if mod_t != info.mod_time {
-                info.has_changed = true
+                info.has_changed = get_content_hash(path) != Some(info.content_hash);
+                if !info.has_changed {
+                    info.mod_time = mod_t;
+                }
             }
             return info.has_changed;
         }
         false
     }
 
+    /// Loads the file at `path` into the buffer `id`. If the buffer already
+    /// has this file open, as when it is reloaded after changing on disk,
+    /// the file is decoded with the encoding it was opened with rather than
+    /// a new guess, and the file is no longer considered changed on disk.
     pub fn open(&mut self, path: &Path, id: BufferId) -> Result<Rope, FileError> {
         if !path.exists() {
             let _ = File::create(path).map_err(|e| FileError::Io(e, path.to_owned()))?;
         }
 
-        let (rope, info) = try_load_file(path)?;
+        let encoding =
+            self.file_info.get(&id).filter(|info| info.path == path).map(|info| info.encoding);
+        let (rope, info) = try_load_file(path, encoding)?;
 
         self.open_files.insert(path.to_owned(), id);
         if self.file_info.insert(id, info).is_none() {
@@ -124,22 +180,59 @@ impl FileManager {
         }
     }
 
-    pub fn save(&mut self, path: &Path, text: &Rope, id: BufferId) -> Result<(), FileError> {
+    /// Reloads the file backing the buffer `id`, decoding it with the given
+    /// encoding, which is used for subsequent saves.
+    pub fn reopen(&mut self, id: BufferId, encoding: CharacterEncoding) -> Result<Rope, FileError> {
+        let path = match self.file_info.get(&id) {
+            Some(info) => info.path.clone(),
+            None => return Err(FileError::NotOpen),
+        };
+        let (rope, info) = try_load_file(&path, Some(encoding))?;
+        self.file_info.insert(id, info);
+        Ok(rope)
+    }
+
+    /// Saves the buffer to `path`. If `encoding` is `None`, the encoding the
+    /// file was opened with is used; new files are saved as UTF-8.
+    /// Files with other hardlinks are overwritten in place, rather than
+    /// replaced, if `hardlinks_in_place` is `true`.
+    ///
+    /// If the file has changed on disk since it was loaded, it is only
+    /// overwritten if `force` is `true`.
+    pub fn save(
+        &mut self,
+        path: &Path,
+        text: &Rope,
+        id: BufferId,
+        encoding: Option<CharacterEncoding>,
+        hardlinks_in_place: bool,
+        force: bool,
+    ) -> Result<(), FileError> {
         let is_existing = self.file_info.contains_key(&id);
         if is_existing {
-            self.save_existing(path, text, id)
+            let encoding = encoding.unwrap_or(self.file_info[&id].encoding);
+            self.save_existing(path, text, id, encoding, hardlinks_in_place, force)
         } else {
-            self.save_new(path, text, id)
+            let encoding = encoding.unwrap_or(CharacterEncoding::Utf8);
+            self.save_new(path, text, id, encoding, hardlinks_in_place)
         }
     }
 
-    fn save_new(&mut self, path: &Path, text: &Rope, id: BufferId) -> Result<(), FileError> {
-        try_save(path, text, CharacterEncoding::Utf8)
-            .map_err(|e| FileError::Io(e, path.to_owned()))?;
+    fn save_new(
+        &mut self,
+        path: &Path,
+        text: &Rope,
+        id: BufferId,
+        encoding: CharacterEncoding,
+        hardlinks_in_place: bool,
+    ) -> Result<(), FileError> {
+        let content_hash = try_save(path, text, encoding, hardlinks_in_place)
+            .map_err(|e| FileError::SaveFailed(e, path.to_owned()))?;
         let info = FileInfo {
-            encoding: CharacterEncoding::Utf8,
+            encoding,
             path: path.to_owned(),
             mod_time: get_mod_time(path),
+            content_hash,
             has_changed: false,
         };
         self.open_files.insert(path.to_owned(), id);
@@ -149,29 +242,45 @@ impl FileManager {
         Ok(())
     }
 
-    fn save_existing(&mut self, path: &Path, text: &Rope, id: BufferId) -> Result<(), FileError> {
+    fn save_existing(
+        &mut self,
+        path: &Path,
+        text: &Rope,
+        id: BufferId,
+        encoding: CharacterEncoding,
+        hardlinks_in_place: bool,
+        force: bool,
+    ) -> Result<(), FileError> {
         let prev_path = self.file_info[&id].path.clone();
         if prev_path != path {
-            self.save_new(path, text, id)?;
+            self.save_new(path, text, id, encoding, hardlinks_in_place)?;
             self.open_files.remove(&prev_path);
             #[cfg(feature = "notify")]
             self.watcher.unwatch(&prev_path, OPEN_FILE_EVENT_TOKEN);
-        } else if self.file_info[&id].has_changed {
+        } else if self.check_file(path, id) && !force {
             return Err(FileError::HasChanged(path.to_owned()));
         } else {
-            let encoding = self.file_info[&id].encoding;
-            try_save(path, text, encoding).map_err(|e| FileError::Io(e, path.to_owned()))?;
-            self.file_info.get_mut(&id).unwrap().mod_time = get_mod_time(path);
+            let content_hash = try_save(path, text, encoding, hardlinks_in_place)
+                .map_err(|e| FileError::SaveFailed(e, path.to_owned()))?;
+            let info = self.file_info.get_mut(&id).unwrap();
+            info.mod_time = get_mod_time(path);
+            info.content_hash = content_hash;
+            info.has_changed = false;
+            info.encoding = encoding;
         }
         Ok(())
     }
 }
 
-fn try_load_file<P>(path: P) -> Result<(Rope, FileInfo), FileError>
+/// Loads the file at `path`, decoding it with `encoding`, or with a guessed
+/// encoding if `encoding` is `None`.
+fn try_load_file<P>(
+    path: P,
+    encoding: Option<CharacterEncoding>,
+) -> Result<(Rope, FileInfo), FileError>
 where
     P: AsRef<Path>,
 {
-    // TODO: support for non-utf8
     // it's arguable that the rope crate should have file loading functionality
     let mut f =
         File::open(path.as_ref()).map_err(|e| FileError::Io(e, path.as_ref().to_owned()))?;
@@ -180,47 +289,165 @@ where
     let mut bytes = Vec::new();
     f.read_to_end(&mut bytes).map_err(|e| FileError::Io(e, path.as_ref().to_owned()))?;
 
-    let encoding = CharacterEncoding::guess(&bytes);
+    let content_hash = hash_bytes(&bytes);
+    let encoding = encoding.unwrap_or_else(|| CharacterEncoding::guess(&bytes));
     let rope = try_decode(bytes, encoding, path.as_ref())?;
-    let info = FileInfo { encoding, mod_time, path: path.as_ref().to_owned(), has_changed: false };
+    let info = FileInfo {
+        encoding,
+        mod_time,
+        path: path.as_ref().to_owned(),
+        content_hash,
+        has_changed: false,
+    };
     Ok((rope, info))
 }
 
-fn try_save(path: &Path, text: &Rope, encoding: CharacterEncoding) -> io::Result<()> {
-    let tmp_extension = path.extension().map_or_else(
-        || OsString::from("swp"),
-        |ext| {
-            let mut ext = ext.to_os_string();
-            ext.push(".swp");
-            ext
-        },
-    );
-    let tmp_path = &path.with_extension(tmp_extension);
-
-    let mut f = File::create(tmp_path)?;
-    match encoding {
-        CharacterEncoding::Utf8WithBom => f.write_all(UTF8_BOM.as_bytes())?,
-        CharacterEncoding::Utf8 => (),
+/// Saves `text` to `path`, returning the hash of the bytes written.
+///
+/// The text is written to a temporary file in the same directory as the
+/// target, which is then renamed over it, so that a failed save leaves the
+/// original file untouched. The original file's permissions are preserved,
+/// and if `path` is a symlink, the file it points to is replaced.
+///
+/// Renaming replaces the directory entry, which would break any hardlinks
+/// to the original file; if `hardlinks_in_place` is `true`, files with more
+/// than one link are instead overwritten in place.
+fn try_save(
+    path: &Path,
+    text: &Rope,
+    encoding: CharacterEncoding,
+    hardlinks_in_place: bool,
+) -> io::Result<u64> {
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        // the file doesn't exist yet
+        Err(_) => path.to_owned(),
+    };
+    let metadata = fs::metadata(&path).ok();
+
+    if hardlinks_in_place && metadata.as_ref().map(is_hardlinked).unwrap_or(false) {
+        // the text is encoded before the file is truncated, so that text
+        // that can't be encoded leaves the file as it was
+        let mut bytes = Vec::new();
+        let hash = write_text(&mut bytes, text, encoding)?;
+        let mut f = OpenOptions::new().write(true).truncate(true).open(&path)?;
+        f.write_all(&bytes)?;
+        return f.sync_all().map(|_| hash);
     }
 
+    let (tmp_path, mut f) = create_temp_file(&path)?;
+    let result = write_text(&mut f, text, encoding).and_then(|hash| {
+        f.sync_all()?;
+        if let Some(ref metadata) = metadata {
+            fs::set_permissions(&tmp_path, metadata.permissions())?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        Ok(hash)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Writes `text` to `f` in the given encoding, preceded by its byte order
+/// mark, returning the hash of the bytes written.
+fn write_text<W: Write>(f: &mut W, text: &Rope, encoding: CharacterEncoding) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    f.write_all(encoding.bom())?;
+    hasher.write(encoding.bom());
+
+    let mut buf = Vec::new();
     for chunk in text.iter_chunks(..text.len()) {
-        f.write_all(chunk.as_bytes())?;
+        let bytes = match encoding {
+            CharacterEncoding::Utf8 | CharacterEncoding::Utf8WithBom => chunk.as_bytes(),
+            _ => {
+                buf.clear();
+                encoding.encode(chunk, &mut buf)?;
+                &buf
+            }
+        };
+        f.write_all(bytes)?;
+        hasher.write(bytes);
+    }
+    Ok(hasher.finish())
+}
+
+/// Creates a new, hidden file next to `path` to write a save into.
+fn create_temp_file(path: &Path) -> io::Result<(PathBuf, File)> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path does not name a file"))?;
+    for i in 0..MAX_TEMP_FILE_ATTEMPTS {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(".xi-save-{}", i));
+        let tmp_path = path.with_file_name(tmp_name);
+        match OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+            Ok(f) => return Ok((tmp_path, f)),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
     }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "Could not create a temporary file"))
+}
 
-    fs::rename(tmp_path, path)?;
+#[cfg(unix)]
+fn is_hardlinked(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
 
-    Ok(())
+#[cfg(not(unix))]
+fn is_hardlinked(_metadata: &Metadata) -> bool {
+    false
 }
 
 fn try_decode(bytes: Vec<u8>, encoding: CharacterEncoding, path: &Path) -> Result<Rope, FileError> {
+    let bom_len = if bytes.starts_with(encoding.bom()) { encoding.bom().len() } else { 0 };
     match encoding {
-        CharacterEncoding::Utf8 => Ok(Rope::from(
-            str::from_utf8(&bytes).map_err(|_e| FileError::UnknownEncoding(path.to_owned()))?,
+        CharacterEncoding::Utf8 | CharacterEncoding::Utf8WithBom => Ok(Rope::from(
+            str::from_utf8(&bytes[bom_len..])
+                .map_err(|_e| FileError::UnknownEncoding(path.to_owned()))?,
         )),
-        CharacterEncoding::Utf8WithBom => {
-            let s = String::from_utf8(bytes)
+        CharacterEncoding::Utf16Le
+        | CharacterEncoding::Utf16LeWithBom
+        | CharacterEncoding::Utf16Be
+        | CharacterEncoding::Utf16BeWithBom => {
+            let bytes = &bytes[bom_len..];
+            if bytes.len() % 2 != 0 {
+                return Err(FileError::UnknownEncoding(path.to_owned()));
+            }
+            let is_le = encoding == CharacterEncoding::Utf16Le
+                || encoding == CharacterEncoding::Utf16LeWithBom;
+            let units = bytes
+                .chunks(2)
+                .map(|b| {
+                    if is_le {
+                        u16::from(b[0]) | u16::from(b[1]) << 8
+                    } else {
+                        u16::from(b[0]) << 8 | u16::from(b[1])
+                    }
+                })
+                .collect::<Vec<_>>();
+            let s = String::from_utf16(&units)
                 .map_err(|_e| FileError::UnknownEncoding(path.to_owned()))?;
-            Ok(Rope::from(&s[UTF8_BOM.len()..]))
+            Ok(Rope::from(s))
+        }
+        CharacterEncoding::Latin1 => {
+            Ok(Rope::from(bytes.iter().map(|&b| char::from(b)).collect::<String>()))
+        }
+        CharacterEncoding::Windows1252 => {
+            let s = bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize]
+                        .ok_or_else(|| FileError::UnknownEncoding(path.to_owned())),
+                    _ => Ok(char::from(b)),
+                })
+                .collect::<Result<String, _>>()?;
+            Ok(Rope::from(s))
         }
     }
 }
@@ -228,11 +455,113 @@ fn try_decode(bytes: Vec<u8>, encoding: CharacterEncoding, path: &Path) -> Resul
 impl CharacterEncoding {
     fn guess(s: &[u8]) -> Self {
         if s.starts_with(UTF8_BOM.as_bytes()) {
-            CharacterEncoding::Utf8WithBom
-        } else {
+            return CharacterEncoding::Utf8WithBom;
+        } else if s.starts_with(UTF16_LE_BOM) {
+            return CharacterEncoding::Utf16LeWithBom;
+        } else if s.starts_with(UTF16_BE_BOM) {
+            return CharacterEncoding::Utf16BeWithBom;
+        }
+
+        // Without a BOM, UTF-16 is recognized by the zero high bytes of ASCII
+        // characters, which are rare elsewhere in text files.
+        let sample = &s[..min(s.len(), UTF16_SNIFF_LEN) & !1];
+        if !sample.is_empty() {
+            let n_pairs = sample.len() / 2;
+            let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+            let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+            if odd_zeros * 2 > n_pairs && even_zeros * 10 < n_pairs {
+                return CharacterEncoding::Utf16Le;
+            } else if even_zeros * 2 > n_pairs && odd_zeros * 10 < n_pairs {
+                return CharacterEncoding::Utf16Be;
+            }
+        }
+
+        if str::from_utf8(s).is_ok() {
             CharacterEncoding::Utf8
+        } else if s.iter().any(|&b| b >= 0x80 && b <= 0x9f)
+            && s.iter()
+                .all(|&b| b < 0x80 || b > 0x9f || WINDOWS_1252_HIGH[(b - 0x80) as usize].is_some())
+        {
+            // Latin-1 text rarely contains C1 control characters, but
+            // Windows-1252 uses those bytes for printable characters.
+            CharacterEncoding::Windows1252
+        } else {
+            CharacterEncoding::Latin1
         }
     }
+
+    /// The byte order mark written at the start of files in this encoding.
+    fn bom(self) -> &'static [u8] {
+        match self {
+            CharacterEncoding::Utf8WithBom => UTF8_BOM.as_bytes(),
+            CharacterEncoding::Utf16LeWithBom => UTF16_LE_BOM,
+            CharacterEncoding::Utf16BeWithBom => UTF16_BE_BOM,
+            _ => &[],
+        }
+    }
+
+    /// Encodes `s`, appending the result to `buf`. Returns an error if `s`
+    /// contains characters that cannot be represented in this encoding.
+    fn encode(self, s: &str, buf: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            CharacterEncoding::Utf8 | CharacterEncoding::Utf8WithBom => {
+                buf.extend_from_slice(s.as_bytes())
+            }
+            CharacterEncoding::Utf16Le | CharacterEncoding::Utf16LeWithBom => {
+                for unit in s.encode_utf16() {
+                    buf.extend_from_slice(&[unit as u8, (unit >> 8) as u8]);
+                }
+            }
+            CharacterEncoding::Utf16Be | CharacterEncoding::Utf16BeWithBom => {
+                for unit in s.encode_utf16() {
+                    buf.extend_from_slice(&[(unit >> 8) as u8, unit as u8]);
+                }
+            }
+            CharacterEncoding::Latin1 | CharacterEncoding::Windows1252 => {
+                for c in s.chars() {
+                    buf.push(self.encode_single_byte(c).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{:?} cannot be represented in {}", c, self),
+                        )
+                    })?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_single_byte(self, c: char) -> Option<u8> {
+        if self == CharacterEncoding::Windows1252 {
+            if let Some(ix) = WINDOWS_1252_HIGH.iter().position(|&h| h == Some(c)) {
+                return Some(0x80 + ix as u8);
+            }
+            if c >= '\u{80}' && c <= '\u{9f}' {
+                return None;
+            }
+        }
+        if (c as u32) < 0x100 {
+            Some(c as u8)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for CharacterEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CharacterEncoding::Utf8 => "UTF-8",
+            CharacterEncoding::Utf8WithBom => "UTF-8 with BOM",
+            CharacterEncoding::Utf16Le => "UTF-16 LE",
+            CharacterEncoding::Utf16LeWithBom => "UTF-16 LE with BOM",
+            CharacterEncoding::Utf16Be => "UTF-16 BE",
+            CharacterEncoding::Utf16BeWithBom => "UTF-16 BE with BOM",
+            CharacterEncoding::Latin1 => "ISO-8859-1",
+            CharacterEncoding::Windows1252 => "Windows-1252",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 /// Returns the modification timestamp for the file at a given path,
@@ -244,6 +573,23 @@ where
     File::open(path).and_then(|f| f.metadata()).and_then(|meta| meta.modified()).ok()
 }
 
+/// Returns a hash of the contents of the file at a given path, if it
+/// can be read.
+fn get_content_hash<P>(path: P) -> Option<u64>
+where
+    P: AsRef<Path>,
+{
+    fs::read(path).ok().map(|bytes| hash_bytes(&bytes))
+}
+
+/// Hashes file contents. Bytes written to the hasher in several pieces hash
+/// the same as when written at once, so saves can be hashed as they are written.
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
 impl From<FileError> for RemoteError {
     fn from(src: FileError) -> RemoteError {
         //TODO: when we migrate to using the failure crate for error handling,
@@ -260,6 +606,8 @@ impl FileError {
             FileError::Io(_, _) => 5,
             FileError::UnknownEncoding(_) => 6,
             FileError::HasChanged(_) => 7,
+            FileError::NotOpen => 8,
+            FileError::SaveFailed(_, _) => 9,
         }
     }
 }
@@ -272,9 +620,176 @@ impl fmt::Display for FileError {
             FileError::HasChanged(ref p) => write!(
                 f,
                 "File has changed on disk. \
-                 Please save elsewhere and reload the file. File path: {:?}",
+                 Please save elsewhere and reload the file, \
+                 or force the save to overwrite it. File path: {:?}",
                 p
             ),
+            FileError::NotOpen => write!(f, "Buffer is not backed by a file"),
+            FileError::SaveFailed(ref e, ref p) => {
+                write!(f, "Error saving file: {}. File path: {:?}", e, p)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_fixtures::TempDir;
+
+    fn encode(encoding: CharacterEncoding, s: &str) -> Vec<u8> {
+        let mut buf = encoding.bom().to_vec();
+        encoding.encode(s, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn guess_encoding() {
+        use self::CharacterEncoding::*;
+        assert_eq!(CharacterEncoding::guess(b"plain ascii"), Utf8);
+        assert_eq!(CharacterEncoding::guess("caf\u{e9}".as_bytes()), Utf8);
+        assert_eq!(CharacterEncoding::guess(b"\xef\xbb\xbfhi"), Utf8WithBom);
+        assert_eq!(CharacterEncoding::guess(b"\xff\xfeh\x00i\x00"), Utf16LeWithBom);
+        assert_eq!(CharacterEncoding::guess(b"\xfe\xff\x00h\x00i"), Utf16BeWithBom);
+        assert_eq!(CharacterEncoding::guess(b"h\x00i\x00\n\x00"), Utf16Le);
+        assert_eq!(CharacterEncoding::guess(b"\x00h\x00i\x00\n"), Utf16Be);
+        assert_eq!(CharacterEncoding::guess(b"caf\xe9"), Latin1);
+        assert_eq!(CharacterEncoding::guess(b"\x93quoted\x94 caf\xe9"), Windows1252);
+        // 0x81 is undefined in Windows-1252
+        assert_eq!(CharacterEncoding::guess(b"\x81\x93"), Latin1);
+    }
+
+    #[test]
+    fn round_trip() {
+        use self::CharacterEncoding::*;
+        let path = Path::new("test");
+        let text = "caf\u{e9} \u{201c}\u{20ac}\u{201d}\n";
+        for &encoding in &[Utf8, Utf8WithBom, Utf16Le, Utf16LeWithBom, Utf16Be, Utf16BeWithBom] {
+            let bytes = encode(encoding, text);
+            let rope = try_decode(bytes, encoding, path).ok().unwrap();
+            assert_eq!(String::from(rope), text);
         }
+
+        let bytes = encode(Windows1252, text);
+        assert_eq!(bytes, b"caf\xe9 \x93\x80\x94\n");
+        let rope = try_decode(bytes, Windows1252, path).ok().unwrap();
+        assert_eq!(String::from(rope), text);
+
+        let mut buf = Vec::new();
+        assert!(Latin1.encode(text, &mut buf).is_err());
+        assert_eq!(encode(Latin1, "caf\u{e9}"), b"caf\xe9");
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn save_replaces_file() {
+        let temp_dir = TempDir::new("save-replace");
+        let dir = temp_dir.path();
+        let path = dir.join("file.txt");
+        try_save(&path, &Rope::from("new file"), CharacterEncoding::Utf8, true).unwrap();
+        assert_eq!(read(&path), "new file");
+        try_save(&path, &Rope::from("edited"), CharacterEncoding::Utf8, true).unwrap();
+        assert_eq!(read(&path), "edited");
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+
+        // a failed save leaves the original untouched
+        let text = Rope::from("\u{201c}unencodable\u{201d}");
+        assert!(try_save(&path, &text, CharacterEncoding::Latin1, true).is_err());
+        assert_eq!(read(&path), "edited");
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_preserves_links_and_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        let temp_dir = TempDir::new("save-links");
+        let dir = temp_dir.path();
+        let path = dir.join("file.txt");
+        fs::write(&path, "original").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let link = dir.join("link.txt");
+        symlink(&path, &link).unwrap();
+        try_save(&link, &Rope::from("via symlink"), CharacterEncoding::Utf8, true).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(read(&path), "via symlink");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+        let hardlink = dir.join("hardlink.txt");
+        fs::hard_link(&path, &hardlink).unwrap();
+        try_save(&path, &Rope::from("in place"), CharacterEncoding::Utf8, true).unwrap();
+        assert_eq!(read(&hardlink), "in place");
+        let text = Rope::from("\u{201c}unencodable\u{201d}");
+        assert!(try_save(&path, &text, CharacterEncoding::Latin1, true).is_err());
+        assert_eq!(read(&hardlink), "in place");
+        try_save(&path, &Rope::from("replaced"), CharacterEncoding::Utf8, false).unwrap();
+        assert_eq!(read(&path), "replaced");
+        assert_eq!(read(&hardlink), "in place");
+    }
+
+    #[cfg(feature = "notify")]
+    fn file_manager() -> FileManager {
+        use watcher::Notify;
+        struct NoNotify;
+        impl Notify for NoNotify {
+            fn notify(&self) {}
+        }
+        FileManager::new(FileWatcher::new(NoNotify))
+    }
+
+    #[cfg(not(feature = "notify"))]
+    fn file_manager() -> FileManager {
+        FileManager::new()
+    }
+
+    #[test]
+    fn detect_changes() {
+        let temp_dir = TempDir::new("changes");
+        let path = temp_dir.path().join("file.txt");
+        let id = BufferId(1);
+        let mut manager = file_manager();
+        manager.save(&path, &Rope::from("saved"), id, None, true, false).ok().unwrap();
+        assert_eq!(get_content_hash(&path), Some(manager.get_info(id).unwrap().content_hash));
+
+        // rewriting the same contents is not a change
+        fs::write(&path, "saved").unwrap();
+        assert!(!manager.check_file(&path, id));
+
+        fs::write(&path, "changed elsewhere").unwrap();
+        assert!(manager.check_file(&path, id));
+        match manager.save(&path, &Rope::from("edited"), id, None, true, false) {
+            Err(FileError::HasChanged(_)) => (),
+            _ => panic!("save should not overwrite a changed file"),
+        }
+        assert_eq!(read(&path), "changed elsewhere");
+
+        manager.save(&path, &Rope::from("edited"), id, None, true, true).ok().unwrap();
+        assert_eq!(read(&path), "edited");
+        assert!(!manager.check_file(&path, id));
+        manager.close(id);
+    }
+
+    #[test]
+    fn reload_keeps_encoding() {
+        let temp_dir = TempDir::new("reload");
+        let path = temp_dir.path().join("file.txt");
+        let id = BufferId(1);
+        let mut manager = file_manager();
+        fs::write(&path, b"caf\xc3\xa9").unwrap();
+        manager.open(&path, id).ok().unwrap();
+        let rope = manager.reopen(id, CharacterEncoding::Latin1).ok().unwrap();
+        assert_eq!(String::from(rope), "caf\u{c3}\u{a9}");
+
+        // the contents would be guessed to be UTF-8
+        fs::write(&path, b"caf\xc3\xa9!").unwrap();
+        let rope = manager.open(&path, id).ok().unwrap();
+        assert_eq!(String::from(rope), "caf\u{c3}\u{a9}!");
+        assert_eq!(manager.get_info(id).unwrap().encoding, CharacterEncoding::Latin1);
+        manager.close(id);
     }
 }
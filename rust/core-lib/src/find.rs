@@ -17,6 +17,7 @@
 use std::cmp::{max, min};
 
 use regex::{Regex, RegexBuilder};
+use rpc::FindQuery;
 use selection::{InsertDrift, SelRegion, Selection};
 use view::View;
 use word_boundaries::WordCursor;
@@ -100,6 +101,17 @@ impl Find {
         self.hls_dirty
     }
 
+    /// Returns a query that recreates the active search, without its `id`.
+    pub fn query(&self) -> Option<FindQuery> {
+        self.search_string.as_ref().map(|chars| FindQuery {
+            id: None,
+            chars: chars.clone(),
+            case_sensitive: self.case_matching == CaseMatching::Exact,
+            regex: self.regex.is_some(),
+            whole_words: self.whole_words,
+        })
+    }
+
     pub fn find_status(&self, view: &View, text: &Rope, matches_only: bool) -> FindStatus {
         if matches_only {
             FindStatus {
@@ -389,6 +401,43 @@ impl Find {
         }
     }
 
+    /// Returns `true` if `region` is exactly one of this query's occurrences.
+    pub fn is_occurrence(&self, region: &SelRegion) -> bool {
+        self.occurrences
+            .regions_in_range(region.min(), region.max())
+            .iter()
+            .any(|o| o.min() == region.min() && o.max() == region.max())
+    }
+
+    /// Computes the replacement for `region`, an occurrence of this query. If
+    /// the query is a regular expression, references to capture groups (`$1`,
+    /// `${name}`) in `replacement` are expanded with the text they captured.
+    pub fn expand_replacement(&self, text: &Rope, region: &SelRegion, replacement: &str) -> String {
+        let regex = match self.regex.as_ref() {
+            Some(regex) => regex,
+            None => return replacement.to_owned(),
+        };
+        // match against the whole lines of the occurrence, so that anchors
+        // and word boundaries see the text around it, without copying the
+        // rest of the text for each occurrence of a multiline regex
+        let start = text.offset_of_line(text.line_of_offset(region.min()));
+        let last_line = text.line_of_offset(region.max());
+        let end = if last_line < text.measure::<LinesMetric>() {
+            text.offset_of_line(last_line + 1)
+        } else {
+            text.len()
+        };
+        let haystack = text.slice_to_cow(start..end);
+        match regex.captures_at(&haystack, region.min() - start) {
+            Some(captures) => {
+                let mut expanded = String::new();
+                captures.expand(replacement, &mut expanded);
+                expanded
+            }
+            None => replacement.to_owned(),
+        }
+    }
+
     /// Checks if the start and end of a match is matching whole words.
     fn is_matching_whole_words(&self, text: &Rope, start: usize, end: usize) -> bool {
         let mut word_end_cursor = WordCursor::new(text, end - 1);
@@ -409,3 +458,49 @@ impl Find {
         true
     }
 }
+
+/// Applies the casing of `matched` to `replacement`: if `matched` is all
+/// lowercase or all uppercase, so is the result; if only its first letter is
+/// uppercase, the first letter of the result is uppercased. Otherwise the
+/// replacement is returned unchanged.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let mut cased = matched.chars().filter(|c| c.is_lowercase() || c.is_uppercase());
+    let first_upper = match cased.next() {
+        Some(c) => c.is_uppercase(),
+        None => return replacement.to_owned(),
+    };
+    let (mut rest_upper, mut rest_lower) = (false, false);
+    for c in cased {
+        rest_upper |= c.is_uppercase();
+        rest_lower |= c.is_lowercase();
+    }
+
+    match (first_upper, rest_upper, rest_lower) {
+        (false, false, _) => replacement.to_lowercase(),
+        (true, true, false) => replacement.to_uppercase(),
+        (true, false, _) => {
+            let mut chars = replacement.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+        _ => replacement.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preserve_case_mapping() {
+        assert_eq!(preserve_case("foo", "Bar"), "bar");
+        assert_eq!(preserve_case("FOO", "bar"), "BAR");
+        assert_eq!(preserve_case("Foo", "bar baz"), "Bar baz");
+        assert_eq!(preserve_case("F", "bar"), "Bar");
+        assert_eq!(preserve_case("fOO", "bar"), "bar");
+        assert_eq!(preserve_case("FoO", "bar"), "bar");
+        assert_eq!(preserve_case("123", "Bar"), "Bar");
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Folded (hidden) regions of a view.
+
+use std::cmp::{max, min};
+use std::slice;
+
+use xi_rope::{Interval, LinesMetric, Rope, RopeDelta, Transformer};
+
+use layers::Layers;
+
+/// The set of folded regions in a view. A fold hides the text in its
+/// interval; the line containing the start of a fold is joined with the
+/// line containing its end, and a placeholder is shown in between.
+#[derive(Default, Debug, Clone)]
+pub struct Folds {
+    // Invariant: regions are non-empty, sorted, and do not touch or overlap.
+    regions: Vec<Interval>,
+}
+
+impl Folds {
+    pub fn new() -> Folds {
+        Folds::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<Interval> {
+        self.regions.iter()
+    }
+
+    /// Removes all folds.
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Adds a fold. Any existing folds that overlap or touch the new one
+    /// are merged into it.
+    pub fn add(&mut self, iv: Interval) {
+        if iv.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = iv.start_end();
+        let first =
+            self.regions.iter().position(|r| r.end() >= start).unwrap_or(self.regions.len());
+        let mut last = first;
+        while last < self.regions.len() && self.regions[last].start() <= end {
+            start = min(start, self.regions[last].start());
+            end = max(end, self.regions[last].end());
+            last += 1;
+        }
+        self.regions.splice(first..last, Some(Interval::new(start, end)));
+    }
+
+    /// Removes all folds that intersect the closed range `start..=end`,
+    /// returning `true` if any were removed.
+    pub fn remove_range(&mut self, start: usize, end: usize) -> bool {
+        let len = self.regions.len();
+        self.regions.retain(|r| r.end() < start || r.start() > end);
+        self.regions.len() != len
+    }
+
+    /// Returns `true` if any fold intersects the closed range `start..=end`.
+    pub fn intersects(&self, start: usize, end: usize) -> bool {
+        self.regions.iter().any(|r| r.end() >= start && r.start() <= end)
+    }
+
+    /// Returns the fold strictly containing `offset`, if any. Offsets at
+    /// the boundaries of a fold are visible, and are not considered contained.
+    pub fn containing(&self, offset: usize) -> Option<Interval> {
+        self.regions.iter().find(|r| r.start() < offset && offset < r.end()).cloned()
+    }
+
+    /// Returns the first fold starting in the half-open range `start..end`.
+    pub fn first_starting_in(&self, start: usize, end: usize) -> Option<Interval> {
+        self.regions.iter().find(|r| r.start() >= start && r.start() < end).cloned()
+    }
+
+    /// Updates the folds after an edit. Text inserted at either boundary of
+    /// a fold stays visible; folds whose contents were deleted are removed.
+    pub fn apply_delta(&mut self, delta: &RopeDelta) {
+        let mut transformer = Transformer::new(delta);
+        let regions = self
+            .regions
+            .iter()
+            .map(|r| {
+                Interval::new(
+                    transformer.transform(r.start(), true),
+                    transformer.transform(r.end(), false),
+                )
+            })
+            .filter(|r| !r.is_empty())
+            .collect::<Vec<_>>();
+        self.regions = regions;
+    }
+}
+
+/// Computes the region hidden by folding the indentation block that starts
+/// at `line`: every following line indented more deeply than `line` (blank
+/// lines included, save trailing ones). The fold runs from the end of `line`
+/// to the end of the last line of the block, so that the line closing the
+/// block remains visible.
+pub fn indent_fold_for_line(text: &Rope, line: usize) -> Option<Interval> {
+    let n_lines = text.measure::<LinesMetric>() + 1;
+    let base = line_indent(text, line)?;
+    let mut last = line;
+    for next in line + 1..n_lines {
+        match line_indent(text, next) {
+            None => continue,
+            Some(indent) if indent > base => last = next,
+            Some(_) => break,
+        }
+    }
+    if last == line {
+        return None;
+    }
+    Some(Interval::new(end_of_line(text, line), end_of_line(text, last)))
+}
+
+/// Computes the region hidden by folding the longest syntax scope that
+/// starts on `line`: the lines after `line` up to, but not including, the
+/// line on which the scope ends. Like an indentation fold, it runs from the
+/// end of `line`, so that the line closing the scope remains visible.
+pub fn scope_fold_for_line(text: &Rope, layers: &Layers, line: usize) -> Option<Interval> {
+    let line_iv = Interval::new(text.offset_of_line(line), end_of_line(text, line));
+    let last = layers
+        .scopes_starting_in(line_iv)
+        .iter()
+        .map(|extent| text.line_of_offset(extent.end() - 1))
+        .max()?;
+    if last <= line + 1 {
+        return None;
+    }
+    Some(Interval::new(end_of_line(text, line), end_of_line(text, last - 1)))
+}
+
+/// Returns the width of the leading whitespace of the line, or `None` if
+/// the line is blank.
+pub fn line_indent(text: &Rope, line: usize) -> Option<usize> {
+    let start = text.offset_of_line(line);
+    let line_str = text.slice_to_cow(start..end_of_line(text, line));
+    let indent = line_str.bytes().take_while(|&b| b == b' ' || b == b'\t').count();
+    if indent == line_str.len() {
+        None
+    } else {
+        Some(indent)
+    }
+}
+
+/// Returns the offset of the end of the line, before any line ending.
+pub fn end_of_line(text: &Rope, line: usize) -> usize {
+    let start = text.offset_of_line(line);
+    let end = text.offset_of_line(line + 1);
+    let line_str = text.slice_to_cow(start..end);
+    let eol_len = line_str.bytes().rev().take_while(|&b| b == b'\n' || b == b'\r').count();
+    start + line_str.len() - eol_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugins::PluginPid;
+    use styles::ThemeStyleMap;
+    use xi_rope::spans::SpansBuilder;
+    use xi_rope::Delta;
+
+    fn regions(folds: &Folds) -> Vec<(usize, usize)> {
+        folds.iter().map(|r| r.start_end()).collect()
+    }
+
+    #[test]
+    fn add_merges() {
+        let mut folds = Folds::new();
+        folds.add(Interval::new(10, 20));
+        folds.add(Interval::new(30, 40));
+        folds.add(Interval::new(0, 5));
+        assert_eq!(regions(&folds), vec![(0, 5), (10, 20), (30, 40)]);
+        folds.add(Interval::new(15, 30));
+        assert_eq!(regions(&folds), vec![(0, 5), (10, 40)]);
+        folds.add(Interval::new(7, 7));
+        assert_eq!(regions(&folds), vec![(0, 5), (10, 40)]);
+        assert!(folds.remove_range(5, 5));
+        assert_eq!(regions(&folds), vec![(10, 40)]);
+        assert!(!folds.remove_range(41, 50));
+    }
+
+    #[test]
+    fn apply_delta() {
+        let text = Rope::from("0123456789");
+        let mut folds = Folds::new();
+        folds.add(Interval::new(2, 4));
+        folds.add(Interval::new(6, 8));
+        // insertions at the fold boundaries stay outside the fold
+        let delta = Delta::simple_edit(Interval::new(2, 2), Rope::from("ab"), text.len());
+        folds.apply_delta(&delta);
+        assert_eq!(regions(&folds), vec![(4, 6), (8, 10)]);
+        // deleting the contents of a fold removes it
+        let delta = Delta::simple_edit(Interval::new(3, 7), Rope::from(""), 12);
+        folds.apply_delta(&delta);
+        assert_eq!(regions(&folds), vec![(4, 6)]);
+    }
+
+    #[test]
+    fn indent_fold() {
+        let text = Rope::from("fn main() {\n    a;\n\n    b;\n}\n");
+        assert_eq!(indent_fold_for_line(&text, 0), Some(Interval::new(11, 26)));
+        assert_eq!(indent_fold_for_line(&text, 1), None);
+        assert_eq!(indent_fold_for_line(&text, 2), None);
+    }
+
+    #[test]
+    fn scope_fold() {
+        let text = "f(\na,\nb\n) // c\n";
+        let rope = Rope::from(text);
+        let style_map = ThemeStyleMap::new(None);
+        let mut layers = Layers::default();
+        let pid = PluginPid(1);
+        let scopes = vec![
+            vec!["source.rust".to_owned()],
+            vec!["source.rust".to_owned(), "meta.group.rust".to_owned()],
+            vec!["source.rust".to_owned(), "comment.line.rust".to_owned()],
+        ];
+        layers.add_scopes(pid, scopes, &style_map);
+        let mut sb = SpansBuilder::new(text.len());
+        sb.add_span(Interval::new(0, 1), 0);
+        sb.add_span(Interval::new(1, 9), 1);
+        sb.add_span(Interval::new(9, 10), 0);
+        sb.add_span(Interval::new(10, text.len()), 2);
+        layers.update_all(&Delta::simple_edit(Interval::new(0, 0), rope.clone(), 0));
+        layers.update_layer(pid, Interval::new(0, text.len()), sb.build());
+
+        // the lines up to the one closing the group are hidden
+        assert_eq!(scope_fold_for_line(&rope, &layers, 0), Some(Interval::new(2, 7)));
+        assert_eq!(indent_fold_for_line(&rope, 0), None);
+        assert_eq!(scope_fold_for_line(&rope, &layers, 1), None);
+        // scopes within a line are not folded
+        assert_eq!(scope_fold_for_line(&rope, &layers, 3), None);
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The kill ring, which keeps recently killed text for yanking, and the
+//! named registers that text can be copied to and pasted from.
+
+use std::collections::{HashMap, VecDeque};
+
+/// The number of kills the kill ring keeps; older kills are dropped.
+const MAX_KILLS: usize = 60;
+
+/// Killed or copied text, which keeps the text of each selection region it
+/// came from, so that it can be put back at as many cursors.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Kill {
+    regions: Vec<String>,
+}
+
+impl Kill {
+    pub fn new(regions: Vec<String>) -> Kill {
+        Kill { regions }
+    }
+
+    /// Returns all of the text, with the regions joined by newlines.
+    pub fn text(&self) -> String {
+        self.regions.join("\n")
+    }
+
+    /// Returns the text to insert at each of `n_cursors` cursors: the text of
+    /// one region at each cursor if there are as many regions as cursors,
+    /// and otherwise all of the text at every cursor.
+    pub fn texts_for(&self, n_cursors: usize) -> Vec<String> {
+        if self.regions.len() == n_cursors {
+            self.regions.clone()
+        } else {
+            vec![self.text(); n_cursors]
+        }
+    }
+
+    /// Adds the text of `other` to the end of this kill, or to its start if
+    /// `prepend` is set, region by region if they have as many regions.
+    fn append(&mut self, other: Kill, prepend: bool) {
+        let others = if other.regions.len() == self.regions.len() {
+            other.regions
+        } else {
+            let text = other.text();
+            self.regions = vec![self.text()];
+            vec![text]
+        };
+        for (region, other) in self.regions.iter_mut().zip(others) {
+            if prepend {
+                region.insert_str(0, &other);
+            } else {
+                region.push_str(&other);
+            }
+        }
+    }
+}
+
+/// What the last command did with the kill ring, which the next command
+/// can build on.
+#[derive(Debug, Clone, PartialEq)]
+enum LastCommand {
+    /// It killed text, so the next kill is added to that one.
+    Kill,
+    /// It yanked the kill at `index`, inserting text of these lengths before
+    /// each cursor, which `yank_pop` can replace with an earlier kill.
+    Yank { index: usize, lens: Vec<usize> },
+}
+
+/// The recently killed text, most recent first, and the named registers.
+#[derive(Debug, Default)]
+pub struct KillRing {
+    kills: VecDeque<Kill>,
+    registers: HashMap<String, Kill>,
+    last_command: Option<LastCommand>,
+}
+
+impl KillRing {
+    pub fn new() -> KillRing {
+        KillRing::default()
+    }
+
+    /// Adds killed text to the ring. If the last command was also a kill,
+    /// the text is added to that kill instead, before it if `prepend` is
+    /// set, as when killing backwards.
+    pub fn kill(&mut self, kill: Kill, prepend: bool) {
+        match (&self.last_command, self.kills.front_mut()) {
+            (Some(LastCommand::Kill), Some(last)) => last.append(kill, prepend),
+            _ => self.push(kill),
+        }
+        self.last_command = Some(LastCommand::Kill);
+    }
+
+    /// Adds copied text to the ring, as a new kill.
+    pub fn push(&mut self, kill: Kill) {
+        self.kills.push_front(kill);
+        self.kills.truncate(MAX_KILLS);
+    }
+
+    /// Returns the most recent kill.
+    pub fn latest(&self) -> Option<&Kill> {
+        self.kills.front()
+    }
+
+    /// Records that the most recent kill was yanked, inserting text of
+    /// `lens` before each cursor.
+    pub fn set_yanked(&mut self, lens: Vec<usize>) {
+        self.last_command = Some(LastCommand::Yank { index: 0, lens });
+    }
+
+    /// If the last command was a yank, moves on to the kill before the one it
+    /// yanked, returning that kill along with the lengths of the text that
+    /// the yank inserted before each cursor. The kills wrap around.
+    pub fn yank_pop(&mut self) -> Option<(&Kill, Vec<usize>)> {
+        let (index, lens) = match self.last_command.take() {
+            Some(LastCommand::Yank { index, lens }) => (index, lens),
+            _ => return None,
+        };
+        let index = (index + 1) % self.kills.len();
+        self.last_command = Some(LastCommand::Yank { index, lens: lens.clone() });
+        Some((&self.kills[index], lens))
+    }
+
+    /// Records the lengths of the text that a yank pop inserted before each
+    /// cursor, so that it can be popped again.
+    pub fn set_popped(&mut self, new_lens: Vec<usize>) {
+        if let Some(LastCommand::Yank { ref mut lens, .. }) = self.last_command {
+            *lens = new_lens;
+        }
+    }
+
+    /// Ends the sequence of kills or yanks, so that the next kill is added as
+    /// a new kill, and the last yank can no longer be popped.
+    pub fn end_sequence(&mut self) {
+        self.last_command = None;
+    }
+
+    pub fn register(&self, name: &str) -> Option<&Kill> {
+        self.registers.get(name)
+    }
+
+    pub fn set_register(&mut self, name: String, kill: Kill) {
+        self.registers.insert(name, kill);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kill(regions: &[&str]) -> Kill {
+        Kill::new(regions.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn consecutive_kills() {
+        let mut ring = KillRing::new();
+        ring.kill(kill(&["b"]), false);
+        ring.kill(kill(&["c"]), false);
+        ring.kill(kill(&["a"]), true);
+        assert_eq!(ring.latest(), Some(&kill(&["abc"])));
+        ring.end_sequence();
+        ring.kill(kill(&["x", "y"]), false);
+        ring.kill(kill(&["1", "2"]), false);
+        assert_eq!(ring.latest(), Some(&kill(&["x1", "y2"])));
+        // kills of different numbers of regions are joined
+        ring.kill(kill(&["z"]), false);
+        assert_eq!(ring.latest(), Some(&kill(&["x1\ny2z"])));
+        assert_eq!(ring.kills.len(), 2);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut ring = KillRing::new();
+        for i in 0..MAX_KILLS + 5 {
+            ring.push(kill(&[&i.to_string()]));
+        }
+        assert_eq!(ring.kills.len(), MAX_KILLS);
+        assert_eq!(ring.latest(), Some(&kill(&[&(MAX_KILLS + 4).to_string()])));
+    }
+
+    #[test]
+    fn yank_pop() {
+        let mut ring = KillRing::new();
+        assert!(ring.yank_pop().is_none());
+        ring.push(kill(&["a"]));
+        ring.push(kill(&["bb"]));
+        ring.set_yanked(vec![2]);
+        assert_eq!(ring.yank_pop(), Some((&kill(&["a"]), vec![2])));
+        ring.set_popped(vec![1]);
+        assert_eq!(ring.yank_pop(), Some((&kill(&["bb"]), vec![1])));
+        ring.end_sequence();
+        assert!(ring.yank_pop().is_none());
+    }
+
+    #[test]
+    fn texts_for_cursors() {
+        let k = kill(&["a", "b"]);
+        assert_eq!(k.texts_for(2), vec!["a", "b"]);
+        assert_eq!(k.texts_for(3), vec!["a\nb", "a\nb", "a\nb"]);
+    }
+}
@@ -18,6 +18,7 @@
 //! Scope information originating from any number of plugins can be resolved
 //! into styles using a theme, augmented with additional style definitions.
 
+use std::cmp::{max, min};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use syntect::highlighting::StyleModifier;
 use syntect::parsing::Scope;
@@ -29,6 +30,11 @@ use xi_trace::trace_block;
 use plugins::PluginPid;
 use styles::{Style, ThemeStyleMap};
 
+lazy_static! {
+    static ref STRING_SCOPE: Scope = Scope::new("string").unwrap();
+    static ref COMMENT_SCOPE: Scope = Scope::new("comment").unwrap();
+}
+
 /// A collection of layers containing scope information.
 #[derive(Default)]
 pub struct Layers {
@@ -137,6 +143,89 @@ impl Layers {
         self.merged.edit(iv, resolved);
     }
 
+    /// Returns `true` if the text at `offset` is in a string or a comment,
+    /// according to the scopes of any layer.
+    pub fn is_string_or_comment(&self, offset: usize) -> bool {
+        if self.layers.is_empty() {
+            return false;
+        }
+        self.layers.values().any(|layer| {
+            layer
+                .scopes_at(offset)
+                .iter()
+                .any(|s| STRING_SCOPE.is_prefix_of(*s) || COMMENT_SCOPE.is_prefix_of(*s))
+        })
+    }
+
+    /// Returns the extents of the scopes, in any layer, that enclose `iv`,
+    /// along with the scopes. A scope's extent is the run of text around
+    /// `iv` in which it is on the scope stack at the same depth.
+    pub fn enclosing_scopes(&self, iv: Interval) -> Vec<(Interval, Scope)> {
+        let encloses =
+            |e: &Interval| e.start() <= iv.start() && e.end() >= iv.end() && e.end() > iv.start();
+        let mut result = Vec::new();
+        for layer in self.layers.values() {
+            let len = layer.scope_spans.len();
+            if iv.start() >= len {
+                continue;
+            }
+            // only look at the spans near `iv`, widening the window while the
+            // extents of the enclosing scopes may continue past it
+            let mut window = Interval::new(iv.start(), max(iv.end(), iv.start() + 1));
+            loop {
+                let extents = layer.scope_extents(window);
+                let is_cut_off = extents.iter().any(|(e, _)| {
+                    encloses(e)
+                        && ((e.start() == window.start() && window.start() > 0)
+                            || (e.end() == window.end() && window.end() < len))
+                });
+                if !is_cut_off {
+                    result.extend(extents.into_iter().filter(|(e, _)| encloses(e)));
+                    break;
+                }
+                let size = window.size();
+                let start = window.start().saturating_sub(size);
+                window = Interval::new(start, min(len, window.end() + size));
+            }
+        }
+        result
+    }
+
+    /// Returns the extents of the scopes, in any layer, that start in `iv`,
+    /// other than the base scopes (`source` or `text`) of whole documents.
+    pub fn scopes_starting_in(&self, iv: Interval) -> Vec<Interval> {
+        let source = Scope::new("source").unwrap();
+        let text = Scope::new("text").unwrap();
+        let starts_in = |&(e, scope): &(Interval, Scope)| {
+            e.start() >= iv.start()
+                && e.start() < iv.end()
+                && !source.is_prefix_of(scope)
+                && !text.is_prefix_of(scope)
+        };
+        let mut result = Vec::new();
+        for layer in self.layers.values() {
+            let len = layer.scope_spans.len();
+            if iv.start() >= len {
+                continue;
+            }
+            // start the window before `iv`, so that scopes continuing into it
+            // don't seem to start in it, and widen it while the extents of
+            // the scopes starting in `iv` may continue past it
+            let mut window = Interval::new(iv.start().saturating_sub(1), min(len, iv.end()));
+            loop {
+                let extents = layer.scope_extents(window);
+                let is_cut_off = window.end() < len
+                    && extents.iter().any(|e| starts_in(e) && e.0.end() == window.end());
+                if !is_cut_off {
+                    result.extend(extents.into_iter().filter(starts_in).map(|(e, _)| e));
+                    break;
+                }
+                window = Interval::new(window.start(), min(len, window.end() + window.size()));
+            }
+        }
+        result
+    }
+
     /// Prints scopes and style information for the given `Interval`.
     pub fn debug_print_spans(&self, iv: Interval) {
         for (id, layer) in &self.layers {
@@ -263,6 +352,49 @@ impl ScopeLayer {
         new_styles
     }
 
+    /// Returns the extents of the scopes on the stacks of the spans in
+    /// `window`, along with the scopes. Extents that continue past the window
+    /// are cut off at its edges.
+    fn scope_extents(&self, window: Interval) -> Vec<(Interval, Scope)> {
+        let mut result = Vec::new();
+        let mut starts: Vec<usize> = Vec::new();
+        let mut prev_stack: &[Scope] = &[];
+        let mut prev_end = window.start();
+        for (span, &id) in self.scope_spans.subseq(window).iter() {
+            let span = span.translate(window.start());
+            let stack = self.stack_lookup.get(id as usize).map_or(&[][..], Vec::as_slice);
+            let common = if span.start() == prev_end {
+                prev_stack.iter().zip(stack).take_while(|(a, b)| a == b).count()
+            } else {
+                0
+            };
+            for depth in (common..prev_stack.len()).rev() {
+                result.push((Interval::new(starts[depth], prev_end), prev_stack[depth]));
+            }
+            starts.truncate(common);
+            starts.resize(stack.len(), span.start());
+            prev_stack = stack;
+            prev_end = span.end();
+        }
+        for depth in (0..prev_stack.len()).rev() {
+            result.push((Interval::new(starts[depth], prev_end), prev_stack[depth]));
+        }
+        result
+    }
+
+    /// Returns the scope stack at `offset`.
+    fn scopes_at(&self, offset: usize) -> &[Scope] {
+        if offset >= self.scope_spans.len() {
+            return &[];
+        }
+        let spans = self.scope_spans.subseq(Interval::new(offset, offset + 1));
+        let id = match spans.iter().next() {
+            Some((_, &id)) => id as usize,
+            None => return &[],
+        };
+        self.stack_lookup.get(id).map_or(&[], Vec::as_slice)
+    }
+
     fn update_scopes(&mut self, iv: Interval, spans: &Spans<u32>) {
         self.scope_spans.edit(iv, spans.to_owned());
         self.update_styles(iv, spans);
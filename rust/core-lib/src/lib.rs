@@ -35,6 +35,8 @@
     )
 )]
 
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate regex;
@@ -70,28 +72,46 @@ mod ledger_includes {
 use ledger_includes::*;
 
 pub mod backspace;
+pub mod brackets;
 pub mod client;
+pub mod column_selection;
+pub mod comment;
+pub mod completion;
 pub mod config;
+pub mod config_schema;
 pub mod core;
 pub mod edit_types;
 pub mod editor;
+pub mod editorconfig;
 pub mod event_context;
+pub mod expand_selection;
 pub mod file;
 pub mod find;
+pub mod fold;
 #[cfg(feature = "ledger")]
 pub mod fuchsia;
 pub mod index_set;
+pub mod kill_ring;
 pub mod layers;
 pub mod line_cache_shadow;
 pub mod line_ending;
+pub mod line_ops;
 pub mod linewrap;
+pub mod merge;
+pub mod modal;
+pub mod modeline;
 pub mod movement;
 pub mod plugins;
 pub mod recorder;
+pub mod recovery;
 pub mod selection;
+pub mod session;
+pub mod snippet;
 pub mod styles;
 pub mod syntax;
 pub mod tabs;
+#[cfg(test)]
+mod test_fixtures;
 pub mod view;
 #[cfg(feature = "notify")]
 pub mod watcher;
@@ -0,0 +1,359 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Edits that operate on the whole lines touched by selection regions.
+
+use std::cmp::{max, min, Ordering};
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
+
+use xi_rope::{DeltaBuilder, Interval, LinesMetric, Rope, RopeDelta};
+
+use selection::{SelRegion, Selection};
+
+/// Returns the range of lines touched by `region`. A region that ends at
+/// the start of a line doesn't touch it, unless the region is a caret.
+pub(crate) fn touched_lines(text: &Rope, region: &SelRegion) -> Range<usize> {
+    let first = text.line_of_offset(region.min());
+    let mut last = text.line_of_offset(region.max());
+    if last > first && text.offset_of_line(last) == region.max() {
+        last -= 1;
+    }
+    first..last + 1
+}
+
+fn n_lines(text: &Rope) -> usize {
+    text.measure::<LinesMetric>() + 1
+}
+
+/// Returns the last line of the text, leaving out the empty line after a
+/// final line ending, which stays where it is.
+fn last_line(text: &Rope) -> usize {
+    let last = n_lines(text) - 1;
+    if last > 0 && text.offset_of_line(last) == text.len() {
+        last - 1
+    } else {
+        last
+    }
+}
+
+/// Returns the offset of the end of `line`, before its line ending.
+fn line_end(text: &Rope, line: usize) -> usize {
+    let start = text.offset_of_line(line);
+    start + text.lines(start..).next().map_or(0, |line| line.len())
+}
+
+/// Sorts `ranges` and merges the ones that overlap, or, if `adjacent` is
+/// `true`, that are next to each other.
+fn merge_ranges<I>(ranges: I, adjacent: bool) -> Vec<Range<usize>>
+where
+    I: IntoIterator<Item = Range<usize>>,
+{
+    let mut ranges = ranges.into_iter().collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        if let Some(last) = merged.last_mut() {
+            if range.start < last.end || (adjacent && range.start == last.end) {
+                last.end = max(last.end, range.end);
+                continue;
+            }
+        }
+        merged.push(range);
+    }
+    merged
+}
+
+/// Replaces the lines of each block of lines touched by the non-caret
+/// regions in `regions`, or of the whole text if all of them are carets,
+/// with the lines returned by `f`. Returns the delta and a selection of
+/// each block, or `None` if no lines change.
+pub fn transform_lines<F>(
+    text: &Rope,
+    regions: &[SelRegion],
+    f: F,
+) -> Option<(RopeDelta, Selection)>
+where
+    F: Fn(&mut Vec<String>),
+{
+    let blocks = if regions.iter().all(|region| region.is_caret()) {
+        let all_lines = 0..last_line(text) + 1;
+        vec![all_lines]
+    } else {
+        let ranges = regions
+            .iter()
+            .filter(|region| !region.is_caret())
+            .map(|region| touched_lines(text, region));
+        merge_ranges(ranges, false)
+    };
+
+    let mut builder = DeltaBuilder::new(text.len());
+    let mut sel = Selection::new();
+    // the change in length of the text before the current block
+    let mut shift = 0isize;
+    for block in blocks {
+        let start = text.offset_of_line(block.start);
+        let end = line_end(text, block.end - 1);
+        let ending = match block.len() {
+            1 => "\n".to_owned(),
+            _ => {
+                let first_end = line_end(text, block.start);
+                text.slice_to_cow(first_end..text.offset_of_line(block.start + 1)).into_owned()
+            }
+        };
+        let old = text.slice_to_cow(start..end);
+        let mut lines = text.lines(start..end).map(String::from).collect::<Vec<_>>();
+        f(&mut lines);
+        let new = lines.join(&ending);
+
+        let new_start = (start as isize + shift) as usize;
+        sel.add_region(SelRegion::new(new_start, new_start + new.len()));
+        if new != old {
+            shift += new.len() as isize - old.len() as isize;
+            builder.replace(Interval::new(start, end), Rope::from(new));
+        }
+    }
+    if builder.is_empty() {
+        None
+    } else {
+        Some((builder.build(), sel))
+    }
+}
+
+/// Removes the lines that are equal to a previous line.
+pub fn dedup_lines(lines: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    lines.retain(|line| seen.insert(line.clone()));
+}
+
+/// Compares strings so that runs of digits are ordered by their numeric
+/// value, as in `a2 < a10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (a_num, a_rest) = split_digits(a);
+                let (b_num, b_rest) = split_digits(b);
+                let a_num = a_num.trim_start_matches('0');
+                let b_num = b_num.trim_start_matches('0');
+                let ord = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a = a_rest;
+                b = b_rest;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// Joins each line touched by a caret or a single-line region with the
+/// next line, and the lines touched by each other region with each other,
+/// replacing each line break and the indentation after it with a space.
+pub fn join_lines(text: &Rope, regions: &[SelRegion]) -> Option<RopeDelta> {
+    // the lines whose line breaks are removed
+    let mut breaks = BTreeSet::new();
+    for region in regions {
+        let lines = touched_lines(text, region);
+        let last = if lines.len() > 1 { lines.end - 1 } else { lines.end };
+        breaks.extend(lines.start..last);
+    }
+    let n_lines = n_lines(text);
+    let mut builder = DeltaBuilder::new(text.len());
+    for line in breaks.into_iter().filter(|&line| line + 1 < n_lines) {
+        let end = line_end(text, line);
+        let next = text.offset_of_line(line + 1);
+        let next_end = line_end(text, line + 1);
+        let next_text = text.slice_to_cow(next..next_end);
+        let indent = next_text.len() - next_text.trim_start().len();
+        let is_empty = end == text.offset_of_line(line) || next + indent == next_end;
+        let space = if is_empty { "" } else { " " };
+        builder.replace(Interval::new(end, next + indent), Rope::from(space));
+    }
+    if builder.is_empty() {
+        None
+    } else {
+        Some(builder.build())
+    }
+}
+
+/// Moves each block of lines touched by `regions` up, or, if `up` is
+/// `false`, down by one line, swapping it with the line before or after it.
+/// Returns the delta and the moved selection, or `None` if a block is
+/// already at the start or the end of the text.
+pub fn move_lines(text: &Rope, regions: &[SelRegion], up: bool) -> Option<(RopeDelta, Selection)> {
+    let blocks = merge_ranges(regions.iter().map(|region| touched_lines(text, region)), true);
+    let last_line = last_line(text);
+    let can_move = match (up, blocks.first(), blocks.last()) {
+        (_, _, Some(last)) if last.start > last_line => false,
+        (true, Some(first), _) => first.start > 0,
+        (false, _, Some(last)) => last.end <= last_line,
+        _ => false,
+    };
+    if !can_move {
+        return None;
+    }
+
+    let mut builder = DeltaBuilder::new(text.len());
+    let mut sel = Selection::new();
+    for block in blocks {
+        let start = text.offset_of_line(block.start);
+        let end = line_end(text, block.end - 1);
+        let moved = text.slice_to_cow(start..end);
+        let (interval, new, new_start) = if up {
+            let other_start = text.offset_of_line(block.start - 1);
+            let other_end = line_end(text, block.start - 1);
+            let other = text.slice_to_cow(other_start..other_end);
+            let ending = text.slice_to_cow(other_end..start);
+            let new = format!("{}{}{}", moved, ending, other);
+            (Interval::new(other_start, end), new, other_start)
+        } else {
+            let other_start = text.offset_of_line(block.end);
+            let other_end = line_end(text, block.end);
+            let other = text.slice_to_cow(other_start..other_end);
+            let ending = text.slice_to_cow(end..other_start);
+            let new = format!("{}{}{}", other, ending, moved);
+            (Interval::new(start, other_end), new, start + other.len() + ending.len())
+        };
+        builder.replace(interval, Rope::from(new));
+
+        let offset = |offset: usize| min(offset + new_start - start, text.len());
+        for region in regions {
+            if block.contains(&touched_lines(text, region).start) {
+                let start = offset(region.start);
+                let end = offset(region.end);
+                sel.add_region(SelRegion { start, end, ..*region });
+            }
+        }
+    }
+    Some((builder.build(), sel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(offsets: &[(usize, usize)]) -> Vec<SelRegion> {
+        offsets.iter().map(|&(start, end)| SelRegion::new(start, end)).collect()
+    }
+
+    fn sel_offsets(sel: &Selection) -> Vec<(usize, usize)> {
+        sel.iter().map(|region| (region.start, region.end)).collect()
+    }
+
+    fn transform<F>(text: &str, offsets: &[(usize, usize)], f: F) -> (String, Vec<(usize, usize)>)
+    where
+        F: Fn(&mut Vec<String>),
+    {
+        let text = Rope::from(text);
+        match transform_lines(&text, &regions(offsets), f) {
+            Some((delta, sel)) => (String::from(delta.apply(&text)), sel_offsets(&sel)),
+            None => (String::from(text), Vec::new()),
+        }
+    }
+
+    #[test]
+    fn sort_lines() {
+        let sort = |lines: &mut Vec<String>| lines.sort();
+        assert_eq!(transform("c\nb\na\n", &[(0, 0)], sort), ("a\nb\nc\n".to_owned(), vec![(0, 5)]));
+        assert_eq!(transform("a\nb\n", &[(0, 0)], sort), ("a\nb\n".to_owned(), vec![]));
+        // only the lines touched by selections, in separate blocks
+        let text = "d\nc\n\nb\na\n";
+        assert_eq!(
+            transform(text, &[(0, 3), (6, 9)], sort),
+            ("c\nd\n\na\nb\n".to_owned(), vec![(0, 3), (5, 8)])
+        );
+        assert_eq!(transform("b\r\na", &[(0, 4)], sort), ("a\r\nb".to_owned(), vec![(0, 4)]));
+    }
+
+    #[test]
+    fn unique_and_reverse_lines() {
+        let (text, sel) = transform("a\nb\na\nc\nb", &[(0, 0)], dedup_lines);
+        assert_eq!((text.as_str(), sel), ("a\nb\nc", vec![(0, 5)]));
+        let (text, _) = transform("a\nb\nc", &[(0, 3)], |lines| lines.reverse());
+        assert_eq!(text, "b\na\nc");
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut lines = vec!["a10", "a2", "b", "a02b", "a2a", "10", "9"];
+        lines.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(lines, vec!["9", "10", "a2", "a2a", "a02b", "a10", "b"]);
+    }
+
+    #[test]
+    fn join() {
+        let join = |text: &str, offsets: &[(usize, usize)]| {
+            let text = Rope::from(text);
+            match join_lines(&text, &regions(offsets)) {
+                Some(delta) => String::from(delta.apply(&text)),
+                None => String::from(text),
+            }
+        };
+        assert_eq!(join("a\n    b\nc", &[(0, 0)]), "a b\nc");
+        assert_eq!(join("a\n    b\nc", &[(0, 9)]), "a b c");
+        assert_eq!(join("a\n  \nb", &[(0, 5)]), "a\nb");
+        assert_eq!(join("a\n\nb", &[(0, 2)]), "a\nb");
+        assert_eq!(join("a\nb\nc\nd", &[(0, 0), (4, 4)]), "a b\nc d");
+        assert_eq!(join("a", &[(0, 0)]), "a");
+    }
+
+    #[test]
+    fn move_up_and_down() {
+        let move_lines = |text: &str, offsets: &[(usize, usize)], up: bool| {
+            let text = Rope::from(text);
+            match move_lines(&text, &regions(offsets), up) {
+                Some((delta, sel)) => (String::from(delta.apply(&text)), sel_offsets(&sel)),
+                None => (String::from(text), Vec::new()),
+            }
+        };
+        let text = "a\nb\nc\nd";
+        assert_eq!(move_lines(text, &[(2, 5)], true), ("b\nc\na\nd".to_owned(), vec![(0, 3)]));
+        assert_eq!(move_lines(text, &[(2, 5)], false), ("a\nd\nb\nc".to_owned(), vec![(4, 7)]));
+        assert_eq!(move_lines(text, &[(6, 7)], false), (text.to_owned(), vec![]));
+        assert_eq!(move_lines(text, &[(0, 0)], true), (text.to_owned(), vec![]));
+        // the empty line after a final line ending stays at the end
+        let text = "a\nb\n";
+        assert_eq!(move_lines(text, &[(2, 2)], false), (text.to_owned(), vec![]));
+        assert_eq!(move_lines(text, &[(4, 4)], true), (text.to_owned(), vec![]));
+        assert_eq!(move_lines(text, &[(0, 0)], false), ("b\na\n".to_owned(), vec![(2, 2)]));
+        let text = "a\nb\nc\nd";
+        // separate blocks move together
+        assert_eq!(
+            move_lines(text, &[(2, 2), (7, 7)], true),
+            ("b\na\nd\nc".to_owned(), vec![(0, 0), (5, 5)])
+        );
+        // adjacent lines move as one block
+        assert_eq!(
+            move_lines(text, &[(0, 0), (2, 2)], false),
+            ("c\na\nb\nd".to_owned(), vec![(2, 2), (4, 4)])
+        );
+    }
+}
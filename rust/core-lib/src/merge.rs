@@ -0,0 +1,303 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Three-way merging of changes made to a file on disk into a buffer
+//! with unsaved edits.
+
+use std::cmp::min;
+
+use xi_rope::diff::{Diff, LineHashDiff};
+use xi_rope::engine::Engine;
+use xi_rope::{
+    Delta, DeltaBuilder, DeltaElement, Interval, LinesMetric, Rope, RopeDelta, Transformer,
+};
+
+/// Priorities of the two sides of a merge, which order text inserted by
+/// both at the same position.
+const OURS_PRIORITY: usize = 1;
+const THEIRS_PRIORITY: usize = 0;
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`.
+///
+/// Both sets of changes are computed with `LineHashDiff` and merged with the
+/// CRDT engine, as though they were concurrent edits. Where the two sets of
+/// changes touch or overlap, the lines they change are replaced with both
+/// versions of them, between conflict markers:
+///
+/// ```text
+/// <<<<<<< buffer
+/// our version
+/// =======
+/// their version
+/// >>>>>>> disk
+/// ```
+///
+/// Returns the merged text, along with the regions of it taken up by each
+/// of these conflicts, markers included.
+pub fn three_way_merge(base: &Rope, ours: &Rope, theirs: &Rope) -> (Rope, Vec<Interval>) {
+    let ours_delta = compute_delta(base, ours);
+    let theirs_delta = compute_delta(base, theirs);
+    let conflicts = whole_lines(base, &conflicting_regions(&ours_delta, &theirs_delta));
+
+    let mut engine = Engine::new(base.clone());
+    let base_rev = engine.get_head_rev_id().token();
+    engine.edit_rev(OURS_PRIORITY, 1, base_rev, ours_delta.clone());
+    engine.edit_rev(THEIRS_PRIORITY, 2, base_rev, theirs_delta.clone());
+    let merged = engine.get_head().clone();
+    let merge_delta = engine.delta_rev_head(base_rev);
+
+    let transform = |delta: &RopeDelta, iv: Interval| {
+        let mut transformer = Transformer::new(delta);
+        Interval::new(
+            transformer.transform(iv.start(), false),
+            transformer.transform(iv.end(), true),
+        )
+    };
+    let mut builder = DeltaBuilder::new(merged.len());
+    let mut regions = Vec::with_capacity(conflicts.len());
+    let mut shift = 0;
+    for &iv in &conflicts {
+        let ours_text = ours.slice_to_cow(transform(&ours_delta, iv));
+        let theirs_text = theirs.slice_to_cow(transform(&theirs_delta, iv));
+        let block = conflict_block(&ours_text, &theirs_text);
+        let merged_iv = transform(&merge_delta, iv);
+        let start = merged_iv.start() + shift;
+        regions.push(Interval::new(start, start + block.len()));
+        shift = shift + block.len() - merged_iv.size();
+        builder.replace(merged_iv, Rope::from(block));
+    }
+    (builder.build().apply(&merged), regions)
+}
+
+/// Returns the text that replaces a conflict: both versions of the lines,
+/// between conflict markers.
+fn conflict_block(ours: &str, theirs: &str) -> String {
+    let mut block = String::from("<<<<<<< buffer\n");
+    push_lines(&mut block, ours);
+    block.push_str("=======\n");
+    push_lines(&mut block, theirs);
+    block.push_str(">>>>>>> disk\n");
+    block
+}
+
+/// Appends `lines` to `block`, ending them with a newline if they don't
+/// already end with one, so that the next marker starts a line.
+fn push_lines(block: &mut String, lines: &str) {
+    block.push_str(lines);
+    if !lines.is_empty() && !lines.ends_with('\n') {
+        block.push('\n');
+    }
+}
+
+/// Extends each of the sorted `regions` of `text` to the whole lines it
+/// touches, merging regions that then touch or overlap. An empty region
+/// at the start of a line, which only inserts whole lines, is kept as is.
+fn whole_lines(text: &Rope, regions: &[Interval]) -> Vec<Interval> {
+    let line_start = |offset: usize| text.offset_of_line(text.line_of_offset(offset));
+    let mut result: Vec<Interval> = Vec::new();
+    for iv in regions {
+        let start = line_start(iv.start());
+        let end = if line_start(iv.end()) == iv.end() && (iv.start() == start || !iv.is_empty()) {
+            iv.end()
+        } else {
+            let line = text.line_of_offset(iv.end());
+            if line < text.measure::<LinesMetric>() {
+                text.offset_of_line(line + 1)
+            } else {
+                text.len()
+            }
+        };
+        let iv = Interval::new(start, end);
+        match result.last_mut() {
+            Some(last) if last.end() >= iv.start() => *last = last.union(iv),
+            _ => result.push(iv),
+        }
+    }
+    result
+}
+
+/// Computes the delta from `base` to `target` with `LineHashDiff`, in a form
+/// that can be applied by the engine.
+///
+/// `LineHashDiff` may copy the same text from the base more than once, such
+/// as a final newline that is both part of a matched line and of the common
+/// suffix. The engine only supports deltas whose copies are in order, so
+/// text that has already been copied is inserted instead.
+pub(crate) fn compute_delta(base: &Rope, target: &Rope) -> RopeDelta {
+    let delta = LineHashDiff::compute_delta(base, target);
+    let mut els = Vec::with_capacity(delta.els.len());
+    let mut pos = 0;
+    for el in delta.els {
+        match el {
+            DeltaElement::Copy(start, end) if start < pos => {
+                els.push(DeltaElement::Insert(base.slice(start..min(end, pos))));
+                if end > pos {
+                    els.push(DeltaElement::Copy(pos, end));
+                    pos = end;
+                }
+            }
+            DeltaElement::Copy(start, end) => {
+                els.push(DeltaElement::Copy(start, end));
+                pos = end;
+            }
+            insert => els.push(insert),
+        }
+    }
+    Delta { els, base_len: delta.base_len }
+}
+
+/// Returns the regions of the base document where the changes made by the
+/// two deltas touch or overlap, sorted and merged.
+fn conflicting_regions(a: &RopeDelta, b: &RopeDelta) -> Vec<Interval> {
+    let b_regions = changed_regions(b);
+    let mut conflicts: Vec<Interval> = Vec::new();
+    for a_iv in changed_regions(a) {
+        for b_iv in b_regions.iter().filter(|b_iv| overlaps(a_iv, **b_iv)) {
+            let iv = a_iv.union(*b_iv);
+            match conflicts.last_mut() {
+                Some(last) if last.end() >= iv.start() => *last = last.union(iv),
+                _ => conflicts.push(iv),
+            }
+        }
+    }
+    conflicts
+}
+
+/// Returns the regions of the base document that are deleted or replaced by
+/// `delta`, in order. Insertions are represented by empty intervals.
+fn changed_regions(delta: &RopeDelta) -> Vec<Interval> {
+    let mut regions = Vec::new();
+    let mut pos = 0;
+    let mut inserted = false;
+    for el in &delta.els {
+        match *el {
+            DeltaElement::Copy(start, end) => {
+                if start > pos || inserted {
+                    regions.push(Interval::new(pos, start));
+                }
+                pos = end;
+                inserted = false;
+            }
+            DeltaElement::Insert(_) => inserted = true,
+        }
+    }
+    if pos < delta.base_len || inserted {
+        regions.push(Interval::new(pos, delta.base_len));
+    }
+    regions
+}
+
+/// Two changed regions conflict if they overlap, or if one is an insertion
+/// that touches the other.
+fn overlaps(a: Interval, b: Interval) -> bool {
+    if a.is_empty() || b.is_empty() {
+        a.start() <= b.end() && b.start() <= a.end()
+    } else {
+        a.start() < b.end() && b.start() < a.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(prefix: &str, n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{} line number {} with some padding text\n", prefix, i)).collect()
+    }
+
+    #[test]
+    fn merge_disjoint() {
+        let base = lines("base", 10);
+        let mut ours = base.clone();
+        ours[2] = "edited in the buffer, on a line that is long enough\n".into();
+        let mut theirs = base.clone();
+        theirs[7] = "edited on disk, on another line that is long enough\n".into();
+        theirs.push("appended on disk\n".into());
+
+        let (merged, conflicts) = three_way_merge(
+            &Rope::from(base.concat()),
+            &Rope::from(ours.concat()),
+            &Rope::from(theirs.concat()),
+        );
+        let mut expected = theirs.clone();
+        expected[2] = ours[2].clone();
+        assert_eq!(String::from(merged), expected.concat());
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_conflict() {
+        let base = lines("base", 6);
+        let mut ours = base.clone();
+        ours[3] = "ours\n".into();
+        let mut theirs = base.clone();
+        theirs[3] = "theirs\n".into();
+
+        let (merged, conflicts) = three_way_merge(
+            &Rope::from(base.concat()),
+            &Rope::from(ours.concat()),
+            &Rope::from(theirs.concat()),
+        );
+        let merged = String::from(merged);
+        let conflict = "<<<<<<< buffer\nours\n=======\ntheirs\n>>>>>>> disk\n";
+        assert_eq!(merged, format!("{}{}{}", base[..3].concat(), conflict, base[4..].concat()));
+        let start = base[..3].concat().len();
+        assert_eq!(conflicts, vec![Interval::new(start, start + conflict.len())]);
+    }
+
+    #[test]
+    fn merge_conflict_within_line() {
+        // both sides change the same word, and theirs also an earlier line;
+        // the conflict takes up the whole line
+        let base = lines("base", 6);
+        let mut ours = base.clone();
+        ours[3] = ours[3].replace("padding", "ours");
+        let mut theirs = base.clone();
+        theirs[3] = theirs[3].replace("padding", "theirs");
+        theirs[0] = "changed on disk only\n".into();
+
+        let (merged, conflicts) = three_way_merge(
+            &Rope::from(base.concat()),
+            &Rope::from(ours.concat()),
+            &Rope::from(theirs.concat()),
+        );
+        let conflict = format!("<<<<<<< buffer\n{}=======\n{}>>>>>>> disk\n", ours[3], theirs[3]);
+        let mut expected = theirs.clone();
+        expected[3] = conflict.clone();
+        assert_eq!(String::from(merged), expected.concat());
+        let start = expected[..3].concat().len();
+        assert_eq!(conflicts, vec![Interval::new(start, start + conflict.len())]);
+    }
+
+    #[test]
+    fn delta_for_engine() {
+        // the diff copies the final newline twice
+        let base = lines("base", 10).concat();
+        let target = base.replace("number 7 with", "number seven, edited, with") + "appended\n";
+        let (base, target) = (Rope::from(base), Rope::from(target));
+        let mut engine = Engine::new(base.clone());
+        let base_rev = engine.get_head_rev_id().token();
+        engine.edit_rev(0, 1, base_rev, compute_delta(&base, &target));
+        assert_eq!(String::from(engine.get_head()), String::from(target));
+    }
+
+    #[test]
+    fn changed() {
+        let mut builder = DeltaBuilder::new(10);
+        builder.replace(Interval::new(2, 3), Rope::from("x"));
+        builder.replace(Interval::new(10, 10), Rope::from("y"));
+        let delta = builder.build();
+        assert_eq!(changed_regions(&delta), vec![Interval::new(2, 3), Interval::new(10, 10)]);
+    }
+}
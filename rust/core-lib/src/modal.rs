@@ -0,0 +1,931 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An optional modal, vi-style input layer.
+//!
+//! When the `modal_editing` config is set, the characters typed in normal
+//! and visual modes are parsed as commands instead of being inserted. The
+//! commands are carried out with the same events as other edits, which is
+//! also how the last change is recorded for the `.` command to repeat.
+
+use std::cmp::{max, min};
+use std::mem;
+
+use xi_rope::{Cursor, Interval, LinesMetric, Rope};
+
+use edit_types::EventDomain;
+use expand_selection::indent_block;
+use fold::{end_of_line, line_indent};
+use movement::Movement;
+use rpc::TextObjectKind;
+use selection::SelRegion;
+use word_boundaries::WordCursor;
+
+/// The largest count a command can be given. Commands are run once for
+/// each time they are repeated, so larger counts are clamped to it.
+const MAX_COUNT: usize = 9_999;
+
+/// The input mode of a view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Typed characters are commands.
+    Normal,
+    /// Typed characters are inserted.
+    Insert,
+    /// Motions extend the selection, and operators act on it.
+    Visual,
+    /// Like `Visual`, but the selection always spans whole lines.
+    VisualLine,
+}
+
+/// An operator, which acts on the text covered by a motion or a text object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `d`, which kills the text.
+    Delete,
+    /// `c`, which kills the text and enters insert mode.
+    Change,
+    /// `y`, which copies the text to the kill ring.
+    Yank,
+    /// `>`, which indents the lines of the text.
+    Indent,
+    /// `<`, which outdents the lines of the text.
+    Outdent,
+}
+
+impl Operator {
+    /// Whether the operator, applied to whole lines, leaves out their line
+    /// endings. Deleted and yanked lines take their line endings along.
+    pub fn takes_inner_lines(self) -> bool {
+        match self {
+            Operator::Delete | Operator::Yank => false,
+            _ => true,
+        }
+    }
+}
+
+/// A range of text around a cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    /// The lines touched by a region, and at least `count` lines from the
+    /// first of them. Inner lines don't include the last line ending.
+    Lines { inner: bool },
+    /// The word under the cursor. Outer words also include the whitespace
+    /// after them, or else the whitespace before them.
+    Word { inner: bool },
+    /// The contents of the `count`th pair of `open` and `close` characters
+    /// around the cursor, ignoring escaped ones. Outer pairs include the
+    /// characters themselves.
+    Pair { open: char, close: char, inner: bool },
+    /// The contents of the string between a pair of `quote` characters
+    /// around the cursor, or else after it, on the cursor's line. Escaped
+    /// quotes don't end strings. Outer strings include the quotes.
+    Quotes { quote: char, inner: bool },
+    /// The lines of `count` paragraphs, which are blocks of non-blank lines
+    /// or of blank lines. Outer paragraphs also include the next block, or
+    /// else the one before them.
+    Paragraph { inner: bool },
+    /// The lines around the cursor that are indented at least as much as its
+    /// line, or with a count, the enclosing blocks at lower indentations.
+    /// Outer blocks also include the line before them.
+    IndentBlock { inner: bool },
+}
+
+impl TextObject {
+    /// Returns the object of `kind`, or its inner object if `inner` is set.
+    pub fn new(kind: TextObjectKind, inner: bool) -> TextObject {
+        use rpc::TextObjectKind::*;
+        let pair = |open, close| TextObject::Pair { open, close, inner };
+        let quotes = |quote| TextObject::Quotes { quote, inner };
+        match kind {
+            Word => TextObject::Word { inner },
+            Line => TextObject::Lines { inner },
+            Parentheses => pair('(', ')'),
+            Brackets => pair('[', ']'),
+            Braces => pair('{', '}'),
+            AngleBrackets => pair('<', '>'),
+            DoubleQuotes => quotes('"'),
+            SingleQuotes => quotes('\''),
+            Backticks => quotes('`'),
+            Paragraph => TextObject::Paragraph { inner },
+            IndentBlock => TextObject::IndentBlock { inner },
+        }
+    }
+
+    /// Whether the object is made of whole lines.
+    pub fn is_linewise(self) -> bool {
+        match self {
+            TextObject::Lines { .. }
+            | TextObject::Paragraph { .. }
+            | TextObject::IndentBlock { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// The text an operator acts on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The text between the cursor and where a movement takes it.
+    Motion(Movement),
+    Object(TextObject),
+    /// The selection made in a visual mode.
+    Selection,
+}
+
+/// Where insert mode starts, relative to the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPosition {
+    /// `i`: at the cursor.
+    Cursor,
+    /// `a`: after the character following the cursor.
+    Append,
+    /// `I`: at the start of the line.
+    LineStart,
+    /// `A`: at the end of the line.
+    LineEnd,
+    /// `o`: on a new line below.
+    LineBelow,
+    /// `O`: on a new line above.
+    LineAbove,
+}
+
+/// A command typed in normal or visual mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Moves the cursor, or in visual modes extends the selection.
+    Move(Movement),
+    /// Moves to the start of a line, numbered from zero.
+    GotoLine(usize),
+    Operate(Operator, Target),
+    /// Selects a text object in visual modes.
+    SelectObject(TextObject),
+    /// Enters insert mode.
+    Insert(InsertPosition),
+    /// Inserts the contents of the kill ring after the cursor, or before it.
+    /// Killed lines are inserted below the cursor's line, or above it.
+    Put {
+        before: bool,
+    },
+    JoinLines,
+    Undo,
+    /// `.`, which repeats the last change.
+    Repeat,
+    /// Switches between normal and visual modes.
+    SetMode(Mode),
+}
+
+impl Command {
+    /// Whether the command changes the text, and is repeated by `.`.
+    pub fn is_change(&self) -> bool {
+        match *self {
+            Command::Operate(Operator::Yank, _) => false,
+            Command::Operate(..)
+            | Command::Insert(_)
+            | Command::Put { .. }
+            | Command::JoinLines => true,
+            _ => false,
+        }
+    }
+}
+
+/// The modal state of a view.
+pub(crate) struct ModalState {
+    mode: Mode,
+    /// The keys typed so far of an incomplete command.
+    keys: String,
+    /// The events of the change being made, while one is.
+    change: Option<Vec<EventDomain>>,
+    /// In visual line mode, the line the selection started on and the offset
+    /// of the cursor, which moves independently of the selected lines.
+    line_selection: (usize, usize),
+}
+
+impl ModalState {
+    pub(crate) fn new() -> Self {
+        ModalState { mode: Mode::Normal, keys: String::new(), change: None, line_selection: (0, 0) }
+    }
+
+    pub(crate) fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Switches to `mode`, discarding any incomplete command. Returns whether
+    /// the mode changed.
+    pub(crate) fn set_mode(&mut self, mode: Mode) -> bool {
+        self.keys.clear();
+        mem::replace(&mut self.mode, mode) != mode
+    }
+
+    /// Discards any incomplete command. Returns whether there was one.
+    pub(crate) fn clear_keys(&mut self) -> bool {
+        let had_keys = !self.keys.is_empty();
+        self.keys.clear();
+        had_keys
+    }
+
+    /// Adds a key to the command being typed, returning the command and its
+    /// count once they are complete. Keys that can't start or continue a
+    /// command are discarded along with the incomplete command.
+    pub(crate) fn push_key(&mut self, key: char) -> Option<(usize, Command)> {
+        self.keys.push(key);
+        match parse(&self.keys, self.mode) {
+            Parse::Incomplete => None,
+            Parse::Invalid => {
+                self.keys.clear();
+                None
+            }
+            Parse::Complete(count, command) => {
+                self.keys.clear();
+                Some((count, command))
+            }
+        }
+    }
+
+    /// Starts recording a change, replacing any change being recorded.
+    pub(crate) fn start_change(&mut self) {
+        self.change = Some(Vec::new());
+    }
+
+    /// Adds an event to the change being recorded, if there is one.
+    pub(crate) fn record_change(&mut self, event: &EventDomain) {
+        if let Some(ref mut change) = self.change {
+            change.push(event.clone());
+        }
+    }
+
+    /// Stops recording the current change, returning its events.
+    pub(crate) fn finish_change(&mut self) -> Option<Vec<EventDomain>> {
+        self.change.take()
+    }
+
+    pub(crate) fn line_selection(&self) -> (usize, usize) {
+        self.line_selection
+    }
+
+    pub(crate) fn set_line_selection(&mut self, anchor_line: usize, head: usize) {
+        self.line_selection = (anchor_line, head);
+    }
+}
+
+/// The result of parsing the keys of a command.
+#[derive(Debug, PartialEq)]
+enum Parse {
+    /// The keys are the start of a command.
+    Incomplete,
+    /// The keys are not the start of any command.
+    Invalid,
+    /// A command, and the number of times to run it.
+    Complete(usize, Command),
+}
+
+/// Parses the keys typed in `mode`: a command preceded by an optional count.
+fn parse(keys: &str, mode: Mode) -> Parse {
+    use self::Command::*;
+    use self::Parse::*;
+
+    let (count, rest) = split_count(keys);
+    let mut chars = rest.chars();
+    let key = match chars.next() {
+        Some(key) => key,
+        None => return Incomplete,
+    };
+    let is_visual = mode == Mode::Visual || mode == Mode::VisualLine;
+    let command = match key {
+        'g' => match chars.next() {
+            Some('g') if !is_visual && count.is_some() => GotoLine(count.unwrap() - 1),
+            Some('g') => Move(Movement::StartOfDocument),
+            Some(_) => return Invalid,
+            None => return Incomplete,
+        },
+        'G' if !is_visual && count.is_some() => GotoLine(count.unwrap() - 1),
+        'G' => Move(Movement::EndOfDocument),
+        'v' if mode == Mode::Visual => SetMode(Mode::Normal),
+        'v' => SetMode(Mode::Visual),
+        'V' if mode == Mode::VisualLine => SetMode(Mode::Normal),
+        'V' => SetMode(Mode::VisualLine),
+        'J' => JoinLines,
+        'i' | 'a' if is_visual => match parse_object(rest) {
+            Ok(object) => SelectObject(object),
+            Err(parse) => return parse,
+        },
+        'd' | 'x' if is_visual => Operate(Operator::Delete, Target::Selection),
+        'c' | 's' if is_visual => Operate(Operator::Change, Target::Selection),
+        'y' if is_visual => Operate(Operator::Yank, Target::Selection),
+        '>' if is_visual => Operate(Operator::Indent, Target::Selection),
+        '<' if is_visual => Operate(Operator::Outdent, Target::Selection),
+        _ if is_visual => match motion(key) {
+            Some(movement) => Move(movement),
+            None => return Invalid,
+        },
+        'x' => Operate(Operator::Delete, Target::Motion(Movement::Right)),
+        'X' => Operate(Operator::Delete, Target::Motion(Movement::Left)),
+        's' => Operate(Operator::Change, Target::Motion(Movement::Right)),
+        'D' => Operate(Operator::Delete, Target::Motion(Movement::EndOfParagraph)),
+        'C' => Operate(Operator::Change, Target::Motion(Movement::EndOfParagraph)),
+        'S' => Operate(Operator::Change, Target::Object(TextObject::Lines { inner: true })),
+        'Y' => Operate(Operator::Yank, Target::Object(TextObject::Lines { inner: false })),
+        'i' => Insert(InsertPosition::Cursor),
+        'a' => Insert(InsertPosition::Append),
+        'I' => Insert(InsertPosition::LineStart),
+        'A' => Insert(InsertPosition::LineEnd),
+        'o' => Insert(InsertPosition::LineBelow),
+        'O' => Insert(InsertPosition::LineAbove),
+        'p' => Put { before: false },
+        'P' => Put { before: true },
+        'u' => Undo,
+        '.' => Repeat,
+        _ => match (operator(key), motion(key)) {
+            (Some(operator), _) => {
+                // a count may also be typed between an operator and its target
+                let (target_count, rest) = split_count(chars.as_str());
+                let count = count.unwrap_or(1).saturating_mul(target_count.unwrap_or(1));
+                let count = min(count, MAX_COUNT);
+                let target = match parse_target(key, operator, rest) {
+                    Ok(target) => target,
+                    Err(parse) => return parse,
+                };
+                return Complete(count, Operate(operator, target));
+            }
+            (None, Some(movement)) => Move(movement),
+            (None, None) => return Invalid,
+        },
+    };
+    let count = match command {
+        GotoLine(_) => 1,
+        _ => count.unwrap_or(1),
+    };
+    Complete(count, command)
+}
+
+/// Parses the target of the operator typed with `key`.
+fn parse_target(key: char, operator: Operator, keys: &str) -> Result<Target, Parse> {
+    let mut chars = keys.chars();
+    let target = match chars.next() {
+        None => return Err(Parse::Incomplete),
+        // a doubled operator acts on whole lines
+        Some(k) if k == key => {
+            let inner = operator.takes_inner_lines();
+            Target::Object(TextObject::Lines { inner })
+        }
+        Some('i') | Some('a') => Target::Object(parse_object(keys)?),
+        Some('g') => match chars.next() {
+            Some('g') => Target::Motion(Movement::StartOfDocument),
+            Some(_) => return Err(Parse::Invalid),
+            None => return Err(Parse::Incomplete),
+        },
+        Some('G') => Target::Motion(Movement::EndOfDocument),
+        Some(k) => match motion(k) {
+            Some(movement) => Target::Motion(movement),
+            None => return Err(Parse::Invalid),
+        },
+    };
+    Ok(target)
+}
+
+/// Parses a text object: `i` or `a`, for the inner or outer object, and the
+/// key of its kind.
+fn parse_object(keys: &str) -> Result<TextObject, Parse> {
+    let mut chars = keys.chars();
+    let inner = match chars.next() {
+        Some('i') => true,
+        Some('a') => false,
+        Some(_) => return Err(Parse::Invalid),
+        None => return Err(Parse::Incomplete),
+    };
+    let kind = match chars.next() {
+        Some('w') => TextObjectKind::Word,
+        Some('(') | Some(')') | Some('b') => TextObjectKind::Parentheses,
+        Some('[') | Some(']') => TextObjectKind::Brackets,
+        Some('{') | Some('}') | Some('B') => TextObjectKind::Braces,
+        Some('<') | Some('>') => TextObjectKind::AngleBrackets,
+        Some('"') => TextObjectKind::DoubleQuotes,
+        Some('\'') => TextObjectKind::SingleQuotes,
+        Some('`') => TextObjectKind::Backticks,
+        Some('p') => TextObjectKind::Paragraph,
+        Some('i') => TextObjectKind::IndentBlock,
+        Some(_) => return Err(Parse::Invalid),
+        None => return Err(Parse::Incomplete),
+    };
+    Ok(TextObject::new(kind, inner))
+}
+
+/// Splits a count, which doesn't start with `0`, from the start of `keys`.
+/// Counts larger than `MAX_COUNT` are clamped to it.
+fn split_count(keys: &str) -> (Option<usize>, &str) {
+    let len = keys
+        .char_indices()
+        .find(|&(i, c)| !c.is_ascii_digit() || (i == 0 && c == '0'))
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| keys.len());
+    if len == 0 {
+        return (None, keys);
+    }
+    // the digits only fail to parse if they overflow
+    let count = keys[..len].parse().unwrap_or(MAX_COUNT);
+    (Some(min(count, MAX_COUNT)), &keys[len..])
+}
+
+fn operator(key: char) -> Option<Operator> {
+    match key {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        '>' => Some(Operator::Indent),
+        '<' => Some(Operator::Outdent),
+        _ => None,
+    }
+}
+
+fn motion(key: char) -> Option<Movement> {
+    match key {
+        'h' => Some(Movement::Left),
+        'l' => Some(Movement::Right),
+        'j' => Some(Movement::Down),
+        'k' => Some(Movement::Up),
+        'w' => Some(Movement::NextWordStart),
+        'e' => Some(Movement::RightWord),
+        'b' => Some(Movement::LeftWord),
+        '0' => Some(Movement::StartOfParagraph),
+        '^' => Some(Movement::FirstNonBlank),
+        '$' => Some(Movement::EndOfParagraph),
+        '%' => Some(Movement::MatchingBracket),
+        _ => None,
+    }
+}
+
+/// Whether an operator acts on the whole lines that `movement` moves over.
+pub fn is_linewise(movement: Movement) -> bool {
+    match movement {
+        Movement::Up | Movement::Down | Movement::StartOfDocument | Movement::EndOfDocument => true,
+        _ => false,
+    }
+}
+
+/// Returns the region of `object` around `region`, or `None` if there is no
+/// such object, such as a pair of brackets, around it.
+pub fn text_object_region(
+    text: &Rope,
+    region: SelRegion,
+    object: TextObject,
+    count: usize,
+) -> Option<SelRegion> {
+    let region = match object {
+        TextObject::Lines { inner } => {
+            let first = text.line_of_offset(region.min());
+            let last = max(text.line_of_offset(region.max()), first + count.max(1) - 1);
+            lines_region(text, first, last, inner)
+        }
+        TextObject::Word { inner } => {
+            let (start, end) = WordCursor::new(text, region.end).select_word();
+            if inner {
+                return Some(SelRegion::new(start, end));
+            }
+            let is_blank = |c: char| c == ' ' || c == '\t';
+            let after = text.slice_to_cow(end..content_end(text, text.line_of_offset(end)));
+            let trailing = after.len() - after.trim_start_matches(is_blank).len();
+            if trailing > 0 {
+                return Some(SelRegion::new(start, end + trailing));
+            }
+            let before = text.slice_to_cow(text.offset_of_line(text.line_of_offset(start))..start);
+            let leading = before.len() - before.trim_end_matches(is_blank).len();
+            SelRegion::new(start - leading, end)
+        }
+        TextObject::Pair { open, close, inner } => {
+            return pair_region(text, region, (open, close), inner, count);
+        }
+        TextObject::Quotes { quote, inner } => return quotes_region(text, region, quote, inner),
+        TextObject::Paragraph { inner } => {
+            let n_lines = text.measure::<LinesMetric>() + 1;
+            let is_blank = |line| line_indent(text, line).is_none();
+            let block_end = |mut line| {
+                while line + 1 < n_lines && is_blank(line + 1) == is_blank(line) {
+                    line += 1;
+                }
+                line
+            };
+            let (mut first, last) = region_lines(text, region);
+            while first > 0 && is_blank(first - 1) == is_blank(first) {
+                first -= 1;
+            }
+            let mut last = block_end(last);
+            for _ in 1..count {
+                if last + 1 < n_lines {
+                    last = block_end(last + 1);
+                }
+            }
+            if !inner {
+                if last + 1 < n_lines {
+                    last = block_end(last + 1);
+                } else {
+                    while first > 0 && is_blank(first - 1) != is_blank(last) {
+                        first -= 1;
+                    }
+                }
+            }
+            lines_region(text, first, last, false)
+        }
+        TextObject::IndentBlock { inner } => {
+            let (_, last) = region_lines(text, region);
+            let start = Interval::new(region.min(), min(region.max(), end_of_line(text, last)));
+            let mut block = start;
+            for _ in 0..count.max(1) {
+                match indent_block(text, block) {
+                    Some(iv) => block = iv,
+                    None => break,
+                }
+            }
+            if block == start {
+                return None;
+            }
+            let mut first = text.line_of_offset(block.start());
+            let last = text.line_of_offset(block.end());
+            if !inner {
+                first = (0..first)
+                    .rev()
+                    .find(|&line| line_indent(text, line).is_some())
+                    .unwrap_or(first);
+            }
+            lines_region(text, first, last, false)
+        }
+    };
+    Some(region)
+}
+
+/// Returns the region of the lines from `first` to `last`. Inner lines don't
+/// include the last line ending.
+fn lines_region(text: &Rope, first: usize, last: usize, inner: bool) -> SelRegion {
+    if last >= text.line_of_offset(text.len()) {
+        // there is no line ending after the last line, so take the one
+        // before the first line instead
+        let start = if inner || first == 0 {
+            text.offset_of_line(first)
+        } else {
+            content_end(text, first - 1)
+        };
+        SelRegion::new(start, text.len())
+    } else if inner {
+        SelRegion::new(text.offset_of_line(first), content_end(text, last))
+    } else {
+        SelRegion::new(text.offset_of_line(first), text.offset_of_line(last + 1))
+    }
+}
+
+/// Returns the first and last lines of `region`, where a region ending at the
+/// start of a line doesn't include that line.
+fn region_lines(text: &Rope, region: SelRegion) -> (usize, usize) {
+    let first = text.line_of_offset(region.min());
+    let last = text.line_of_offset(region.max());
+    if last > first && text.offset_of_line(last) == region.max() {
+        (first, last - 1)
+    } else {
+        (first, last)
+    }
+}
+
+/// Returns the `count`th pair of `open` and `close` characters whose contents
+/// enclose `region`, or those contents. A cursor on an opening character is
+/// inside its pair.
+fn pair_region(
+    text: &Rope,
+    region: SelRegion,
+    (open, close): (char, char),
+    inner: bool,
+    count: usize,
+) -> Option<SelRegion> {
+    let (min, max) = (region.min(), region.max());
+    let mut offset = min;
+    if region.is_caret() && Cursor::new(text, min).next_codepoint() == Some(open) {
+        offset += open.len_utf8();
+    }
+    let mut found = 0;
+    loop {
+        let open_start = unmatched(text, offset, (open, close), false)?;
+        let close_start = unmatched(text, open_start + open.len_utf8(), (close, open), true)?;
+        let (start, end) = if inner {
+            pair_contents(text, open_start + open.len_utf8(), close_start)
+        } else {
+            (open_start, close_start + close.len_utf8())
+        };
+        // a pair that is already selected grows to the next one
+        if close_start >= max && (start, end) != (min, max) {
+            found += 1;
+            if found >= count {
+                return Some(SelRegion::new(start, end));
+            }
+        }
+        offset = open_start;
+    }
+}
+
+/// Returns the contents of a pair between `start` and `end`. When the pair
+/// spans lines, and its characters are the last and first on their lines,
+/// the contents are the whole lines between them.
+fn pair_contents(text: &Rope, start: usize, end: usize) -> (usize, usize) {
+    let first = text.line_of_offset(start);
+    let last = text.line_of_offset(end);
+    let after_start = text.slice_to_cow(start..content_end(text, first));
+    let before_end = text.slice_to_cow(text.offset_of_line(last)..end);
+    if first < last && after_start.trim().is_empty() && before_end.trim().is_empty() {
+        (text.offset_of_line(first + 1), text.offset_of_line(last))
+    } else {
+        (start, end)
+    }
+}
+
+/// Finds the first unmatched `target` character before or after `offset`,
+/// where `other` is the character matching it, skipping escaped characters.
+fn unmatched(
+    text: &Rope,
+    offset: usize,
+    (target, other): (char, char),
+    forward: bool,
+) -> Option<usize> {
+    let mut cursor = Cursor::new(text, offset);
+    let mut depth = 0;
+    loop {
+        let (c, pos) = if forward {
+            let pos = cursor.pos();
+            (cursor.next_codepoint()?, pos)
+        } else {
+            let c = cursor.prev_codepoint()?;
+            (c, cursor.pos())
+        };
+        if (c != target && c != other) || is_escaped(text, pos) {
+            continue;
+        }
+        if c == other {
+            depth += 1;
+        } else if depth == 0 {
+            return Some(pos);
+        } else {
+            depth -= 1;
+        }
+    }
+}
+
+/// Returns whether the character at `offset` follows an odd number of
+/// backslashes.
+fn is_escaped(text: &Rope, offset: usize) -> bool {
+    let mut cursor = Cursor::new(text, offset);
+    let mut backslashes = 0;
+    while cursor.prev_codepoint() == Some('\\') {
+        backslashes += 1;
+    }
+    backslashes % 2 == 1
+}
+
+/// Returns the string between `quote` characters on the line of `region`
+/// that encloses it, or else the first one after it, or its contents.
+fn quotes_region(text: &Rope, region: SelRegion, quote: char, inner: bool) -> Option<SelRegion> {
+    let line = text.line_of_offset(region.min());
+    let line_start = text.offset_of_line(line);
+    let line_str = text.slice_to_cow(line_start..content_end(text, line));
+    let quotes = line_str
+        .char_indices()
+        .filter(|&(i, c)| {
+            let backslashes = line_str[..i].chars().rev().take_while(|&c| c == '\\').count();
+            c == quote && backslashes % 2 == 0
+        })
+        .map(|(i, _)| line_start + i)
+        .collect::<Vec<_>>();
+    let len = quote.len_utf8();
+    let (min, max) = (region.min(), region.max());
+    let mut pairs = quotes.chunks(2).filter(|pair| pair.len() == 2);
+    let (start, end) = pairs
+        .clone()
+        .find(|pair| pair[0] <= min && pair[1] + len >= max)
+        .or_else(|| pairs.find(|pair| pair[0] >= max))
+        .map(|pair| (pair[0], pair[1]))?;
+    if inner {
+        Some(SelRegion::new(start + len, end))
+    } else {
+        Some(SelRegion::new(start, end + len))
+    }
+}
+
+/// Returns the selection of visual line mode, which spans the lines from
+/// `anchor_line` to the line of `head`, and ends on the side of `head`.
+pub fn visual_line_region(text: &Rope, anchor_line: usize, head: usize) -> SelRegion {
+    let head_line = text.line_of_offset(head);
+    let line_end = |line| {
+        if line < text.line_of_offset(text.len()) {
+            text.offset_of_line(line + 1)
+        } else {
+            text.len()
+        }
+    };
+    if head_line < anchor_line {
+        SelRegion::new(line_end(anchor_line), text.offset_of_line(head_line))
+    } else {
+        SelRegion::new(text.offset_of_line(anchor_line), line_end(head_line))
+    }
+}
+
+/// Returns where a caret at `offset` rests in normal mode: on the last
+/// character of its line rather than past it, unless the line is empty.
+pub fn normal_mode_caret(text: &Rope, offset: usize) -> usize {
+    let line = text.line_of_offset(offset);
+    if offset == end_of_line(text, line) && offset > text.offset_of_line(line) {
+        text.prev_grapheme_offset(offset).unwrap_or(offset)
+    } else {
+        offset
+    }
+}
+
+/// Returns the lines of killed text that ends or starts with a line ending,
+/// without that line ending, or `None` if the text isn't whole lines.
+pub fn killed_lines(text: &str) -> Option<&str> {
+    if text.ends_with("\r\n") {
+        Some(&text[..text.len() - 2])
+    } else if text.ends_with('\n') {
+        Some(&text[..text.len() - 1])
+    } else if text.starts_with("\r\n") {
+        Some(&text[2..])
+    } else if text.starts_with('\n') {
+        Some(&text[1..])
+    } else {
+        None
+    }
+}
+
+/// Returns the offset of the end of `line`, before its line ending.
+fn content_end(text: &Rope, line: usize) -> usize {
+    let start = text.offset_of_line(line);
+    start + text.lines(start..).next().map_or(0, |line| line.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(keys: &str, mode: Mode) -> Option<(usize, Command)> {
+        match parse(keys, mode) {
+            Parse::Complete(count, command) => Some((count, command)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        use self::Command::*;
+        let normal = Mode::Normal;
+        assert_eq!(parse("", normal), Parse::Incomplete);
+        assert_eq!(parse("2", normal), Parse::Incomplete);
+        assert_eq!(parse("d2", normal), Parse::Incomplete);
+        assert_eq!(parse("di", normal), Parse::Incomplete);
+        assert_eq!(parse("dq", normal), Parse::Invalid);
+        assert_eq!(parse("Q", normal), Parse::Invalid);
+        assert_eq!(complete("j", normal), Some((1, Move(Movement::Down))));
+        assert_eq!(complete("0", normal), Some((1, Move(Movement::StartOfParagraph))));
+        assert_eq!(complete("^", normal), Some((1, Move(Movement::FirstNonBlank))));
+        assert_eq!(complete("10l", normal), Some((10, Move(Movement::Right))));
+        assert_eq!(complete("12G", normal), Some((1, GotoLine(11))));
+        assert_eq!(complete("G", normal), Some((1, Move(Movement::EndOfDocument))));
+        let lines = Target::Object(TextObject::Lines { inner: false });
+        assert_eq!(complete("2d3d", normal), Some((6, Operate(Operator::Delete, lines))));
+        let word = Target::Object(TextObject::Word { inner: true });
+        assert_eq!(complete("ciw", normal), Some((1, Operate(Operator::Change, word))));
+        let parens = Target::Object(TextObject::Pair { open: '(', close: ')', inner: false });
+        assert_eq!(complete("2dab", normal), Some((2, Operate(Operator::Delete, parens))));
+        let quotes = Target::Object(TextObject::Quotes { quote: '"', inner: true });
+        assert_eq!(complete("ci\"", normal), Some((1, Operate(Operator::Change, quotes))));
+        assert_eq!(parse("dix", normal), Parse::Invalid);
+        let motion = Target::Motion(Movement::EndOfParagraph);
+        assert_eq!(complete("y$", normal), Some((1, Operate(Operator::Yank, motion))));
+        assert_eq!(complete("3.", normal), Some((3, Repeat)));
+        // counts are clamped, including when they are multiplied
+        let huge = "99999999999999999999999";
+        assert_eq!(complete(&format!("{}u", huge), normal), Some((MAX_COUNT, Undo)));
+        let motion = Target::Motion(Movement::NextWordStart);
+        let delete = Some((MAX_COUNT, Operate(Operator::Delete, motion)));
+        assert_eq!(complete(&format!("{}d{}w", huge, huge), normal), delete);
+        assert_eq!(complete("200d200w", normal), delete);
+
+        let visual = Mode::Visual;
+        assert_eq!(complete("x", visual), Some((1, Operate(Operator::Delete, Target::Selection))));
+        assert_eq!(complete("v", visual), Some((1, SetMode(Mode::Normal))));
+        assert_eq!(complete("V", visual), Some((1, SetMode(Mode::VisualLine))));
+        assert_eq!(complete("5G", visual), Some((5, Move(Movement::EndOfDocument))));
+        assert_eq!(parse("i", visual), Parse::Incomplete);
+        assert_eq!(parse("iq", visual), Parse::Invalid);
+        let block = TextObject::IndentBlock { inner: false };
+        assert_eq!(complete("ai", visual), Some((1, SelectObject(block))));
+    }
+
+    #[test]
+    fn push_keys() {
+        let mut state = ModalState::new();
+        assert_eq!(state.push_key('d'), None);
+        assert_eq!(state.push_key('q'), None);
+        assert_eq!(
+            state.push_key('x'),
+            Some((1, Command::Operate(Operator::Delete, Target::Motion(Movement::Right))))
+        );
+        assert_eq!(state.push_key('d'), None);
+        assert!(state.set_mode(Mode::Insert));
+        assert!(!state.set_mode(Mode::Insert));
+        assert!(!state.clear_keys());
+    }
+
+    #[test]
+    fn text_objects() {
+        let text = Rope::from("one two  three\nfour\nfive");
+        let region = |offset, object, count| {
+            let region =
+                text_object_region(&text, SelRegion::caret(offset), object, count).unwrap();
+            (region.start, region.end)
+        };
+        let lines = TextObject::Lines { inner: false };
+        let inner_lines = TextObject::Lines { inner: true };
+        assert_eq!(region(2, lines, 1), (0, 15));
+        assert_eq!(region(2, lines, 2), (0, 20));
+        assert_eq!(region(2, inner_lines, 2), (0, 19));
+        // the last line takes the line ending before it
+        assert_eq!(region(17, lines, 5), (14, 24));
+        assert_eq!(region(22, inner_lines, 1), (20, 24));
+        let word = TextObject::Word { inner: true };
+        let a_word = TextObject::Word { inner: false };
+        assert_eq!(region(5, word, 1), (4, 7));
+        assert_eq!(region(5, a_word, 1), (4, 9));
+        assert_eq!(region(10, a_word, 1), (7, 14));
+    }
+
+    fn object_text(text: &str, start: usize, end: usize, object: TextObject, count: usize) -> &str {
+        let region =
+            text_object_region(&Rope::from(text), SelRegion::new(start, end), object, count);
+        region.map_or("", |region| &text[region.min()..region.max()])
+    }
+
+    #[test]
+    fn pair_objects() {
+        let text = "f(a, g(b), \"c\\)\")";
+        let parens = TextObject::Pair { open: '(', close: ')', inner: true };
+        let a_parens = TextObject::Pair { open: '(', close: ')', inner: false };
+        assert_eq!(object_text(text, 7, 7, parens, 1), "b");
+        assert_eq!(object_text(text, 7, 7, a_parens, 1), "(b)");
+        assert_eq!(object_text(text, 7, 7, parens, 2), "a, g(b), \"c\\)\"");
+        // a cursor on a bracket is inside its pair
+        assert_eq!(object_text(text, 6, 6, a_parens, 1), "(b)");
+        assert_eq!(object_text(text, 8, 8, a_parens, 1), "(b)");
+        // a selected pair grows to the next one
+        assert_eq!(object_text(text, 7, 8, parens, 1), "a, g(b), \"c\\)\"");
+        // escaped brackets don't count
+        assert_eq!(object_text("[a\\]b]", 1, 1, parens, 1), "");
+        let brackets = TextObject::Pair { open: '[', close: ']', inner: true };
+        assert_eq!(object_text("[a\\]b]", 1, 1, brackets, 1), "a\\]b");
+        let braces = TextObject::Pair { open: '{', close: '}', inner: true };
+        assert_eq!(object_text("if x {\n    y\n}\n", 10, 10, braces, 1), "    y\n");
+        assert_eq!(object_text("{ y }", 2, 2, braces, 1), " y ");
+    }
+
+    #[test]
+    fn quote_objects() {
+        let text = r#"a "b \"c\" d" 'e' "f""#;
+        let quotes = TextObject::Quotes { quote: '"', inner: true };
+        let a_quotes = TextObject::Quotes { quote: '"', inner: false };
+        assert_eq!(object_text(text, 4, 4, quotes, 1), r#"b \"c\" d"#);
+        assert_eq!(object_text(text, 8, 8, a_quotes, 1), r#""b \"c\" d""#);
+        assert_eq!(object_text(text, 2, 2, quotes, 1), r#"b \"c\" d"#);
+        // a cursor outside of strings takes the next one on its line
+        assert_eq!(object_text(text, 0, 0, quotes, 1), r#"b \"c\" d"#);
+        assert_eq!(object_text(text, 16, 16, quotes, 1), "f");
+        let single = TextObject::Quotes { quote: '\'', inner: true };
+        assert_eq!(object_text(text, 15, 15, single, 1), "e");
+        // a cursor on a closing quote is inside its string
+        assert_eq!(object_text(text, 16, 16, single, 1), "e");
+    }
+
+    #[test]
+    fn block_objects() {
+        let text = "a\nb\n\n\nc\n  d\n\n  e\nf";
+        let paragraph = TextObject::Paragraph { inner: true };
+        let a_paragraph = TextObject::Paragraph { inner: false };
+        assert_eq!(object_text(text, 2, 2, paragraph, 1), "a\nb\n");
+        assert_eq!(object_text(text, 2, 2, a_paragraph, 1), "a\nb\n\n\n");
+        assert_eq!(object_text(text, 5, 5, paragraph, 1), "\n\n");
+        assert_eq!(object_text(text, 0, 0, paragraph, 3), "a\nb\n\n\nc\n  d\n");
+        // the last paragraph takes the blank lines before it
+        assert_eq!(object_text(text, 17, 17, a_paragraph, 1), "\n\n  e\nf");
+
+        let block = TextObject::IndentBlock { inner: true };
+        let a_block = TextObject::IndentBlock { inner: false };
+        assert_eq!(object_text(text, 11, 11, block, 1), "  d\n\n  e\n");
+        assert_eq!(object_text(text, 11, 11, a_block, 1), "c\n  d\n\n  e\n");
+        assert_eq!(object_text(text, 11, 11, block, 2), text);
+        assert_eq!(object_text(text, 8, 17, block, 1), text);
+        assert_eq!(object_text(text, 5, 5, block, 1), "");
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of the Vim and Emacs modelines that set per-file editor options,
+//! such as `vim: set ts=2 sw=2 et:` or `-*- mode: python; tab-width: 4 -*-`.
+
+use std::borrow::Cow;
+use std::cmp::{max, min};
+
+use regex::Regex;
+use serde_json::Value;
+use xi_rope::{LinesMetric, Rope};
+
+use config::Table;
+
+/// The number of lines at the start and at the end of a file that are
+/// searched for Vim modelines, as with Vim's default `modelines` option.
+const MODELINE_LINES: usize = 5;
+
+/// The number of bytes at the end of a file that are searched for an Emacs
+/// `Local Variables:` list.
+const LOCAL_VARIABLES_LEN: usize = 3000;
+
+lazy_static! {
+    static ref VIM_MODELINE: Regex =
+        Regex::new(r"(?:^|\s)(?:vi|[Vv]im[<=>]?\d*|ex):\s*(.*)$").unwrap();
+    static ref EMACS_MODELINE: Regex = Regex::new(r"-\*-(.*?)-\*-").unwrap();
+}
+
+/// The settings from a file's modelines. Where a setting is given by more
+/// than one modeline, the last one in the file is used.
+#[derive(Debug, Default, PartialEq)]
+pub struct Modeline {
+    pub tab_size: Option<usize>,
+    pub translate_tabs_to_spaces: Option<bool>,
+    /// The Vim filetype or the Emacs major mode, such as `python`.
+    pub mode: Option<String>,
+}
+
+/// The options set by a single Vim modeline.
+#[derive(Debug, Default)]
+struct VimOptions {
+    tabstop: Option<usize>,
+    shiftwidth: Option<usize>,
+    softtabstop: Option<usize>,
+    expandtab: Option<bool>,
+    filetype: Option<String>,
+}
+
+impl Modeline {
+    /// Parses the Vim modelines in the first and last lines of `text`, and
+    /// the Emacs modeline in its first line, or its second line following a
+    /// `#!` line, and the Emacs `Local Variables:` list at its end.
+    pub fn parse(text: &Rope) -> Modeline {
+        let mut modeline = Modeline::default();
+        let n_lines = text.measure::<LinesMetric>() + 1;
+
+        let head_end = text.offset_of_line(min(MODELINE_LINES, n_lines));
+        let mut shebang = false;
+        for (i, line) in text.lines(..head_end).enumerate() {
+            if i == 0 {
+                shebang = line.starts_with("#!");
+            }
+            if i == 0 || (i == 1 && shebang) {
+                modeline.parse_emacs_line(&line);
+            }
+            modeline.parse_vim_line(&line);
+        }
+
+        let tail_start = max(MODELINE_LINES, n_lines.saturating_sub(MODELINE_LINES));
+        let tail_start = text.offset_of_line(min(tail_start, n_lines));
+        for line in text.lines(tail_start..) {
+            modeline.parse_vim_line(&line);
+        }
+
+        let local_variables_start = text.len().saturating_sub(LOCAL_VARIABLES_LEN);
+        let local_variables_start = text.offset_of_line(text.line_of_offset(local_variables_start));
+        modeline.parse_local_variables(text.lines(local_variables_start..));
+        modeline
+    }
+
+    /// Returns the settings of the buffer config that are set by the
+    /// modelines.
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        if let Some(tab_size) = self.tab_size {
+            table.insert("tab_size".into(), Value::from(tab_size));
+        }
+        if let Some(translate) = self.translate_tabs_to_spaces {
+            table.insert("translate_tabs_to_spaces".into(), Value::from(translate));
+        }
+        table
+    }
+
+    fn parse_vim_line(&mut self, line: &str) {
+        let options = match VIM_MODELINE.captures(line) {
+            Some(captures) => captures.get(1).unwrap().as_str(),
+            None => return,
+        };
+        let mut vim = VimOptions::default();
+        match strip_set(options) {
+            // `set` modelines end at the first unescaped ':'
+            Some(options) => {
+                let end = options
+                    .char_indices()
+                    .find(|&(i, c)| c == ':' && !options[..i].ends_with('\\'))
+                    .map(|(i, _)| i);
+                match end {
+                    Some(end) => vim.parse(options[..end].split_whitespace()),
+                    None => return,
+                }
+            }
+            None => vim.parse(options.split(|c: char| c == ':' || c.is_whitespace())),
+        }
+        self.apply_vim(vim);
+    }
+
+    fn apply_vim(&mut self, vim: VimOptions) {
+        let indent = vim.shiftwidth.or(vim.softtabstop).filter(|&n| n > 0);
+        let tab_size = match vim.expandtab {
+            Some(true) => indent.or(vim.tabstop),
+            _ => vim.tabstop.or(indent),
+        };
+        self.tab_size = tab_size.filter(|&n| n > 0).or(self.tab_size);
+        self.translate_tabs_to_spaces = vim.expandtab.or(self.translate_tabs_to_spaces);
+        self.mode = vim.filetype.or_else(|| self.mode.take());
+    }
+
+    fn parse_emacs_line(&mut self, line: &str) {
+        let variables = match EMACS_MODELINE.captures(line) {
+            Some(captures) => captures.get(1).unwrap().as_str().trim(),
+            None => return,
+        };
+        if !variables.contains(':') {
+            self.mode = Some(variables.to_owned()).filter(|m| !m.is_empty());
+            return;
+        }
+        for variable in variables.split(';') {
+            self.parse_emacs_variable(variable);
+        }
+    }
+
+    /// Parses an Emacs `Local Variables:` list, such as
+    ///
+    /// ```text
+    /// # Local Variables:
+    /// # tab-width: 4
+    /// # End:
+    /// ```
+    fn parse_local_variables<'a, I>(&mut self, lines: I)
+    where
+        I: Iterator<Item = Cow<'a, str>>,
+    {
+        let mut affixes: Option<(String, String)> = None;
+        for line in lines {
+            match affixes {
+                None => {
+                    if let Some(idx) = line.find("Local Variables:") {
+                        let suffix = line[idx + "Local Variables:".len()..].trim();
+                        affixes = Some((line[..idx].to_owned(), suffix.to_owned()));
+                    }
+                }
+                Some((ref prefix, ref suffix)) => {
+                    let variable = line.trim_end();
+                    let variable = variable.trim_start_matches(prefix.trim_end());
+                    let variable = variable.trim_end_matches(suffix.as_str()).trim();
+                    if variable == "End:" {
+                        return;
+                    }
+                    self.parse_emacs_variable(variable);
+                }
+            }
+        }
+    }
+
+    fn parse_emacs_variable(&mut self, variable: &str) {
+        let mut parts = variable.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = match parts.next() {
+            Some(value) => value.trim(),
+            None => return,
+        };
+        match name {
+            "mode" => {
+                let mode = value.trim_end_matches("-mode");
+                self.mode = Some(mode.to_owned()).filter(|m| !m.is_empty())
+            }
+            "tab-width" => self.tab_size = value.parse().ok().filter(|&n| n > 0).or(self.tab_size),
+            "indent-tabs-mode" => self.translate_tabs_to_spaces = Some(value == "nil"),
+            _ => (),
+        }
+    }
+}
+
+impl VimOptions {
+    fn parse<'a, I: Iterator<Item = &'a str>>(&mut self, options: I) {
+        for option in options.filter(|o| !o.is_empty()) {
+            let mut parts = option.splitn(2, '=');
+            let name = parts.next().unwrap();
+            let value = parts.next();
+            let number = value.and_then(|v| v.parse().ok());
+            match name {
+                "ts" | "tabstop" => self.tabstop = number,
+                "sw" | "shiftwidth" => self.shiftwidth = number,
+                "sts" | "softtabstop" => self.softtabstop = number,
+                "et" | "expandtab" => self.expandtab = Some(true),
+                "noet" | "noexpandtab" => self.expandtab = Some(false),
+                "ft" | "filetype" | "syn" | "syntax" => {
+                    self.filetype = value.filter(|v| !v.is_empty()).map(str::to_owned)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Returns the options of a modeline of the form `vim: set ts=4:`, without
+/// the leading `set`.
+fn strip_set(options: &str) -> Option<&str> {
+    ["set ", "se "].iter().find(|s| options.starts_with(*s)).map(|s| &options[s.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Modeline {
+        Modeline::parse(&Rope::from(text))
+    }
+
+    #[test]
+    fn vim() {
+        let modeline = parse("/* vim: set ts=2 sw=2 et: */\nint x;\n");
+        assert_eq!(modeline.tab_size, Some(2));
+        assert_eq!(modeline.translate_tabs_to_spaces, Some(true));
+
+        let modeline = parse("# vim:ts=8:sw=4:noexpandtab:ft=python\n");
+        assert_eq!(modeline.tab_size, Some(8));
+        assert_eq!(modeline.translate_tabs_to_spaces, Some(false));
+        assert_eq!(modeline.mode.as_ref().map(String::as_str), Some("python"));
+
+        // options after the end of a `set` modeline are ignored
+        let modeline = parse("// vi: se sw=3 et: ts=9\n");
+        assert_eq!(modeline.tab_size, Some(3));
+        // text before the marker must be separated from it
+        assert_eq!(parse("// xvim: ts=3\n"), Modeline::default());
+    }
+
+    #[test]
+    fn head_and_tail() {
+        let mut lines = vec!["# vim: ts=3"];
+        lines.extend(vec!["x = 1"; 10]);
+        lines.push("# vim: et sw=2");
+        let modeline = parse(&lines.join("\n"));
+        assert_eq!(modeline.tab_size, Some(2));
+        assert_eq!(modeline.translate_tabs_to_spaces, Some(true));
+
+        // modelines in the middle of a file are ignored
+        lines.insert(6, "# vim: ft=rust");
+        assert_eq!(parse(&lines.join("\n")).mode, None);
+    }
+
+    #[test]
+    fn emacs() {
+        let modeline = parse("# -*- mode: python; indent-tabs-mode: nil; tab-width: 4 -*-\n");
+        assert_eq!(modeline.tab_size, Some(4));
+        assert_eq!(modeline.translate_tabs_to_spaces, Some(true));
+        assert_eq!(modeline.mode.as_ref().map(String::as_str), Some("python"));
+
+        let modeline = parse("#!/bin/sh\n# -*- shell-script -*-\n");
+        assert_eq!(modeline.mode.as_ref().map(String::as_str), Some("shell-script"));
+        assert_eq!(parse("\n\n# -*- python -*-\n").mode, None);
+    }
+
+    #[test]
+    fn local_variables() {
+        let text = "x = 1\n\n/* Local Variables: */\n/* indent-tabs-mode: t */\n\
+                    /* End: */\n/* tab-width: 2 */\n";
+        let modeline = parse(text);
+        assert_eq!(modeline.translate_tabs_to_spaces, Some(false));
+        assert_eq!(modeline.tab_size, None);
+    }
+
+    #[test]
+    fn to_table() {
+        let table = parse("// vim: ts=2 et ft=rust\n").to_table();
+        assert_eq!(table.get("tab_size"), Some(&json!(2)));
+        assert_eq!(table.get("translate_tabs_to_spaces"), Some(&json!(true)));
+        assert_eq!(table.len(), 2);
+    }
+}
@@ -16,9 +16,10 @@
 
 use std::cmp::max;
 
+use fold::{end_of_line, line_indent};
 use selection::{HorizPos, SelRegion, Selection};
 use view::View;
-use word_boundaries::WordCursor;
+use word_boundaries::{SubwordCursor, WordCursor};
 use xi_rope::{Cursor, LinesMetric, Rope};
 
 /// The specification of a movement.
@@ -32,6 +33,18 @@ pub enum Movement {
     LeftWord,
     /// Move to the right by one word.
     RightWord,
+    /// Move to the start of the next word, past any whitespace, as vi's `w`
+    /// does.
+    NextWordStart,
+    /// Move to the start of the next word on the same line, or else to the
+    /// end of the line. At the end of a line, move to the start of the next
+    /// word.
+    NextWordStartInLine,
+    /// Move to the left by one subword, such as the parts of a camelCase or
+    /// snake_case name.
+    LeftSubword,
+    /// Move to the right by one subword.
+    RightSubword,
     /// Move to left end of visible line.
     LeftOfLine,
     /// Move to right end of visible line.
@@ -50,6 +63,9 @@ pub enum Movement {
     DownExactPosition,
     /// Move to the start of the text line.
     StartOfParagraph,
+    /// Move to the first character of the text line that isn't whitespace,
+    /// as vi's `^` does.
+    FirstNonBlank,
     /// Move to the end of the text line.
     EndOfParagraph,
     /// Move to the end of the text line, or next line if already at end.
@@ -58,6 +74,9 @@ pub enum Movement {
     StartOfDocument,
     /// Move to the end of the document
     EndOfDocument,
+    /// Move to the bracket that matches the one next to the cursor, or to
+    /// the closing bracket of the pair enclosing it.
+    MatchingBracket,
 }
 
 /// Compute movement based on vertical motion by the given number of lines.
@@ -173,6 +192,7 @@ pub fn region_movement(
     r: SelRegion,
     view: &View,
     text: &Rope,
+    subword_separators: &str,
     modify: bool,
 ) -> SelRegion {
     let (offset, horiz) = match m {
@@ -208,6 +228,32 @@ pub fn region_movement(
             let offset = word_cursor.next_boundary().unwrap_or_else(|| text.len());
             (offset, None)
         }
+        Movement::NextWordStart => {
+            let mut word_cursor = WordCursor::new(text, r.end);
+            let offset = word_cursor.next_start().unwrap_or_else(|| text.len());
+            (offset, None)
+        }
+        Movement::NextWordStartInLine => {
+            let mut word_cursor = WordCursor::new(text, r.end);
+            let offset = word_cursor.next_start().unwrap_or_else(|| text.len());
+            // the end of the line, before its line ending
+            let line_end = r.end + text.lines(r.end..).next().map_or(0, |line| line.len());
+            if r.end < line_end && offset > line_end {
+                (line_end, None)
+            } else {
+                (offset, None)
+            }
+        }
+        Movement::LeftSubword => {
+            let mut cursor = SubwordCursor::new(text, r.end, subword_separators);
+            let offset = cursor.prev_boundary().unwrap_or(0);
+            (offset, None)
+        }
+        Movement::RightSubword => {
+            let mut cursor = SubwordCursor::new(text, r.end, subword_separators);
+            let offset = cursor.next_boundary().unwrap_or_else(|| text.len());
+            (offset, None)
+        }
         Movement::LeftOfLine => {
             let line = view.line_of_offset(text, r.end);
             let offset = view.offset_of_line(text, line);
@@ -236,6 +282,12 @@ pub fn region_movement(
             let offset = cursor.prev::<LinesMetric>().unwrap_or(0);
             (offset, None)
         }
+        Movement::FirstNonBlank => {
+            let line = text.line_of_offset(r.end);
+            let start = text.offset_of_line(line);
+            let indent = line_indent(text, line).unwrap_or(end_of_line(text, line) - start);
+            (start + indent, None)
+        }
         Movement::EndOfParagraph => {
             // Note: TextEdit would start at modify ? r.end : r.max()
             let mut offset = r.end;
@@ -271,7 +323,13 @@ pub fn region_movement(
         Movement::DownPage => vertical_motion(r, view, text, scroll_height(view), modify),
         Movement::StartOfDocument => (0, None),
         Movement::EndOfDocument => (text.len(), None),
+        Movement::MatchingBracket => match view.bracket_pair_at(r.end) {
+            Some(pair) => (pair.matching_offset(r.end), None),
+            None => (r.end, r.horiz),
+        },
     };
+    // never leave the cursor inside a folded region
+    let offset = view.skip_folded(offset, offset < r.end);
     SelRegion::new(if modify { r.start } else { offset }, offset).with_horiz(horiz)
 }
 
@@ -287,11 +345,12 @@ pub fn selection_movement(
     s: &Selection,
     view: &View,
     text: &Rope,
+    subword_separators: &str,
     modify: bool,
 ) -> Selection {
     let mut result = Selection::new();
     for &r in s.iter() {
-        let new_region = region_movement(m, r, view, text, modify);
+        let new_region = region_movement(m, r, view, text, subword_separators, modify);
         result.add_region(new_region);
     }
     result
@@ -28,6 +28,9 @@ pub(crate) struct Recorder {
     active_recording: Option<String>,
     recording_buffer: Vec<EventDomain>,
     recordings: HashMap<String, Recording>,
+    /// The last change of the modal input layer, which `.` repeats. It is
+    /// kept apart from the named recordings, which clients play and clear.
+    last_change: Option<Recording>,
 }
 
 impl Recorder {
@@ -36,6 +39,7 @@ impl Recorder {
             active_recording: None,
             recording_buffer: Vec::new(),
             recordings: HashMap::new(),
+            last_change: None,
         }
     }
 
@@ -128,6 +132,21 @@ impl Recorder {
         });
     }
 
+    /// Saves `events` as the last change, replacing the previous one.
+    pub(crate) fn save_last_change(&mut self, events: Vec<EventDomain>) {
+        self.last_change = Some(Recording::new(events));
+    }
+
+    /// Runs the specified action on each event of the last change.
+    pub(crate) fn play_last_change<F>(&self, action: F)
+    where
+        F: FnMut(&EventDomain) -> (),
+    {
+        if let Some(ref recording) = self.last_change {
+            recording.play(action);
+        }
+    }
+
     /// Completely removes the specified recording from the Recorder
     pub(crate) fn clear(&mut self, recording_name: &str) {
         self.recordings.remove(recording_name);
@@ -0,0 +1,198 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recovery files, which preserve the unsaved contents of buffers in case
+//! xi-core or the frontend crashes.
+//!
+//! The recovery file for a file lives in the recovery directory, and is
+//! named after the file's absolute path, with path separators escaped. An
+//! untitled buffer, which has no path, has a recovery file named after its
+//! untitled id instead. Unlike buffer ids, untitled ids are unique across
+//! runs of xi-core, so that a new untitled buffer is never offered the
+//! recovery file of an unrelated buffer from an earlier run.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use xi_rope::Rope;
+
+const RECOVERY_EXTENSION: &str = "xirecovery";
+
+static NEXT_UNTITLED_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The buffer whose unsaved contents a recovery file preserves.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A buffer backed by the file at this path.
+    File(PathBuf),
+    /// An untitled buffer, with its untitled id.
+    Untitled(String),
+}
+
+impl Source {
+    /// Returns the path of the file backing the buffer, if it has one.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            Source::File(ref path) => Some(path),
+            Source::Untitled(_) => None,
+        }
+    }
+}
+
+/// Returns a new untitled id, made of the time, the process id and a
+/// counter, so that it is unique across runs of xi-core.
+pub fn new_untitled_id() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let count = NEXT_UNTITLED_ID.fetch_add(1, Ordering::SeqCst);
+    format!("{}{:09}-{}-{}", now.as_secs(), now.subsec_nanos(), process::id(), count)
+}
+
+/// Returns the path of the recovery file for `source`.
+pub fn recovery_path(dir: &Path, source: &Source) -> PathBuf {
+    let mut name = match *source {
+        Source::File(ref path) => {
+            let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+            escape(&path.to_string_lossy())
+        }
+        // untitled ids are read back from session files, so they are escaped
+        // like paths to keep them inside the recovery directory
+        Source::Untitled(ref id) => format!("untitled-{}", escape(id)),
+    };
+    name.push('.');
+    name.push_str(RECOVERY_EXTENSION);
+    dir.join(name)
+}
+
+/// Escapes the characters of `s` that separate paths.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '/' => escaped.push_str("%2F"),
+            '\\' => escaped.push_str("%5C"),
+            ':' => escaped.push_str("%3A"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes `text`, the unsaved contents of `source`, to its recovery file.
+pub fn write(dir: &Path, source: &Source, text: &Rope) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let recovery_path = recovery_path(dir, source);
+    // write to a temporary file first, so that a crash while writing does
+    // not destroy a previous recovery file.
+    let tmp_path = recovery_path.with_extension("tmp");
+    let mut f = fs::File::create(&tmp_path)?;
+    for chunk in text.iter_chunks(..text.len()) {
+        f.write_all(chunk.as_bytes())?;
+    }
+    f.sync_all()?;
+    fs::rename(&tmp_path, &recovery_path)
+}
+
+/// Returns `true` if there is a recovery file for `source` that was written
+/// after its file was last modified. An untitled buffer has no file, so any
+/// recovery file for it is newer.
+pub fn has_newer(dir: &Path, source: &Source) -> bool {
+    let recovery_time = match fs::metadata(recovery_path(dir, source)).and_then(|m| m.modified()) {
+        Ok(time) => time,
+        Err(_) => return false,
+    };
+    let path = match source.path() {
+        Some(path) => path,
+        None => return true,
+    };
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(file_time) => recovery_time >= file_time,
+        Err(_) => true,
+    }
+}
+
+/// Loads the recovery file for `source`.
+pub fn load(dir: &Path, source: &Source) -> io::Result<Rope> {
+    fs::read_to_string(recovery_path(dir, source)).map(Rope::from)
+}
+
+/// Removes the recovery file for `source`, if there is one.
+pub fn remove(dir: &Path, source: &Source) {
+    let recovery_path = recovery_path(dir, source);
+    if recovery_path.exists() {
+        if let Err(e) = fs::remove_file(&recovery_path) {
+            warn!("failed to remove recovery file {:?}: {:?}", recovery_path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_fixtures::TempDir;
+
+    #[test]
+    fn escape_path() {
+        let dir = Path::new("recovery");
+        let path = recovery_path(dir, &Source::File("/no/such/100%.txt".into()));
+        assert_eq!(path, dir.join("%2Fno%2Fsuch%2F100%25.txt.xirecovery"));
+        let path = recovery_path(dir, &Source::Untitled("../3".into()));
+        assert_eq!(path, dir.join("untitled-..%2F3.xirecovery"));
+    }
+
+    #[test]
+    fn write_and_load() {
+        let temp_dir = TempDir::new("recovery");
+        let root = temp_dir.path();
+        let dir = root.join("recovery");
+        let path = root.join("file.txt");
+        fs::write(&path, "saved").unwrap();
+        let file = Source::File(path);
+        let untitled = Source::Untitled(new_untitled_id());
+
+        for source in &[file, untitled] {
+            assert!(!has_newer(&dir, source));
+            write(&dir, source, &Rope::from("unsaved")).unwrap();
+            assert!(has_newer(&dir, source));
+            assert_eq!(String::from(load(&dir, source).unwrap()), "unsaved");
+
+            remove(&dir, source);
+            assert!(!has_newer(&dir, source));
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn untitled_in_later_run() {
+        let temp_dir = TempDir::new("untitled-recovery");
+        let dir = temp_dir.path();
+        let earlier = Source::Untitled(new_untitled_id());
+        write(dir, &earlier, &Rope::from("earlier")).unwrap();
+
+        // a new untitled buffer in a later run is not offered the earlier
+        // buffer's recovery file, and does not overwrite it
+        let later = Source::Untitled(new_untitled_id());
+        assert_ne!(recovery_path(dir, &later), recovery_path(dir, &earlier));
+        assert!(!has_newer(dir, &later));
+        write(dir, &later, &Rope::from("later")).unwrap();
+
+        // while the earlier buffer, restored with its id, is
+        assert!(has_newer(dir, &earlier));
+        assert_eq!(String::from(load(dir, &earlier).unwrap()), "earlier");
+    }
+}
@@ -26,6 +26,7 @@ use serde::ser::{self, Serialize, Serializer};
 use serde_json::{self, Value};
 
 use config::{ConfigDomainExternal, Table};
+use file::CharacterEncoding;
 use plugins::PlaceholderRpc;
 use syntax::LanguageId;
 use tabs::ViewId;
@@ -177,8 +178,37 @@ pub enum CoreNotification {
     /// Tells `xi-core` to close the specified view.
     CloseView { view_id: ViewId },
     /// Tells `xi-core` to save the contents of the specified view's
-    /// buffer to the specified path.
-    Save { view_id: ViewId, file_path: String },
+    /// buffer to the specified path. If `encoding` is given, the file is
+    /// written (and subsequently saved) in that encoding; otherwise the
+    /// encoding the file was opened with is preserved. Unless `force` is
+    /// `true`, a file that has been changed on disk since it was loaded is
+    /// not overwritten.
+    Save {
+        view_id: ViewId,
+        file_path: String,
+        #[serde(default)]
+        encoding: Option<CharacterEncoding>,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Tells `xi-core` to reload the file backing the specified view,
+    /// decoding it with the given encoding.
+    ReopenWithEncoding { view_id: ViewId, encoding: CharacterEncoding },
+    /// Tells `xi-core` to replace the contents of the specified view's buffer
+    /// with those of the buffer's recovery file, after a `recovery_available`
+    /// notification.
+    RestoreRecovery { view_id: ViewId },
+    /// Tells `xi-core` to delete the recovery file of the buffer of the
+    /// specified view.
+    DiscardRecovery { view_id: ViewId },
+    /// Tells `xi-core` to save the open views, along with their selections,
+    /// scroll positions, find queries, languages and per-view settings, to
+    /// the session file at `file_path`, or to `session.json` in the config
+    /// directory.
+    SaveSession {
+        #[serde(default)]
+        file_path: Option<PathBuf>,
+    },
     /// Tells `xi-core` to set the theme.
     SetTheme { theme_name: String },
     /// Notifies `xi-core` that the client has started.
@@ -251,10 +281,23 @@ pub enum CoreRequest {
     NewView { file_path: Option<String> },
     /// Returns the current collated config object for the given view.
     GetConfig { view_id: ViewId },
+    /// Returns the schema of the known config keys, describing the type,
+    /// default value, allowed values and purpose of each.
+    GetConfigSchema {},
     /// Returns the contents of the buffer for a given `ViewId`.
     /// In the future this might also be used to return structured data (such
     /// as for printing).
     DebugGetContents { view_id: ViewId },
+    /// Opens the views saved by a `save_session` notification, skipping
+    /// files that no longer exist, and untitled views without a recovery
+    /// file. The session is read from `file_path`, or from `session.json`
+    /// in the config directory.
+    ///
+    /// Returns the new views, as a list of `view_id`, `file_path` pairs.
+    RestoreSession {
+        #[serde(default)]
+        file_path: Option<PathBuf>,
+    },
 }
 
 /// A helper type, which extracts the `view_id` field from edit
@@ -303,6 +346,7 @@ pub enum GestureType {
     WordSelect,
     MultiLineSelect,
     MultiWordSelect,
+    ColumnSelect,
 }
 
 /// An inclusive range.
@@ -352,6 +396,24 @@ impl Default for SelectionModifier {
     }
 }
 
+/// A kind of text object, for the `select_text_object` and
+/// `delete_text_object` commands.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TextObjectKind {
+    Word,
+    Line,
+    Parentheses,
+    Brackets,
+    Braces,
+    AngleBrackets,
+    DoubleQuotes,
+    SingleQuotes,
+    Backticks,
+    Paragraph,
+    IndentBlock,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct FindQuery {
@@ -376,12 +438,18 @@ pub enum EditNotification {
         chars: String,
     },
     Paste {
+        #[serde(default)]
         chars: String,
+        /// The register to paste from, instead of `chars`.
+        #[serde(default)]
+        register: Option<String>,
     },
     DeleteForward,
     DeleteBackward,
     DeleteWordForward,
     DeleteWordBackward,
+    DeleteSubwordForward,
+    DeleteSubwordBackward,
     DeleteToEndOfParagraph,
     DeleteToBeginningOfLine,
     InsertNewline,
@@ -402,6 +470,10 @@ pub enum EditNotification {
     MoveWordLeftAndModifySelection,
     MoveWordRight,
     MoveWordRightAndModifySelection,
+    MoveSubwordLeft,
+    MoveSubwordLeftAndModifySelection,
+    MoveSubwordRight,
+    MoveSubwordRightAndModifySelection,
     MoveToBeginningOfParagraph,
     MoveToBeginningOfParagraphAndModifySelection,
     MoveToEndOfParagraph,
@@ -414,6 +486,9 @@ pub enum EditNotification {
     MoveToBeginningOfDocumentAndModifySelection,
     MoveToEndOfDocument,
     MoveToEndOfDocumentAndModifySelection,
+    /// Moves to the bracket matching the one next to each cursor.
+    MoveToMatchingBracket,
+    MoveToMatchingBracketAndModifySelection,
     ScrollPageUp,
     PageUpAndModifySelection,
     ScrollPageDown,
@@ -421,6 +496,10 @@ pub enum EditNotification {
     SelectAll,
     AddSelectionAbove,
     AddSelectionBelow,
+    ColumnSelectUp,
+    ColumnSelectDown,
+    ColumnSelectLeft,
+    ColumnSelectRight,
     Scroll(LineRange),
     Resize(Size),
     GotoLine {
@@ -428,6 +507,7 @@ pub enum EditNotification {
     },
     RequestLines(LineRange),
     Yank,
+    YankPop,
     Transpose,
     Click(MouseAction),
     Drag(MouseAction),
@@ -497,9 +577,69 @@ pub enum EditNotification {
         position: Option<Position>,
     },
     SelectionIntoLines,
+    ColumnSelectionIntoLines,
+    /// Selects the bracket pair around each cursor, including the brackets.
+    SelectMatchingBrackets,
+    /// Expands each selection region to the smallest syntactic unit around
+    /// it, such as a word, a string, the contents of brackets or a block.
+    ExpandSelection,
+    /// Restores the selection from before the last `ExpandSelection`.
+    ShrinkSelection,
+    /// Selects the text object around each selection region.
+    SelectTextObject {
+        object: TextObjectKind,
+        #[serde(default)]
+        inner: bool,
+    },
+    /// Deletes the text object around each selection region, saving it in
+    /// the kill ring.
+    DeleteTextObject {
+        object: TextObjectKind,
+        #[serde(default)]
+        inner: bool,
+    },
+    /// Folds the selected regions, or the syntax scopes or indentation
+    /// blocks starting on the lines of any carets.
+    Fold,
+    /// Removes any folds on the lines touched by the selection.
+    Unfold,
+    ToggleFold,
+    UnfoldAll,
     DuplicateLine,
     IncreaseNumber,
     DecreaseNumber,
+    ToggleComment,
+    ToggleBlockComment,
+    SortLines,
+    SortLinesCaseInsensitive,
+    SortLinesNatural,
+    UniqueLines,
+    ReverseLines,
+    JoinLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    /// Inserts a snippet in place of each selection region, and selects
+    /// its first tab stop.
+    InsertSnippet {
+        snippet: String,
+    },
+    /// Replaces the prefix of a snippet before the cursor with the snippet.
+    ExpandSnippet,
+    /// Selects the next tab stop of the inserted snippet.
+    NextTabStop,
+    /// Selects the previous tab stop of the inserted snippet.
+    PrevTabStop,
+    /// Requests the completions of the identifier before the cursor, which
+    /// are sent with the `completion_list` notification.
+    RequestCompletions {
+        request_id: usize,
+    },
+    /// Replaces the identifier before each cursor with one of the
+    /// completions sent for the request.
+    AcceptCompletion {
+        request_id: usize,
+        index: usize,
+    },
     ToggleRecording {
         recording_name: Option<String>,
     },
@@ -517,11 +657,19 @@ pub enum EditNotification {
 #[serde(tag = "method", content = "params")]
 pub enum EditRequest {
     /// Cuts the active selection, returning their contents,
-    /// or `Null` if the selection was empty.
-    Cut,
+    /// or `Null` if the selection was empty. The contents are
+    /// also saved in `register`, if given.
+    Cut {
+        #[serde(default)]
+        register: Option<String>,
+    },
     /// Copies the active selection, returning their contents or
-    /// or `Null` if the selection was empty.
-    Copy,
+    /// or `Null` if the selection was empty. The contents are
+    /// also saved in `register`, if given.
+    Copy {
+        #[serde(default)]
+        register: Option<String>,
+    },
 }
 
 /// The plugin related notifications.
@@ -0,0 +1,224 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Saving and restoring the views open in an editing session.
+
+use std::cmp::min;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use xi_rope::{LinesMetric, Rope};
+
+use config::Table;
+use rpc::{FindQuery, Position};
+use selection::{SelRegion, Selection};
+use syntax::LanguageId;
+use view::View;
+
+/// The views open in a session, in the order they were opened.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub views: Vec<ViewSession>,
+}
+
+/// The saved state of a single view, and of its buffer.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewSession {
+    /// The file the view displays, or `None` for an untitled buffer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<PathBuf>,
+    /// The untitled id of an untitled buffer, which names its recovery file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub untitled_id: Option<String>,
+    /// Selected regions, as positions in the text, ignoring folds.
+    pub selections: Vec<SavedRegion>,
+    /// The first visible line.
+    pub first_line: usize,
+    /// The language chosen by the user, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<LanguageId>,
+    /// The buffer's `ConfigDomain::UserOverride` table, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<Table>,
+    #[serde(default)]
+    pub find: Vec<FindQuery>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedRegion {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> io::Result<Session> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Saves the session to `path`.
+    ///
+    /// The session is written to a temporary file in the same directory,
+    /// which is then renamed over `path`, so that a failed save leaves the
+    /// previous session intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file_name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Path does not name a file")
+        })?;
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let result = File::create(&tmp_path).and_then(|f| {
+            let mut writer = BufWriter::new(f);
+            serde_json::to_writer_pretty(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            fs::rename(&tmp_path, path)
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+}
+
+impl ViewSession {
+    /// Records the state of `view`, which displays `text`, the contents of
+    /// the file at `file_path`, or of an untitled buffer if it is `None`.
+    pub fn new(file_path: Option<&Path>, view: &View, text: &Rope) -> ViewSession {
+        let selections = view
+            .sel_regions()
+            .iter()
+            .map(|r| SavedRegion {
+                start: position_of_offset(text, r.start),
+                end: position_of_offset(text, r.end),
+            })
+            .collect();
+        ViewSession {
+            file_path: file_path.map(Path::to_owned),
+            untitled_id: None,
+            selections,
+            first_line: view.first_line(),
+            language: None,
+            config: None,
+            find: view.find_queries(),
+        }
+    }
+
+    /// Restores the selections, scroll position and find state of `view`.
+    /// Positions past the end of a line, such as when the file has changed
+    /// since the session was saved, are moved to the end of the line.
+    pub fn restore_view(&self, view: &mut View, text: &Rope) {
+        let mut selection = Selection::new();
+        for region in &self.selections {
+            let start = offset_of_position(text, &region.start);
+            let end = offset_of_position(text, &region.end);
+            selection.add_region(SelRegion::new(start, end));
+        }
+        if !selection.is_empty() {
+            view.set_selection(text, selection);
+        }
+        if !self.find.is_empty() {
+            view.do_find(text, self.find.clone());
+        }
+        let first_line = min(self.first_line, text.measure::<LinesMetric>());
+        view.scroll_to_offset(text.offset_of_line(first_line));
+    }
+}
+
+fn position_of_offset(text: &Rope, offset: usize) -> Position {
+    let line = text.line_of_offset(offset);
+    Position { line, column: offset - text.offset_of_line(line) }
+}
+
+fn offset_of_position(text: &Rope, position: &Position) -> usize {
+    let line = min(position.line, text.measure::<LinesMetric>());
+    let line_start = text.offset_of_line(line);
+    let line_end = text.offset_of_line(line + 1);
+    let line_end = match text.prev_codepoint_offset(line_end) {
+        Some(prev) if prev >= line_start && text.byte_at(prev) == b'\n' => prev,
+        _ => line_end,
+    };
+    let mut offset = min(line_start + position.column, line_end);
+    while !text.is_codepoint_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tabs::{BufferId, ViewId};
+    use test_fixtures::TempDir;
+
+    #[test]
+    fn restore_selections() {
+        let text = Rope::from("one\ntwo\nthree\n");
+        let mut view = View::new(ViewId(1), BufferId(2));
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 2));
+        selection.add_region(SelRegion::new(12, 9));
+        view.set_selection(&text, selection);
+
+        let saved = ViewSession::new(Some(Path::new("file.txt")), &view, &text);
+        let json = serde_json::to_string(&saved).unwrap();
+        let saved: ViewSession = serde_json::from_str(&json).unwrap();
+        assert_eq!(saved.selections[1].start, Position { line: 2, column: 4 });
+
+        let mut view = View::new(ViewId(3), BufferId(4));
+        saved.restore_view(&mut view, &text);
+        let regions = view.sel_regions().iter().map(|r| (r.start, r.end)).collect::<Vec<_>>();
+        assert_eq!(regions, vec![(1, 2), (12, 9)]);
+
+        // the file was shortened since the session was saved
+        let text = Rope::from("on\nt");
+        let mut view = View::new(ViewId(5), BufferId(6));
+        saved.restore_view(&mut view, &text);
+        let regions = view.sel_regions().iter().map(|r| (r.start, r.end)).collect::<Vec<_>>();
+        assert_eq!(regions, vec![(1, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let temp_dir = TempDir::new("session");
+        let dir = temp_dir.path();
+        let path = dir.join("session.json");
+        fs::write(&path, "previous").unwrap();
+
+        let text = Rope::from("one\ntwo\n");
+        let view = View::new(ViewId(1), BufferId(2));
+        let mut untitled = ViewSession::new(None, &view, &text);
+        untitled.untitled_id = Some("1-2-3".into());
+        let views = vec![ViewSession::new(Some(Path::new("file.txt")), &view, &text), untitled];
+        let session = Session { views };
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap(), session);
+        // the temporary file was renamed over the previous session
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn clamp_positions() {
+        let text = Rope::from("caf\u{e9}\nx");
+        assert_eq!(offset_of_position(&text, &Position { line: 0, column: 4 }), 3);
+        assert_eq!(offset_of_position(&text, &Position { line: 0, column: 9 }), 5);
+        assert_eq!(offset_of_position(&text, &Position { line: 7, column: 0 }), 6);
+    }
+}
@@ -0,0 +1,693 @@
+// Copyright 2018 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snippets: templates of text with tab stops, placeholders and variables,
+//! written in the syntax of TextMate and the Language Server Protocol.
+//! Once inserted, a snippet is filled in by moving from one tab stop to
+//! the next.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Read;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+
+use toml;
+use xi_rope::{Rope, RopeDelta, Transformer};
+
+use config::ConfigError;
+use config_schema::ValidationError;
+use selection::{SelRegion, Selection};
+use syntax::LanguageId;
+use word_boundaries::WordCursor;
+
+/// The deepest that placeholders can be nested; placeholders nested more
+/// deeply are read as text, so that parsing and expanding don't overflow
+/// the stack.
+const MAX_DEPTH: usize = 32;
+
+/// A part of a snippet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    /// A tab stop, `$1` or `${1:placeholder}`. Tab stops with the same
+    /// index are linked, and those without a placeholder mirror the text
+    /// of the first one that has one.
+    TabStop {
+        index: usize,
+        placeholder: Vec<Piece>,
+    },
+    /// A tab stop whose placeholder is the first of several choices,
+    /// `${1|one,two|}`.
+    Choice {
+        index: usize,
+        choices: Vec<String>,
+    },
+    /// A variable, `$NAME` or `${NAME:default}`, whose default is used if
+    /// it has no value.
+    Variable {
+        name: String,
+        default: Vec<Piece>,
+    },
+}
+
+/// A parsed snippet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pieces: Vec<Piece>,
+}
+
+/// A snippet expanded to text, with the ranges of its tab stops in the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// The ranges of each tab stop, by index. There is always a final tab
+    /// stop, `0`, which is the end of the text if the snippet has none.
+    pub tab_stops: BTreeMap<usize, Vec<Range<usize>>>,
+}
+
+impl Snippet {
+    /// Parses a snippet body. Anything that isn't valid snippet syntax,
+    /// such as an unclosed placeholder, is read as text.
+    pub fn parse(body: &str) -> Snippet {
+        let pieces = Parser { body, pos: 0, depth: 0 }.pieces(false);
+        Snippet { pieces }
+    }
+
+    /// Expands the snippet, replacing the newlines of its text with
+    /// `newline` and its tabs with `tab`, and its variables with the values
+    /// returned by `variable`. Unknown variables, for which `variable`
+    /// returns `None`, are replaced by their names if they have no default.
+    pub fn expand<F>(&self, newline: &str, tab: &str, variable: F) -> Expansion
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut placeholders = HashMap::new();
+        find_placeholders(&self.pieces, &mut placeholders);
+        let mut expander = Expander {
+            newline,
+            tab,
+            variable: &variable,
+            placeholders,
+            expanding: Vec::new(),
+            text: String::new(),
+            tab_stops: BTreeMap::new(),
+        };
+        expander.expand(&self.pieces, true);
+        let Expander { text, mut tab_stops, .. } = expander;
+        let end = text.len();
+        tab_stops.entry(0).or_insert_with(|| vec![end..end]);
+        Expansion { text, tab_stops }
+    }
+}
+
+struct Parser<'a> {
+    body: &'a str,
+    pos: usize,
+    /// The number of placeholders around the current position.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.body[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
+        let rest = &self.body[self.pos..];
+        let len = rest.find(|c| !f(c)).unwrap_or_else(|| rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Parses pieces up to the end of the body, or if `nested`, up to the
+    /// closing brace of a placeholder.
+    fn pieces(&mut self, nested: bool) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if c == '}' && nested {
+                break;
+            }
+            let start = self.pos;
+            self.pos += c.len_utf8();
+            if c == '$' {
+                if let Some(piece) = self.dollar() {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(mem::replace(&mut text, String::new())));
+                    }
+                    pieces.push(piece);
+                    continue;
+                }
+                self.pos = start + 1;
+            } else if c == '\\' {
+                if let Some(escaped) = self.peek().filter(|&c| c == '$' || c == '}' || c == '\\') {
+                    self.pos += 1;
+                    text.push(escaped);
+                    continue;
+                }
+            }
+            text.push(c);
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        pieces
+    }
+
+    /// Parses the pieces of a placeholder or a default, unless it would be
+    /// nested too deeply.
+    fn nested_pieces(&mut self) -> Option<Vec<Piece>> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let pieces = self.pieces(true);
+        self.depth -= 1;
+        Some(pieces)
+    }
+
+    /// Parses the tab stop or variable after a `$`, if there is one.
+    fn dollar(&mut self) -> Option<Piece> {
+        if !self.eat('{') {
+            return match self.index() {
+                Some(index) => Some(Piece::TabStop { index, placeholder: Vec::new() }),
+                None => self.name().map(|name| Piece::Variable { name, default: Vec::new() }),
+            };
+        }
+        if let Some(index) = self.index() {
+            if self.eat('}') {
+                return Some(Piece::TabStop { index, placeholder: Vec::new() });
+            }
+            if self.eat(':') {
+                let placeholder = self.nested_pieces()?;
+                if self.eat('}') {
+                    return Some(Piece::TabStop { index, placeholder });
+                }
+            } else if self.eat('|') {
+                return self.choices().map(|choices| Piece::Choice { index, choices });
+            }
+            return None;
+        }
+        let name = self.name()?;
+        if self.eat('}') {
+            return Some(Piece::Variable { name, default: Vec::new() });
+        }
+        if self.eat(':') {
+            let default = self.nested_pieces()?;
+            if self.eat('}') {
+                return Some(Piece::Variable { name, default });
+            }
+        }
+        None
+    }
+
+    /// Parses the choices of a choice tab stop, after the opening `|`.
+    fn choices(&mut self) -> Option<Vec<String>> {
+        let mut choices = vec![String::new()];
+        loop {
+            let c = self.peek()?;
+            self.pos += c.len_utf8();
+            let c = match c {
+                ',' => {
+                    choices.push(String::new());
+                    continue;
+                }
+                '|' if self.eat('}') => return Some(choices),
+                '|' => return None,
+                '\\' => match self.peek().filter(|c| "$}\\,|".contains(*c)) {
+                    Some(escaped) => {
+                        self.pos += 1;
+                        escaped
+                    }
+                    None => c,
+                },
+                c => c,
+            };
+            choices.last_mut().unwrap().push(c);
+        }
+    }
+
+    fn index(&mut self) -> Option<usize> {
+        self.take_while(|c| c.is_ascii_digit()).parse().ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => (),
+            _ => return None,
+        }
+        Some(self.take_while(|c| c == '_' || c.is_ascii_alphanumeric()).to_owned())
+    }
+}
+
+/// Finds the first tab stop with a placeholder for each index.
+fn find_placeholders<'a>(pieces: &'a [Piece], placeholders: &mut HashMap<usize, &'a Piece>) {
+    for piece in pieces {
+        match *piece {
+            Piece::TabStop { index, ref placeholder } if !placeholder.is_empty() => {
+                placeholders.entry(index).or_insert(piece);
+                find_placeholders(placeholder, placeholders);
+            }
+            Piece::Choice { index, .. } => {
+                placeholders.entry(index).or_insert(piece);
+            }
+            Piece::Variable { ref default, .. } => find_placeholders(default, placeholders),
+            _ => (),
+        }
+    }
+}
+
+struct Expander<'a, 'b, F: 'b> {
+    newline: &'b str,
+    tab: &'b str,
+    variable: &'b F,
+    placeholders: HashMap<usize, &'a Piece>,
+    /// The indices of the tab stops whose placeholders are being expanded.
+    expanding: Vec<usize>,
+    text: String,
+    tab_stops: BTreeMap<usize, Vec<Range<usize>>>,
+}
+
+impl<'a, 'b, F: Fn(&str) -> Option<String>> Expander<'a, 'b, F> {
+    /// Expands `pieces`, recording the ranges of their tab stops if
+    /// `record` is set, which it isn't for the copies of placeholders.
+    fn expand(&mut self, pieces: &'a [Piece], record: bool) {
+        for piece in pieces {
+            let start = self.text.len();
+            match *piece {
+                Piece::Text(ref text) => self.push_text(text),
+                Piece::TabStop { index, ref placeholder } => {
+                    let is_nested = self.expanding.contains(&index);
+                    self.expanding.push(index);
+                    match self.placeholders.get(&index) {
+                        // a tab stop inside its own placeholder mirrors nothing
+                        _ if is_nested && placeholder.is_empty() => (),
+                        Some(&&Piece::TabStop { placeholder: ref first, .. })
+                            if placeholder.is_empty() =>
+                        {
+                            self.expand(first, false)
+                        }
+                        Some(&&Piece::Choice { ref choices, .. }) if placeholder.is_empty() => {
+                            self.push_text(&choices[0])
+                        }
+                        _ => self.expand(placeholder, record),
+                    }
+                    self.expanding.pop();
+                    if record {
+                        self.add_tab_stop(index, start);
+                    }
+                }
+                Piece::Choice { index, ref choices } => {
+                    self.push_text(&choices[0]);
+                    if record {
+                        self.add_tab_stop(index, start);
+                    }
+                }
+                Piece::Variable { ref name, ref default } => match (self.variable)(name) {
+                    Some(ref value) if !value.is_empty() => self.text.push_str(value),
+                    None if default.is_empty() => self.text.push_str(name),
+                    _ => self.expand(default, record),
+                },
+            }
+        }
+    }
+
+    fn add_tab_stop(&mut self, index: usize, start: usize) {
+        let end = self.text.len();
+        self.tab_stops.entry(index).or_insert_with(Vec::new).push(start..end);
+    }
+
+    fn push_text(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => self.text.push_str(self.newline),
+                '\t' => self.text.push_str(self.tab),
+                c => self.text.push(c),
+            }
+        }
+    }
+}
+
+/// Returns the value of the snippet variable `name`, for a snippet inserted
+/// in place of `region` in a file at `path`, or `None` if the variable is
+/// unknown.
+pub fn variable(name: &str, text: &Rope, region: SelRegion, path: Option<&Path>) -> Option<String> {
+    let line = text.line_of_offset(region.min());
+    let file_name = |path: &Path| path.file_name().map(|s| s.to_string_lossy().into_owned());
+    let file_stem = |path: &Path| path.file_stem().map(|s| s.to_string_lossy().into_owned());
+    let directory = |path: &Path| path.parent().map(|p| p.to_string_lossy().into_owned());
+    let value = match name {
+        "TM_SELECTED_TEXT" => Some(text.slice_to_cow(region.min()..region.max()).into_owned()),
+        "TM_CURRENT_LINE" => {
+            let line_text = text.lines(text.offset_of_line(line)..).next().unwrap_or_default();
+            Some(line_text.into_owned())
+        }
+        "TM_CURRENT_WORD" => {
+            let (start, end) = WordCursor::new(text, region.min()).select_word();
+            Some(text.slice_to_cow(start..end).into_owned())
+        }
+        "TM_LINE_INDEX" => Some(line.to_string()),
+        "TM_LINE_NUMBER" => Some((line + 1).to_string()),
+        "TM_FILENAME" => path.and_then(file_name),
+        "TM_FILENAME_BASE" => path.and_then(file_stem),
+        "TM_DIRECTORY" => path.and_then(directory),
+        "TM_FILEPATH" => path.map(|p| p.to_string_lossy().into_owned()),
+        _ => return None,
+    };
+    Some(value.unwrap_or_default())
+}
+
+/// The tab stops of an inserted snippet, which move with edits to the text,
+/// and the one that is selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetSession {
+    /// The regions of each tab stop, in the order they are visited, ending
+    /// with the final tab stop.
+    tab_stops: Vec<Vec<(usize, usize)>>,
+    current: usize,
+}
+
+impl SnippetSession {
+    /// Creates a session at the first tab stop of `tab_stops`, which are
+    /// given by index and are visited in order, apart from the final tab
+    /// stop `0`, which is visited last.
+    pub fn new(mut tab_stops: BTreeMap<usize, Vec<Range<usize>>>) -> SnippetSession {
+        let last = tab_stops.remove(&0).unwrap_or_default();
+        let tab_stops = tab_stops
+            .into_iter()
+            .map(|(_, ranges)| ranges)
+            .chain(Some(last))
+            .map(|ranges| ranges.into_iter().map(|r| (r.start, r.end)).collect())
+            .collect();
+        SnippetSession { tab_stops, current: 0 }
+    }
+
+    /// Returns `true` if the final tab stop has been reached, which ends
+    /// the session.
+    pub fn is_finished(&self) -> bool {
+        self.current + 1 >= self.tab_stops.len()
+    }
+
+    /// Returns the selection of the regions of the current tab stop, which
+    /// are selected together, so that linked tab stops are edited together.
+    pub fn selection(&self) -> Selection {
+        let mut sel = Selection::new();
+        for &(start, end) in &self.tab_stops[self.current] {
+            sel.add_region(SelRegion::new(start, end));
+        }
+        sel
+    }
+
+    /// Moves to the next tab stop, or to the previous one if `forward` is
+    /// not set.
+    pub fn move_to_tab_stop(&mut self, forward: bool) {
+        if forward {
+            self.current = (self.current + 1).min(self.tab_stops.len() - 1);
+        } else {
+            self.current = self.current.saturating_sub(1);
+        }
+    }
+
+    /// Updates the tab stops for an edit. Text inserted at the edges of the
+    /// current tab stop, and of the tab stops containing it, is added to
+    /// them; text inserted at the edges of other tab stops stays outside
+    /// them, before those visited later and after those visited earlier.
+    pub fn apply_delta(&mut self, delta: &RopeDelta) {
+        let mut transformer = Transformer::new(delta);
+        let current = self.current;
+        let current_regions = self.tab_stops[current].clone();
+        for (i, regions) in self.tab_stops.iter_mut().enumerate() {
+            for region in regions.iter_mut() {
+                let (start, end) = *region;
+                // an empty tab stop at the edge of another isn't inside it
+                let contains_current = current_regions
+                    .iter()
+                    .any(|&(s, e)| start <= s && e <= end && (s < e || (start < s && s < end)));
+                let (start_after, end_after) = if i == current || contains_current {
+                    (false, true)
+                } else {
+                    (i > current, i > current)
+                };
+                let start = transformer.transform(start, start_after);
+                let end = transformer.transform(end, end_after);
+                *region = (start, end.max(start));
+            }
+        }
+    }
+}
+
+/// A snippet that is expanded by typing its prefix, as defined in a
+/// snippets file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SnippetDefinition {
+    pub prefix: String,
+    pub body: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// The snippets of each language, which are loaded from the files in the
+/// snippets directory named after their languages, such as `Rust.toml`.
+#[derive(Debug, Default)]
+pub struct Snippets {
+    /// The snippets of each language, by name.
+    languages: HashMap<LanguageId, BTreeMap<String, SnippetDefinition>>,
+    /// The errors from loading the snippets dir, to be reported to the client.
+    errors: Vec<ValidationError>,
+}
+
+impl Snippets {
+    pub fn new() -> Snippets {
+        Snippets::default()
+    }
+
+    /// Loads the snippets files in `dir`.
+    pub(crate) fn load_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("error reading snippets dir {:?}: {:?}", dir, e);
+                return;
+            }
+        };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if let Err(e) = self.load_file(&path) {
+                self.errors.extend(e.into_validation_errors(Some(&path)));
+            }
+        }
+    }
+
+    /// Returns, and forgets, the errors from loading the snippets dir.
+    pub(crate) fn take_errors(&mut self) -> Vec<ValidationError> {
+        mem::replace(&mut self.errors, Vec::new())
+    }
+
+    /// Loads a snippets file, replacing the snippets of its language. Files
+    /// that aren't snippets files are ignored.
+    pub(crate) fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let language = match language_for_path(path) {
+            Some(language) => language,
+            None => return Ok(()),
+        };
+        let mut contents = String::new();
+        fs::File::open(path)?.read_to_string(&mut contents)?;
+        let snippets = toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.into(), e))?;
+        self.set_language(language, snippets);
+        Ok(())
+    }
+
+    /// Removes the snippets of a removed snippets file.
+    pub(crate) fn remove_file(&mut self, path: &Path) {
+        if let Some(language) = language_for_path(path) {
+            self.languages.remove(&language);
+        }
+    }
+
+    pub(crate) fn set_language(
+        &mut self,
+        language: LanguageId,
+        snippets: BTreeMap<String, SnippetDefinition>,
+    ) {
+        self.languages.insert(language, snippets);
+    }
+
+    /// Returns the snippet of `language` with the longest prefix that
+    /// `text` ends with, where that prefix doesn't continue a word.
+    pub fn find_prefix(&self, language: &LanguageId, text: &str) -> Option<&SnippetDefinition> {
+        let is_word_char = |c: char| c == '_' || c.is_alphanumeric();
+        self.languages
+            .get(language)?
+            .values()
+            .filter(|snippet| {
+                let prefix = &snippet.prefix;
+                if prefix.is_empty() || !text.ends_with(prefix.as_str()) {
+                    return false;
+                }
+                let before = text[..text.len() - prefix.len()].chars().next_back();
+                let starts_word = prefix.chars().next().map(is_word_char).unwrap_or(false);
+                !starts_word || !before.map(is_word_char).unwrap_or(false)
+            })
+            .max_by_key(|snippet| snippet.prefix.len())
+    }
+}
+
+/// Returns the language of a snippets file.
+fn language_for_path(path: &Path) -> Option<LanguageId> {
+    if path.extension() != Some(OsStr::new("toml")) {
+        return None;
+    }
+    path.file_stem().and_then(OsStr::to_str).map(LanguageId::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xi_rope::{DeltaBuilder, Interval};
+
+    fn expand(body: &str) -> Expansion {
+        Snippet::parse(body).expand("\n  ", "    ", |name| match name {
+            "TM_FILENAME" => Some("main.rs".into()),
+            "TM_SELECTED_TEXT" => Some(String::new()),
+            _ => None,
+        })
+    }
+
+    fn tab_stops(expansion: &Expansion) -> Vec<(usize, Vec<(usize, usize)>)> {
+        let stops = expansion.tab_stops.iter();
+        stops.map(|(&i, ranges)| (i, ranges.iter().map(|r| (r.start, r.end)).collect())).collect()
+    }
+
+    #[test]
+    fn parse_and_expand() {
+        let e = expand("fn ${1:name}($2) {\n\t$0\n}");
+        assert_eq!(e.text, "fn name() {\n      \n  }");
+        assert_eq!(tab_stops(&e), vec![(0, vec![(18, 18)]), (1, vec![(3, 7)]), (2, vec![(8, 8)])]);
+
+        // the final tab stop defaults to the end
+        let e = expand("a ${1|one,t\\|wo|} $1 $TM_FILENAME ${TM_SELECTED_TEXT:sel} $UNKNOWN");
+        assert_eq!(e.text, "a one one main.rs sel UNKNOWN");
+        assert_eq!(tab_stops(&e), vec![(0, vec![(29, 29)]), (1, vec![(2, 5), (6, 9)])]);
+
+        // nested placeholders, mirrored before their placeholder
+        let e = expand("$1 ${1:a ${2:b}}");
+        assert_eq!(e.text, "a b a b");
+        assert_eq!(
+            tab_stops(&e),
+            vec![(0, vec![(7, 7)]), (1, vec![(0, 3), (4, 7)]), (2, vec![(6, 7)])]
+        );
+
+        // escapes, and invalid syntax read as text
+        let e = expand("\\$1 \\} \\x $ ${1:open");
+        assert_eq!(e.text, "$1 } \\x $ ${1:open");
+        assert_eq!(tab_stops(&e), vec![(0, vec![(18, 18)])]);
+        assert_eq!(expand("${2}$1").text, "");
+
+        // placeholders nested too deeply are read as text
+        let body = format!("{}x{}", "${1:".repeat(100_000), "}".repeat(100_000));
+        assert!(expand(&body).text.starts_with("${1:${1:"));
+    }
+
+    #[test]
+    fn self_referencing_placeholders() {
+        let e = expand("${1:a $1}");
+        assert_eq!(e.text, "a ");
+        assert_eq!(tab_stops(&e), vec![(0, vec![(2, 2)]), (1, vec![(2, 2), (0, 2)])]);
+        assert_eq!(expand("${1:${2:$1}}").text, "");
+        let e = expand("${1:${2:$1}} $2");
+        assert_eq!(e.text, " ");
+        assert_eq!(
+            tab_stops(&e),
+            vec![(0, vec![(1, 1)]), (1, vec![(0, 0), (0, 0)]), (2, vec![(0, 0), (1, 1)])]
+        );
+    }
+
+    fn selected(session: &SnippetSession) -> Vec<(usize, usize)> {
+        session.selection().iter().map(|region| (region.start, region.end)).collect()
+    }
+
+    #[test]
+    fn session() {
+        let e = expand("${1:a}$2$0");
+        let mut session = SnippetSession::new(e.tab_stops);
+        assert_eq!(selected(&session), vec![(0, 1)]);
+        // typing over the first tab stop leaves the later ones after it
+        let mut builder = DeltaBuilder::new(1);
+        builder.replace(Interval::new(0, 1), "xyz".into());
+        session.apply_delta(&builder.build());
+        assert_eq!(selected(&session), vec![(0, 3)]);
+        session.move_to_tab_stop(true);
+        assert_eq!(selected(&session), vec![(3, 3)]);
+        assert!(!session.is_finished());
+        // typing in the second leaves the first before it
+        let mut builder = DeltaBuilder::new(3);
+        builder.replace(Interval::new(3, 3), "w".into());
+        session.apply_delta(&builder.build());
+        session.move_to_tab_stop(false);
+        assert_eq!(selected(&session), vec![(0, 3)]);
+        session.move_to_tab_stop(true);
+        session.move_to_tab_stop(true);
+        assert_eq!(selected(&session), vec![(4, 4)]);
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn find_prefix() {
+        let mut snippets = Snippets::new();
+        let rust = LanguageId::from("Rust");
+        let def = |prefix: &str| SnippetDefinition {
+            prefix: prefix.into(),
+            body: String::new(),
+            description: String::new(),
+        };
+        let mut defs = BTreeMap::new();
+        defs.insert("fn".into(), def("fn"));
+        defs.insert("pub fn".into(), def("pfn"));
+        defs.insert("derive".into(), def("#d"));
+        snippets.set_language(rust.clone(), defs);
+        let find = |text| snippets.find_prefix(&rust, text).map(|s| s.prefix.as_str());
+        assert_eq!(find("  fn"), Some("fn"));
+        assert_eq!(find("pfn"), Some("pfn"));
+        assert_eq!(find("xfn"), None);
+        assert_eq!(find("x#d"), Some("#d"));
+        assert_eq!(snippets.find_prefix(&"Python".into(), "fn"), None);
+    }
+
+    #[test]
+    fn variables() {
+        let text = Rope::from("one two\nthree four");
+        let region = SelRegion::new(10, 12);
+        let path = Path::new("/src/main.rs");
+        let get = |name| variable(name, &text, region, Some(path));
+        assert_eq!(get("TM_SELECTED_TEXT"), Some("re".into()));
+        assert_eq!(get("TM_CURRENT_LINE"), Some("three four".into()));
+        assert_eq!(get("TM_CURRENT_WORD"), Some("three".into()));
+        assert_eq!(get("TM_LINE_NUMBER"), Some("2".into()));
+        assert_eq!(get("TM_FILENAME_BASE"), Some("main".into()));
+        assert_eq!(get("TM_DIRECTORY"), Some("/src".into()));
+        assert_eq!(variable("TM_FILENAME", &text, region, None), Some(String::new()));
+        assert_eq!(get("NOPE"), None);
+    }
+}
@@ -29,7 +29,12 @@ use syntect::LoadingError;
 
 pub use syntect::highlighting::ThemeSettings;
 
-pub const N_RESERVED_STYLES: usize = 8;
+/// The number of reserved styles for find results, which follow the
+/// selection style. Find queries after these share the last of them.
+pub const N_FIND_STYLES: usize = 7;
+/// The reserved style of the pair of brackets around a caret.
+pub const MATCHING_BRACKETS_STYLE: usize = N_FIND_STYLES + 1;
+pub const N_RESERVED_STYLES: usize = MATCHING_BRACKETS_STYLE + 1;
 const SYNTAX_PRIORITY_DEFAULT: u16 = 200;
 const SYNTAX_PRIORITY_LOWEST: u16 = 0;
 pub const DEFAULT_THEME: &str = "InspiredGitHub";
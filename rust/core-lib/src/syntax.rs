@@ -14,13 +14,20 @@
 
 //! Very basic syntax detection.
 
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Arc;
 
+use regex::{self, Regex};
+use xi_rope::Rope;
+
 use config::Table;
 
+/// The maximum number of bytes of the first line of a buffer that are
+/// matched against `first_line_match` patterns.
+const MAX_FIRST_LINE_LEN: usize = 1024;
+
 /// The canonical identifier for a particular `LanguageDefinition`.
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct LanguageId(Arc<String>);
@@ -31,8 +38,22 @@ pub struct LanguageId(Arc<String>);
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LanguageDefinition {
     pub name: LanguageId,
+    /// File extensions, or, as in Sublime Text syntaxes, complete file
+    /// names such as `Makefile` or `.bashrc`.
     pub extensions: Vec<String>,
+    /// File names, or glob patterns matching file names, such as
+    /// `Dockerfile` or `*.dockerfile`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filenames: Vec<String>,
+    /// A regular expression matched against the first line of a file,
+    /// such as a shebang line, when its name does not identify it.
     pub first_line_match: Option<String>,
+    /// The token that starts a line comment, such as `//`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_comment: Option<String>,
+    /// The tokens that start and end a block comment, such as `/*` and `*/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_comment: Option<(String, String)>,
     pub scope: String,
     #[serde(skip)]
     pub default_config: Option<Table>,
@@ -44,25 +65,75 @@ pub struct Languages {
     // NOTE: BTreeMap is used for sorting the languages by name alphabetically
     named: BTreeMap<LanguageId, Arc<LanguageDefinition>>,
     extensions: HashMap<String, Arc<LanguageDefinition>>,
+    filenames: HashMap<String, Arc<LanguageDefinition>>,
+    filename_globs: Vec<(Regex, Arc<LanguageDefinition>)>,
+    first_lines: Vec<(Regex, Arc<LanguageDefinition>)>,
 }
 
 impl Languages {
     pub fn new(language_defs: &[LanguageDefinition]) -> Self {
         let mut named = BTreeMap::new();
         let mut extensions = HashMap::new();
+        let mut filenames = HashMap::new();
+        let mut filename_globs = Vec::new();
+        let mut first_lines = Vec::new();
         for lang in language_defs.iter() {
             let lang_arc = Arc::new(lang.clone());
             named.insert(lang.name.clone(), lang_arc.clone());
             for ext in &lang.extensions {
                 extensions.insert(ext.clone(), lang_arc.clone());
             }
+            for name in &lang.filenames {
+                if !name.contains(&['*', '?', '['][..]) {
+                    filenames.insert(name.clone(), lang_arc.clone());
+                    continue;
+                }
+                match Regex::new(&glob_to_regex(name)) {
+                    Ok(re) => filename_globs.push((re, lang_arc.clone())),
+                    Err(e) => warn!("invalid file name pattern for {:?}: {}", lang.name, e),
+                }
+            }
+            if let Some(pattern) = lang.first_line_match.as_ref() {
+                match Regex::new(pattern) {
+                    Ok(re) => first_lines.push((re, lang_arc.clone())),
+                    Err(e) => warn!("invalid first_line_match for {:?}: {}", lang.name, e),
+                }
+            }
         }
-        Languages { named, extensions }
+        Languages { named, extensions, filenames, filename_globs, first_lines }
     }
 
+    /// Returns the language for the file at `path`, matching its name
+    /// against the file names and patterns of each language, and then
+    /// its extension.
     pub fn language_for_path(&self, path: &Path) -> Option<Arc<LanguageDefinition>> {
-        path.extension()
-            .and_then(|ext| self.extensions.get(ext.to_str().unwrap_or_default()))
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        self.filenames
+            .get(name)
+            .or_else(|| self.filename_globs.iter().find(|(re, _)| re.is_match(name)).map(|g| &g.1))
+            .or_else(|| self.extensions.get(name))
+            .or_else(|| {
+                path.extension()
+                    .and_then(|ext| self.extensions.get(ext.to_str().unwrap_or_default()))
+            })
+            .map(Arc::clone)
+    }
+
+    /// Returns the first language whose `first_line_match` matches the first
+    /// line of `text`.
+    pub fn language_for_first_line(&self, text: &Rope) -> Option<Arc<LanguageDefinition>> {
+        let line = first_line(text);
+        self.first_lines.iter().find(|(re, _)| re.is_match(&line)).map(|(_, lang)| lang.clone())
+    }
+
+    /// Returns the language for a Vim filetype or an Emacs major mode, such
+    /// as `python`, which is either the name of the language, ignoring case,
+    /// or one of its extensions.
+    pub fn language_for_mode(&self, mode: &str) -> Option<Arc<LanguageDefinition>> {
+        self.named
+            .values()
+            .find(|lang| lang.name.as_ref().eq_ignore_ascii_case(mode))
+            .or_else(|| self.extensions.get(mode))
             .map(Arc::clone)
     }
 
@@ -88,6 +159,50 @@ impl Languages {
     }
 }
 
+/// Returns the first line of `text`, without its line ending, and truncated
+/// to at most `MAX_FIRST_LINE_LEN` bytes.
+pub(crate) fn first_line(text: &Rope) -> Cow<str> {
+    let mut end = text.offset_of_line(1).min(MAX_FIRST_LINE_LEN);
+    while !text.is_codepoint_boundary(end) {
+        end -= 1;
+    }
+    match text.slice_to_cow(..end) {
+        Cow::Borrowed(s) => Cow::Borrowed(s.trim_end_matches(&['\n', '\r'][..])),
+        Cow::Owned(s) => Cow::Owned(s.trim_end_matches(&['\n', '\r'][..]).to_owned()),
+    }
+}
+
+/// Converts a glob pattern matching file names to a regular expression.
+/// `*` matches any sequence of characters, `?` any single character, and
+/// `[...]` or `[!...]` any character in, or not in, a set.
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut in_class = false;
+    for c in glob.chars() {
+        match c {
+            ']' if in_class => {
+                in_class = false;
+                re.push(']');
+            }
+            '!' if in_class && re.ends_with('[') => re.push('^'),
+            '\\' | '^' | '[' if in_class => {
+                re.push('\\');
+                re.push(c);
+            }
+            _ if in_class => re.push(c),
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            '[' => {
+                in_class = true;
+                re.push('[');
+            }
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    re
+}
+
 impl AsRef<str> for LanguageId {
     fn as_ref(&self) -> &str {
         self.0.as_ref()
@@ -114,9 +229,84 @@ impl LanguageDefinition {
         LanguageDefinition {
             name: name.into(),
             extensions: exts.iter().map(|s| (*s).into()).collect(),
+            filenames: Vec::new(),
             first_line_match: None,
+            line_comment: None,
+            block_comment: None,
             scope: scope.into(),
             default_config: config,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn languages() -> Languages {
+        let mut make =
+            LanguageDefinition::simple("Makefile", &["mk", "Makefile"], "source.make", None);
+        make.filenames = vec!["GNUmakefile".into(), "*.mk.in".into()];
+        let mut docker = LanguageDefinition::simple("Dockerfile", &[], "source.dockerfile", None);
+        docker.filenames = vec!["Dockerfile".into(), "Dockerfile.*".into(), "[!.]*.docker".into()];
+        let mut python = LanguageDefinition::simple("Python", &["py"], "source.python", None);
+        python.first_line_match = Some(r"^#!\s*/.*\bpython(\d(\.\d)?)?\b".into());
+        let mut shell =
+            LanguageDefinition::simple("Shell", &["sh", ".bashrc"], "source.shell", None);
+        shell.first_line_match = Some(r"(?x) ^ \#! .* \b(bash|sh)\b".into());
+        Languages::new(&[make, docker, python, shell])
+    }
+
+    fn name(lang: Option<Arc<LanguageDefinition>>) -> String {
+        lang.map(|lang| lang.name.as_ref().to_owned()).unwrap_or_default()
+    }
+
+    #[test]
+    fn detect_from_path() {
+        let languages = languages();
+        let name = |path| name(languages.language_for_path(Path::new(path)));
+        assert_eq!(name("src/Makefile"), "Makefile");
+        assert_eq!(name("GNUmakefile"), "Makefile");
+        assert_eq!(name("rules.mk.in"), "Makefile");
+        assert_eq!(name("rules.mk"), "Makefile");
+        assert_eq!(name("Dockerfile"), "Dockerfile");
+        assert_eq!(name("Dockerfile.dev"), "Dockerfile");
+        assert_eq!(name("app.docker"), "Dockerfile");
+        assert_eq!(name(".hidden.docker"), "");
+        assert_eq!(name("/home/me/.bashrc"), "Shell");
+        assert_eq!(name("script.py"), "Python");
+        assert_eq!(name("script"), "");
+    }
+
+    #[test]
+    fn detect_from_mode() {
+        let languages = languages();
+        let name = |mode| name(languages.language_for_mode(mode));
+        assert_eq!(name("python"), "Python");
+        assert_eq!(name("sh"), "Shell");
+        assert_eq!(name("text"), "");
+    }
+
+    #[test]
+    fn detect_from_first_line() {
+        let languages = languages();
+        let name = |text: &str| name(languages.language_for_first_line(&text.into()));
+        assert_eq!(name("#!/usr/bin/env python3\nprint()"), "Python");
+        assert_eq!(name("#!/bin/bash\r\necho"), "Shell");
+        assert_eq!(name("print()\n#!/usr/bin/env python3"), "");
+        assert_eq!(name(""), "");
+    }
+
+    #[test]
+    fn long_first_line() {
+        let text = Rope::from(format!("{}\u{e9}rest", "a".repeat(MAX_FIRST_LINE_LEN - 1)));
+        assert_eq!(first_line(&text).len(), MAX_FIRST_LINE_LEN - 1);
+        assert_eq!(first_line(&Rope::from("one\r\ntwo")), "one");
+    }
+
+    #[test]
+    fn globs() {
+        assert_eq!(glob_to_regex("*.mk"), r"^.*\.mk$");
+        assert_eq!(glob_to_regex("[!.]?"), "^[^.].$");
+    }
+}
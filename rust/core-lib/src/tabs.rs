@@ -20,7 +20,7 @@
 //! be renamed.
 
 use std::cell::{Cell, RefCell};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::io;
@@ -36,18 +36,26 @@ use xi_rpc::{self, ReadError, RemoteError, RpcCtx, RpcPeer};
 use xi_trace::{self, trace_block};
 
 use client::Client;
+use completion::Completions;
 use config::{self, ConfigDomain, ConfigDomainExternal, ConfigManager, Table};
 use editor::Editor;
+#[cfg(feature = "notify")]
+use editorconfig::EDITORCONFIG_FILE_NAME;
 use event_context::EventContext;
-use file::FileManager;
+use file::{CharacterEncoding, FileManager};
+use kill_ring::KillRing;
 use line_ending::LineEnding;
+use modeline::Modeline;
 use plugin_rpc::{PluginNotification, PluginRequest};
 use plugins::{start_plugin_process, Plugin, PluginCatalog, PluginPid};
 use recorder::Recorder;
+use recovery;
 use rpc::{
     CoreNotification, CoreRequest, EditNotification, EditRequest,
     PluginNotification as CorePluginNotification,
 };
+use session::{Session, ViewSession};
+use snippet::Snippets;
 use styles::{ThemeStyleMap, DEFAULT_THEME};
 use syntax::LanguageId;
 use view::View;
@@ -79,6 +87,9 @@ pub type BufferIdentifier = BufferId;
 /// Totally arbitrary; we reserve this space for `ViewId`s
 pub(crate) const RENDER_VIEW_IDLE_MASK: usize = 1 << 25;
 
+/// Reserved for the timers that write the recovery files of `BufferId`s.
+pub(crate) const RECOVERY_BUFFER_IDLE_MASK: usize = 1 << 24;
+
 const NEW_VIEW_IDLE_TOKEN: usize = 1001;
 
 /// xi_rpc idle Token for watcher related idle scheduling.
@@ -94,13 +105,27 @@ pub const OPEN_FILE_EVENT_TOKEN: WatchToken = WatchToken(2);
 #[cfg(feature = "notify")]
 const THEME_FILE_EVENT_TOKEN: WatchToken = WatchToken(3);
 
+/// Token for events in directories that may contain `.editorconfig` files
+/// affecting open files
+#[cfg(feature = "notify")]
+const EDITORCONFIG_EVENT_TOKEN: WatchToken = WatchToken(4);
+
+/// Token for config file events in the config directories of projects
+/// containing open files
+#[cfg(feature = "notify")]
+const PROJECT_CONFIG_EVENT_TOKEN: WatchToken = WatchToken(5);
+
+/// Token for events in the snippets directory
+#[cfg(feature = "notify")]
+const SNIPPET_FILE_EVENT_TOKEN: WatchToken = WatchToken(6);
+
 #[allow(dead_code)]
 pub struct CoreState {
     editors: BTreeMap<BufferId, RefCell<Editor>>,
     views: BTreeMap<ViewId, RefCell<View>>,
     file_manager: FileManager,
     /// A local pasteboard.
-    kill_ring: RefCell<Rope>,
+    kill_ring: RefCell<KillRing>,
     /// Theme and style state.
     style_map: RefCell<ThemeStyleMap>,
     width_cache: RefCell<WidthCache>,
@@ -108,11 +133,18 @@ pub struct CoreState {
     config_manager: ConfigManager,
     /// Recorded editor actions
     recorder: RefCell<Recorder>,
+    /// The snippets of each language.
+    snippets: Snippets,
+    /// The identifiers of the open buffers, for completion.
+    completions: RefCell<Completions>,
     /// A weak reference to the main state container, stashed so that
     /// it can be passed to plugins.
     self_ref: Option<WeakXiCore>,
     /// Views which need to have setup finished.
     pending_views: Vec<(ViewId, Table)>,
+    /// The untitled ids of the buffers that are not backed by a file, which
+    /// name their recovery files.
+    untitled_ids: HashMap<BufferId, String>,
     peer: Client,
     id_counter: Counter,
     plugins: PluginCatalog,
@@ -154,6 +186,15 @@ impl CoreState {
             });
         }
 
+        let mut snippets = Snippets::new();
+        if let Some(p) = config_manager.get_snippets_dir() {
+            snippets.load_dir(&p);
+            #[cfg(feature = "notify")]
+            watcher.watch_filtered(&p, false, SNIPPET_FILE_EVENT_TOKEN, |p| {
+                p.extension().and_then(OsStr::to_str).unwrap_or("") == "toml"
+            });
+        }
+
         CoreState {
             views: BTreeMap::new(),
             editors: BTreeMap::new(),
@@ -161,13 +202,16 @@ impl CoreState {
             file_manager: FileManager::new(watcher),
             #[cfg(not(feature = "notify"))]
             file_manager: FileManager::new(),
-            kill_ring: RefCell::new(Rope::from("")),
+            kill_ring: RefCell::new(KillRing::new()),
             style_map: RefCell::new(ThemeStyleMap::new(themes_dir)),
             width_cache: RefCell::new(WidthCache::new()),
             config_manager,
             recorder: RefCell::new(Recorder::new()),
+            snippets,
+            completions: RefCell::new(Completions::new()),
             self_ref: None,
             pending_views: Vec::new(),
+            untitled_ids: HashMap::new(),
             peer: Client::new(peer.clone()),
             id_counter: Counter::default(),
             plugins: PluginCatalog::default(),
@@ -205,6 +249,7 @@ impl CoreState {
         let languages_ids = languages.iter().map(|l| l.name.clone()).collect::<Vec<_>>();
         self.peer.available_languages(languages_ids);
         self.config_manager.set_languages(languages);
+        self.report_config_errors();
         let theme_names = self.style_map.borrow().get_theme_names();
         self.peer.available_themes(theme_names);
 
@@ -223,20 +268,34 @@ impl CoreState {
         let _t = trace_block("CoreState::load_config_file", &["core"]);
         if let Some(domain) = self.config_manager.domain_for_path(path) {
             match config::try_load_from_file(&path) {
-                Ok(table) => self.set_config(domain, table),
-                Err(e) => self.peer.alert(e.to_string()),
+                Ok(table) => self.set_config(domain, table, Some(path)),
+                Err(e) => self.peer.config_errors(&e.into_validation_errors(Some(path))),
             }
         } else {
             self.peer.alert(format!("Unexpected config file {:?}", path));
         }
     }
 
-    /// Sets (overwriting) the config for a given domain.
-    fn set_config(&mut self, domain: ConfigDomain, table: Table) {
-        match self.config_manager.set_user_config(domain, table) {
-            Err(e) => self.peer.alert(format!("{}", &e)),
+    /// Sets (overwriting) the config for a given domain. `path` is the config
+    /// file the table was loaded from, if any, which is included in any
+    /// errors reported to the client.
+    fn set_config(&mut self, domain: ConfigDomain, table: Table, path: Option<&Path>) {
+        match self.config_manager.set_user_config_from(domain, table, path) {
+            Err(e) => self.peer.config_errors(&e.into_validation_errors(path)),
             Ok(changes) => self.handle_config_changes(changes),
         }
+        self.report_config_errors();
+    }
+
+    /// Reports any errors from config files loaded by the config manager,
+    /// such as the config files of a newly opened project, and from the
+    /// snippets files loaded at startup.
+    fn report_config_errors(&mut self) {
+        let mut errors = self.config_manager.take_errors();
+        errors.extend(self.snippets.take_errors());
+        if !errors.is_empty() {
+            self.peer.config_errors(&errors);
+        }
     }
 
     /// Notify editors/views/plugins of config changes.
@@ -269,6 +328,7 @@ impl CoreState {
             let plugins = self.running_plugins.iter().collect::<Vec<_>>();
             let config = self.config_manager.get_buffer_config(buffer_id);
             let language = self.config_manager.get_buffer_language(buffer_id);
+            let language_def = self.config_manager.get_buffer_language_definition(buffer_id);
 
             EventContext {
                 view_id,
@@ -277,7 +337,10 @@ impl CoreState {
                 editor,
                 config: &config.items,
                 recorder: &self.recorder,
+                snippets: &self.snippets,
+                completions: &self.completions,
                 language,
+                language_def,
                 info,
                 siblings: Vec::new(),
                 plugins,
@@ -301,7 +364,15 @@ impl CoreState {
         use self::CorePluginNotification as PN;
         match cmd {
             Edit(::rpc::EditCommand { view_id, cmd }) => self.do_edit(view_id, cmd),
-            Save { view_id, file_path } => self.do_save(view_id, file_path),
+            Save { view_id, file_path, encoding, force } => {
+                self.do_save(view_id, file_path, encoding, force)
+            }
+            ReopenWithEncoding { view_id, encoding } => {
+                self.do_reopen_with_encoding(view_id, encoding)
+            }
+            RestoreRecovery { view_id } => self.do_restore_recovery(view_id),
+            DiscardRecovery { view_id } => self.do_discard_recovery(view_id),
+            SaveSession { file_path } => self.do_save_session(file_path),
             CloseView { view_id } => self.do_close_view(view_id),
             ModifyUserConfig { domain, changes } => self.do_modify_user_config(domain, changes),
             SetTheme { theme_name } => self.do_set_theme(&theme_name),
@@ -331,7 +402,9 @@ impl CoreState {
             Edit(::rpc::EditCommand { view_id, cmd }) => self.do_edit_sync(view_id, cmd),
             //TODO: why is this a request?? make a notification?
             GetConfig { view_id } => self.do_get_config(view_id).map(|c| json!(c)),
+            GetConfigSchema {} => Ok(json!(self.config_manager.get_config_schema())),
             DebugGetContents { view_id } => self.do_get_contents(view_id).map(|c| json!(c)),
+            RestoreSession { file_path } => self.do_restore_session(file_path),
         }
     }
 
@@ -339,6 +412,41 @@ impl CoreState {
         if let Some(mut edit_ctx) = self.make_context(view_id) {
             edit_ctx.do_edit(cmd);
         }
+        self.update_first_line(view_id);
+    }
+
+    /// Detects the language of the view's buffer again if its first line has
+    /// changed, and its language was not detected from its path.
+    fn update_first_line(&mut self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+        let language = self.config_manager.get_buffer_language(buffer_id);
+        let changes = {
+            let ed = self.editors[&buffer_id].borrow();
+            self.config_manager.update_first_line(buffer_id, ed.get_buffer())
+        };
+        self.after_language_detection(view_id, &language, changes);
+    }
+
+    /// Notifies the view of changes to its buffer's language, previously
+    /// `prev_language`, and to its config, after its language is detected.
+    fn after_language_detection(
+        &mut self,
+        view_id: ViewId,
+        prev_language: &LanguageId,
+        config_changes: Option<Table>,
+    ) {
+        let buffer_id = self.views[&view_id].borrow().get_buffer_id();
+        let language = self.config_manager.get_buffer_language(buffer_id);
+        let mut context = self.make_context(view_id).unwrap();
+        if language != *prev_language {
+            context.language_changed(&language);
+        }
+        if let Some(changes) = config_changes {
+            context.config_changed(&changes);
+        }
     }
 
     fn do_edit_sync(&mut self, view_id: ViewId, cmd: EditRequest) -> Result<Value, RemoteError> {
@@ -351,21 +459,33 @@ impl CoreState {
     }
 
     fn do_new_view(&mut self, path: Option<PathBuf>) -> Result<Value, RemoteError> {
+        self.new_view(path).map(|view_id| json!(view_id))
+    }
+
+    fn new_view(&mut self, path: Option<PathBuf>) -> Result<ViewId, RemoteError> {
         let view_id = self.next_view_id();
         let buffer_id = self.next_buffer_id();
 
         let rope = match path.as_ref() {
             Some(p) => self.file_manager.open(p, buffer_id)?,
-            None => Rope::from(""),
+            None => {
+                self.untitled_ids.insert(buffer_id, recovery::new_untitled_id());
+                Rope::from("")
+            }
         };
 
+        let config =
+            self.config_manager.add_buffer(buffer_id, path.as_ref().map(|p| p.as_path()), &rope);
+
         let editor = RefCell::new(Editor::with_text(rope));
         let view = RefCell::new(View::new(view_id, buffer_id));
 
         self.editors.insert(buffer_id, editor);
         self.views.insert(view_id, view);
 
-        let config = self.config_manager.add_buffer(buffer_id, path.as_ref().map(|p| p.as_path()));
+        self.update_editorconfig_watches(buffer_id, &[]);
+        self.update_project_watch(buffer_id, None);
+        self.report_config_errors();
 
         //NOTE: because this is a synchronous call, we have to return the
         //view_id before we can send any events to this view. We mark the
@@ -374,11 +494,16 @@ impl CoreState {
         self.pending_views.push((view_id, config));
         self.peer.schedule_idle(NEW_VIEW_IDLE_TOKEN);
 
-        Ok(json!(view_id))
+        Ok(view_id)
     }
 
-    fn do_save<P>(&mut self, view_id: ViewId, path: P)
-    where
+    fn do_save<P>(
+        &mut self,
+        view_id: ViewId,
+        path: P,
+        encoding: Option<CharacterEncoding>,
+        force: bool,
+    ) where
         P: AsRef<Path>,
     {
         let _t = trace_block("CoreState::do_save", &["core"]);
@@ -389,34 +514,252 @@ impl CoreState {
             None => return,
         };
 
-        let ed = &self.editors[&buffer_id];
+        let prev_path = self.file_manager.get_info(buffer_id).map(|info| info.path.clone());
+        let language = self.config_manager.get_buffer_language(buffer_id);
+
+        // the settings for a new path, such as those from its `.editorconfig`
+        // files, apply to this save, but are only sent after the save events.
+        let mut config_changes = None;
+        if prev_path.as_ref().map(|p| p != path).unwrap_or(true) {
+            let editorconfig_dirs = self.config_manager.get_editorconfig_dirs(buffer_id).to_owned();
+            let project = self.config_manager.get_buffer_project(buffer_id).map(Path::to_owned);
+            config_changes = {
+                let ed = self.editors[&buffer_id].borrow();
+                self.config_manager.update_buffer_path(buffer_id, path, ed.get_buffer())
+            };
+            self.update_editorconfig_watches(buffer_id, &editorconfig_dirs);
+            self.update_project_watch(buffer_id, project);
+            self.report_config_errors();
+        }
 
-        if let Err(e) = self.file_manager.save(path, ed.borrow().get_buffer(), buffer_id) {
+        // the edits made when saving are only applied if the save succeeds
+        let save_edits = self.make_context(view_id).unwrap().save_edits();
+        let text = {
+            let ed = self.editors[&buffer_id].borrow();
+            match save_edits {
+                Some(ref delta) => delta.apply(ed.get_buffer()),
+                None => ed.get_buffer().clone(),
+            }
+        };
+        let config = &self.config_manager.get_buffer_config(buffer_id).items;
+        // existing files keep the encoding they were opened with
+        let encoding =
+            if prev_path.is_some() { encoding } else { encoding.or(Some(config.default_encoding)) };
+
+        let result = self.file_manager.save(
+            path,
+            &text,
+            buffer_id,
+            encoding,
+            config.save_hardlinks_in_place,
+            force,
+        );
+        if let Err(e) = result {
             let error_message = e.to_string();
             error!("File error: {:?}", error_message);
             self.peer.alert(error_message);
+            self.after_language_detection(view_id, &language, config_changes);
             return;
         }
 
-        self.make_context(view_id).unwrap().after_save(path);
+        let untitled_id = self.untitled_ids.remove(&buffer_id);
+        if let Some(dir) = self.config_manager.get_recovery_dir() {
+            let prev_source = match (prev_path, untitled_id) {
+                (Some(prev_path), _) => Some(recovery::Source::File(prev_path)),
+                (None, Some(id)) => Some(recovery::Source::Untitled(id)),
+                (None, None) => None,
+            };
+            if let Some(prev_source) = prev_source {
+                recovery::remove(&dir, &prev_source);
+            }
+            recovery::remove(&dir, &recovery::Source::File(path.to_owned()));
+        }
 
-        // update the config _after_ sending save related events
-        let changes = self.config_manager.update_buffer_path(buffer_id, path);
-        if let Some(changes) = changes {
-            self.make_context(view_id).unwrap().config_changed(&changes);
+        self.make_context(view_id).unwrap().after_save(path, save_edits);
+
+        // send the language and config changes _after_ sending save related events
+        self.after_language_detection(view_id, &language, config_changes);
+    }
+
+    fn do_reopen_with_encoding(&mut self, view_id: ViewId, encoding: CharacterEncoding) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+
+        match self.file_manager.reopen(buffer_id, encoding) {
+            Ok(text) => self.make_context(view_id).unwrap().reopen(text),
+            Err(e) => {
+                let error_message = e.to_string();
+                error!("File error: {:?}", error_message);
+                self.peer.alert(error_message);
+            }
+        }
+    }
+
+    /// Returns what the recovery file of a buffer preserves: the file
+    /// backing it, or, if it is untitled, the buffer itself.
+    fn recovery_source(&self, buffer_id: BufferId) -> Option<recovery::Source> {
+        match self.file_manager.get_info(buffer_id) {
+            Some(info) => Some(recovery::Source::File(info.path.clone())),
+            None => self.untitled_ids.get(&buffer_id).cloned().map(recovery::Source::Untitled),
+        }
+    }
+
+    /// Returns the recovery directory and the source of the recovery file of
+    /// the buffer of the given view, if the view and the directory exist.
+    fn recovery_file(&self, view_id: ViewId) -> Option<(PathBuf, recovery::Source)> {
+        let buffer_id = self.views.get(&view_id)?.borrow().get_buffer_id();
+        Some((self.config_manager.get_recovery_dir()?, self.recovery_source(buffer_id)?))
+    }
+
+    fn do_restore_recovery(&mut self, view_id: ViewId) {
+        let (dir, source) = match self.recovery_file(view_id) {
+            Some(paths) => paths,
+            None => return,
+        };
+
+        match recovery::load(&dir, &source) {
+            Ok(text) => self.make_context(view_id).unwrap().restore_recovery(text),
+            Err(e) => {
+                let error_message = format!("Error restoring recovery file: {}", e);
+                error!("File error: {:?}", error_message);
+                self.peer.alert(error_message);
+            }
+        }
+    }
+
+    fn do_discard_recovery(&mut self, view_id: ViewId) {
+        if let Some((dir, source)) = self.recovery_file(view_id) {
+            recovery::remove(&dir, &source);
         }
     }
 
+    fn do_save_session(&mut self, path: Option<PathBuf>) {
+        let path = match path.or_else(|| self.config_manager.get_session_path()) {
+            Some(path) => path,
+            None => {
+                warn!("no session path and no config directory");
+                return;
+            }
+        };
+
+        let views = self
+            .views
+            .values()
+            .filter_map(|view| {
+                let view = view.borrow();
+                let buffer_id = view.get_buffer_id();
+                let file_path =
+                    self.file_manager.get_info(buffer_id).map(|info| info.path.as_path());
+                let untitled_id = self.untitled_ids.get(&buffer_id);
+                if file_path.is_none() && untitled_id.is_none() {
+                    return None;
+                }
+                let text = self.editors[&buffer_id].borrow();
+                let mut saved = ViewSession::new(file_path, &view, text.get_buffer());
+                saved.untitled_id = untitled_id.cloned();
+                saved.language = self.config_manager.get_language_override(buffer_id);
+                saved.config = self.config_manager.get_user_override(buffer_id);
+                Some(saved)
+            })
+            .collect();
+
+        if let Err(e) = (Session { views }).save(&path) {
+            let error_message = format!("Error saving session: {}", e);
+            error!("File error: {:?}", error_message);
+            self.peer.alert(error_message);
+        }
+    }
+
+    fn do_restore_session(&mut self, path: Option<PathBuf>) -> Result<Value, RemoteError> {
+        let path = path
+            .or_else(|| self.config_manager.get_session_path())
+            .ok_or_else(|| RemoteError::custom(404, "no session path", None))?;
+        let session = Session::load(&path).map_err(|e| {
+            RemoteError::custom(404, format!("Error loading session {:?}: {}", path, e), None)
+        })?;
+
+        let mut restored = Vec::new();
+        let recovery_dir = self.config_manager.get_recovery_dir();
+        for saved in session.views {
+            match (&saved.file_path, &saved.untitled_id) {
+                (Some(path), _) if !path.exists() => {
+                    info!("not restoring missing file {:?}", path);
+                    continue;
+                }
+                (Some(_), _) => (),
+                // an untitled buffer is only worth restoring for its unsaved
+                // changes, which are offered when its view is finalized, and
+                // only once, as its recovery file can't be shared
+                (None, Some(id)) => {
+                    let source = recovery::Source::Untitled(id.clone());
+                    let has_recovery = recovery_dir
+                        .as_ref()
+                        .map_or(false, |dir| recovery::has_newer(dir, &source));
+                    if !has_recovery || self.untitled_ids.values().any(|open| open == id) {
+                        continue;
+                    }
+                }
+                (None, None) => continue,
+            }
+            let view_id = match self.new_view(saved.file_path.clone()) {
+                Ok(view_id) => view_id,
+                Err(e) => {
+                    warn!("failed to restore {:?}: {:?}", saved.file_path, e);
+                    continue;
+                }
+            };
+            let buffer_id = self.views[&view_id].borrow().get_buffer_id();
+            if saved.file_path.is_none() {
+                self.untitled_ids.insert(buffer_id, saved.untitled_id.clone().unwrap());
+            }
+
+            if let Some(language) = saved.language.clone() {
+                self.config_manager.override_language(buffer_id, language);
+            }
+            if let Some(table) = saved.config.clone() {
+                let domain = ConfigDomain::UserOverride(buffer_id);
+                if let Err(e) = self.config_manager.set_user_config(domain, table) {
+                    warn!("ignoring saved config for {:?}: {:?}", saved.file_path, e);
+                }
+            }
+            // pending views are finalized with the config from when they were
+            // created, which predates the overrides
+            let config = self.config_manager.get_buffer_config(buffer_id).to_table();
+            if let Some(pending) = self.pending_views.iter_mut().find(|(id, _)| *id == view_id) {
+                pending.1 = config;
+            }
+
+            let ed = self.editors[&buffer_id].borrow();
+            saved.restore_view(&mut self.views[&view_id].borrow_mut(), ed.get_buffer());
+            restored.push(json!({"view_id": view_id, "file_path": saved.file_path}));
+        }
+        Ok(json!(restored))
+    }
+
     fn do_close_view(&mut self, view_id: ViewId) {
         let close_buffer = self.make_context(view_id).map(|ctx| ctx.close_view()).unwrap_or(true);
+        let recovery_file = self.recovery_file(view_id);
 
         let buffer_id = self.views.remove(&view_id).map(|v| v.borrow().get_buffer_id());
+        self.completions.borrow_mut().remove_view(view_id);
 
         if let Some(buffer_id) = buffer_id {
             if close_buffer {
+                if let Some((dir, source)) = recovery_file {
+                    recovery::remove(&dir, &source);
+                }
                 self.editors.remove(&buffer_id);
+                self.untitled_ids.remove(&buffer_id);
                 self.file_manager.close(buffer_id);
+                self.completions.borrow_mut().remove_buffer(buffer_id);
+                let editorconfig_dirs =
+                    self.config_manager.get_editorconfig_dirs(buffer_id).to_owned();
+                let project = self.config_manager.get_buffer_project(buffer_id).map(Path::to_owned);
                 self.config_manager.remove_buffer(buffer_id);
+                self.update_editorconfig_watches(buffer_id, &editorconfig_dirs);
+                self.update_project_watch(buffer_id, project);
             }
         }
     }
@@ -461,7 +804,7 @@ impl CoreState {
             },
         };
         let new_config = self.config_manager.table_for_update(domain.clone(), changes);
-        self.set_config(domain, new_config);
+        self.set_config(domain, new_config, None);
     }
 
     fn do_get_config(&self, view_id: ViewId) -> Result<Table, RemoteError> {
@@ -542,6 +885,9 @@ impl CoreState {
         match token {
             NEW_VIEW_IDLE_TOKEN => self.finalize_new_views(),
             WATCH_IDLE_TOKEN => self.handle_fs_events(),
+            other if (other & RECOVERY_BUFFER_IDLE_MASK) != 0 => {
+                self.handle_recovery_timer(other ^ RECOVERY_BUFFER_IDLE_MASK)
+            }
             other if (other & RENDER_VIEW_IDLE_MASK) != 0 => {
                 self.handle_render_timer(other ^ RENDER_VIEW_IDLE_MASK)
             }
@@ -554,11 +900,49 @@ impl CoreState {
         to_start.iter().for_each(|(id, config)| {
             let modified = self.detect_whitespace(*id, config);
             let config = modified.as_ref().unwrap_or(config);
+            let modified = self.apply_modelines(*id);
+            let config = modified.as_ref().unwrap_or(config);
             let mut edit_ctx = self.make_context(*id).unwrap();
             edit_ctx.finish_init(&config);
+
+            if let Some((dir, source)) = self.recovery_file(*id) {
+                if recovery::has_newer(&dir, &source) {
+                    self.peer.recovery_available(*id, source.path());
+                }
+            }
         });
     }
 
+    /// Applies the settings from the Vim or Emacs modelines of the view's
+    /// buffer, and returns the buffer's new config if they change it.
+    /// Like detected whitespace, they are set in the `SysOverride` domain,
+    /// and so are overridden by the user's settings for the buffer.
+    fn apply_modelines(&mut self, id: ViewId) -> Option<Table> {
+        let buffer_id = self.views.get(&id).map(|v| v.borrow().get_buffer_id())?;
+        let modeline = Modeline::parse(self.editors[&buffer_id].borrow().get_buffer());
+
+        let lang_changes = match modeline.mode.as_ref() {
+            Some(mode) => self.config_manager.set_modeline_language(buffer_id, mode),
+            None => None,
+        };
+
+        let table = modeline.to_table();
+        let mut changes = Vec::new();
+        if !table.is_empty() {
+            let domain = ConfigDomain::SysOverride(buffer_id);
+            let config_delta = self.config_manager.table_for_update(domain.clone(), table);
+            match self.config_manager.set_user_config(domain, config_delta) {
+                Ok(items) => changes = items,
+                Err(err) => warn!("apply_modelines failed to update config: {:?}", err),
+            }
+        }
+
+        if lang_changes.is_none() && changes.is_empty() {
+            return None;
+        }
+        Some(self.config_manager.get_buffer_config(buffer_id).to_table())
+    }
+
     // Detects whitespace settings from the file and merges them with the config
     fn detect_whitespace(&mut self, id: ViewId, config: &Table) -> Option<Table> {
         let buffer_id = self.views.get(&id).map(|v| v.borrow().get_buffer_id())?;
@@ -631,6 +1015,33 @@ impl CoreState {
         }
     }
 
+    /// Writes the recovery file of a buffer with unsaved changes, or removes
+    /// it if the buffer no longer has any.
+    fn handle_recovery_timer(&mut self, token: usize) {
+        let buffer_id: BufferId = token.into();
+        let editor = match self.editors.get(&buffer_id) {
+            Some(editor) => editor,
+            None => return,
+        };
+        editor.borrow_mut().set_has_pending_recovery(false);
+
+        let dir = match self.config_manager.get_recovery_dir() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let source = match self.recovery_source(buffer_id) {
+            Some(source) => source,
+            None => return,
+        };
+
+        let editor = editor.borrow();
+        if editor.is_pristine() {
+            recovery::remove(&dir, &source);
+        } else if let Err(e) = recovery::write(&dir, &source, editor.get_buffer()) {
+            error!("error writing recovery file for {:?}: {:?}", source, e);
+        }
+    }
+
     fn handle_render_timer(&mut self, token: usize) {
         let id: ViewId = token.into();
         if let Some(mut ctx) = self.make_context(id) {
@@ -646,8 +1057,12 @@ impl CoreState {
         for (token, event) in events.drain(..) {
             match token {
                 OPEN_FILE_EVENT_TOKEN => self.handle_open_file_fs_event(event),
-                CONFIG_EVENT_TOKEN => self.handle_config_fs_event(event),
+                CONFIG_EVENT_TOKEN | PROJECT_CONFIG_EVENT_TOKEN => {
+                    self.handle_config_fs_event(event)
+                }
                 THEME_FILE_EVENT_TOKEN => self.handle_themes_fs_event(event),
+                EDITORCONFIG_EVENT_TOKEN => self.handle_editorconfig_fs_event(event),
+                SNIPPET_FILE_EVENT_TOKEN => self.handle_snippets_fs_event(event),
                 _ => warn!("unexpected fs event token {:?}", token),
             }
         }
@@ -673,23 +1088,37 @@ impl CoreState {
             None => return,
         };
 
-        let has_changes = self.file_manager.check_file(path, buffer_id);
+        if !self.file_manager.check_file(path, buffer_id) {
+            return;
+        }
+
+        // this is ugly; we don't map buffer_id -> view_id anywhere
+        // but we know we must have a view.
+        let view_ids = self
+            .views
+            .values()
+            .filter(|v| v.borrow().get_buffer_id() == buffer_id)
+            .map(|v| v.borrow().get_view_id())
+            .collect::<Vec<_>>();
         let is_pristine = self.editors.get(&buffer_id).map(|ed| ed.borrow().is_pristine()).unwrap();
-        //TODO: currently we only use the file's modification time when
-        // determining if a file has been changed by another process.
-        // A more robust solution would also hash the file's contents.
 
-        if has_changes && is_pristine {
-            if let Ok(text) = self.file_manager.open(path, buffer_id) {
-                // this is ugly; we don't map buffer_id -> view_id anywhere
-                // but we know we must have a view.
-                let view_id = self
-                    .views
-                    .values()
-                    .find(|v| v.borrow().get_buffer_id() == buffer_id)
-                    .map(|v| v.borrow().get_view_id())
-                    .unwrap();
-                self.make_context(view_id).unwrap().reload(text);
+        // loading the file clears its changed flag, so the change is only
+        // handled once; if it can't be loaded, the flag stays set, which
+        // keeps saves from overwriting it, and loading is tried again on the
+        // next event without notifying the client
+        let text = match self.file_manager.open(path, buffer_id) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("failed to reload {:?}: {}", path, err);
+                return;
+            }
+        };
+        if is_pristine {
+            self.make_context(view_ids[0]).unwrap().reload(text);
+        } else {
+            let conflicts = self.make_context(view_ids[0]).unwrap().merge_disk_changes(text);
+            for view_id in view_ids {
+                self.make_context(view_id).unwrap().file_changed(path, &conflicts);
             }
         }
     }
@@ -711,9 +1140,89 @@ impl CoreState {
         }
     }
 
+    /// Handles the creation, modification or removal of an `.editorconfig`
+    /// file, reloading the settings of the buffers it can affect.
+    #[cfg(feature = "notify")]
+    fn handle_editorconfig_fs_event(&mut self, event: DebouncedEvent) {
+        use self::DebouncedEvent::*;
+        let dirs = match event {
+            Create(ref path) | Write(ref path) | Chmod(ref path) | Remove(ref path) => {
+                vec![path.parent()]
+            }
+            Rename(ref old, ref new) => vec![old.parent(), new.parent()],
+            _ => return,
+        };
+
+        let buffer_ids = self
+            .editors
+            .keys()
+            .filter(|id| {
+                let editorconfig_dirs = self.config_manager.get_editorconfig_dirs(**id);
+                dirs.iter().flatten().any(|dir| editorconfig_dirs.iter().any(|d| d == dir))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut changes = Vec::new();
+        for buffer_id in buffer_ids {
+            let path = match self.file_manager.get_info(buffer_id) {
+                Some(info) => info.path.clone(),
+                None => continue,
+            };
+            let editorconfig_dirs = self.config_manager.get_editorconfig_dirs(buffer_id).to_owned();
+            if let Some(table) = self.config_manager.update_editorconfig(buffer_id, &path) {
+                changes.push((buffer_id, table));
+            }
+            self.update_editorconfig_watches(buffer_id, &editorconfig_dirs);
+        }
+        self.handle_config_changes(changes);
+    }
+
+    /// Watches the directories of the `.editorconfig` files that affect the
+    /// settings of a buffer, replacing the previously watched `old_dirs`.
+    #[cfg(feature = "notify")]
+    fn update_editorconfig_watches(&mut self, buffer_id: BufferId, old_dirs: &[PathBuf]) {
+        let new_dirs = self.config_manager.get_editorconfig_dirs(buffer_id).to_owned();
+        let watcher = self.file_manager.watcher();
+        for dir in old_dirs.iter().filter(|dir| !new_dirs.contains(dir)) {
+            watcher.unwatch(dir, EDITORCONFIG_EVENT_TOKEN);
+        }
+        for dir in new_dirs.iter().filter(|dir| !old_dirs.contains(dir)) {
+            watcher.watch_filtered(dir, false, EDITORCONFIG_EVENT_TOKEN, |p| {
+                p.file_name() == Some(OsStr::new(EDITORCONFIG_FILE_NAME))
+            });
+        }
+    }
+
+    #[cfg(not(feature = "notify"))]
+    fn update_editorconfig_watches(&mut self, _buffer_id: BufferId, _old_dirs: &[PathBuf]) {}
+
+    /// Watches the config directory of the project containing a buffer,
+    /// replacing the previously watched project at `old_root`.
+    #[cfg(feature = "notify")]
+    fn update_project_watch(&mut self, buffer_id: BufferId, old_root: Option<PathBuf>) {
+        let new_root = self.config_manager.get_buffer_project(buffer_id).map(Path::to_owned);
+        if new_root == old_root {
+            return;
+        }
+        let watcher = self.file_manager.watcher();
+        if let Some(root) = old_root {
+            watcher.unwatch(&root.join(config::PROJECT_CONFIG_DIR), PROJECT_CONFIG_EVENT_TOKEN);
+        }
+        if let Some(root) = new_root {
+            let dir = root.join(config::PROJECT_CONFIG_DIR);
+            watcher.watch_filtered(&dir, false, PROJECT_CONFIG_EVENT_TOKEN, |p| {
+                p.extension().and_then(OsStr::to_str).unwrap_or("") == "xiconfig"
+            });
+        }
+    }
+
+    #[cfg(not(feature = "notify"))]
+    fn update_project_watch(&mut self, _buffer_id: BufferId, _old_root: Option<PathBuf>) {}
+
     fn remove_config_at_path(&mut self, path: &Path) {
         if let Some(domain) = self.config_manager.domain_for_path(path) {
-            self.set_config(domain, Table::default());
+            self.set_config(domain, Table::default(), Some(path));
         }
     }
 
@@ -739,6 +1248,29 @@ impl CoreState {
         self.peer.available_themes(theme_names);
     }
 
+    /// Handles changes in snippets files.
+    #[cfg(feature = "notify")]
+    fn handle_snippets_fs_event(&mut self, event: DebouncedEvent) {
+        use self::DebouncedEvent::*;
+        let loaded = match event {
+            Create(ref path) | Write(ref path) => {
+                self.snippets.load_file(path).map_err(|e| (e, path))
+            }
+            NoticeRemove(ref path) | Remove(ref path) if !path.exists() => {
+                self.snippets.remove_file(path);
+                Ok(())
+            }
+            Rename(ref old, ref new) => {
+                self.snippets.remove_file(old);
+                self.snippets.load_file(new).map_err(|e| (e, new))
+            }
+            _ => Ok(()),
+        };
+        if let Err((e, path)) = loaded {
+            self.peer.config_errors(&e.into_validation_errors(Some(path)));
+        }
+    }
+
     /// Load a single theme file. Updates if already present.
     fn load_theme_file(&mut self, path: &Path) {
         let _t = trace_block("CoreState::load_theme_file", &["core"]);
@@ -937,6 +1469,20 @@ impl Counter {
     }
 }
 
+// these two only exist so that we can use BufferIds as the idle tokens of
+// recovery timers
+impl From<usize> for BufferId {
+    fn from(src: usize) -> BufferId {
+        BufferId(src)
+    }
+}
+
+impl From<BufferId> for usize {
+    fn from(src: BufferId) -> usize {
+        src.0
+    }
+}
+
 // these two only exist so that we can use ViewIds as idle tokens
 impl From<usize> for ViewId {
     fn from(src: usize) -> ViewId {
@@ -18,21 +18,29 @@ use std::ops::Range;
 
 use serde_json::Value;
 
+use brackets::{BracketMatcher, BracketPair};
 use client::Client;
+use column_selection::{column_selection, ColumnPosition};
+use config::BufferItems;
 use edit_types::ViewEvent;
-use find::{Find, FindStatus};
+use expand_selection::expand_region;
+use find::{self, Find, FindStatus};
+use fold::{self, Folds};
+use layers::Layers;
 use line_cache_shadow::{self, LineCacheShadow, RenderPlan, RenderTactic};
 use linewrap;
+use modal::{text_object_region, ModalState, TextObject};
 use movement::{region_movement, selection_movement, Movement};
 use rpc::{FindQuery, GestureType, MouseAction, SelectionModifier};
 use selection::{Affinity, InsertDrift, SelRegion, Selection};
-use styles::{Style, ThemeStyleMap};
+use snippet::SnippetSession;
+use styles::{Style, ThemeStyleMap, MATCHING_BRACKETS_STYLE, N_FIND_STYLES};
 use tabs::{BufferId, Counter, ViewId};
 use width_cache::WidthCache;
 use word_boundaries::WordCursor;
 use xi_rope::breaks::{Breaks, BreaksBaseMetric, BreaksInfo, BreaksMetric};
-use xi_rope::spans::Spans;
-use xi_rope::{Cursor, Interval, LinesMetric, Rope, RopeDelta, RopeInfo};
+use xi_rope::spans::{Spans, SpansBuilder};
+use xi_rope::{Cursor, Interval, LinesMetric, Rope, RopeDelta, RopeInfo, Transformer};
 use xi_trace::trace_block;
 
 type StyleMap = RefCell<ThemeStyleMap>;
@@ -61,6 +69,9 @@ pub struct View {
     breaks: Option<Breaks>,
     wrap_col: WrapWidth,
 
+    /// Regions of the text that are hidden in this view.
+    folds: Folds,
+
     /// Front end's line cache state for this view. See the `LineCacheShadow`
     /// description for the invariant.
     lc_shadow: LineCacheShadow,
@@ -88,6 +99,42 @@ pub struct View {
 
     /// Tracks whether the replacement string or replace parameters changed.
     replace_changed: bool,
+
+    /// The bracket pairs around the end of selection regions, keyed by that
+    /// offset. The pairs around carets are highlighted.
+    brackets: Vec<(usize, BracketPair)>,
+
+    /// The selection and visible lines for which the highlighted brackets
+    /// were found, or `None` if the text or its scopes changed since.
+    brackets_for: Option<(Selection, usize, usize)>,
+
+    /// The selections before each selection expansion, followed by the
+    /// expanded selection, which shrinking restores in turn.
+    expansions: Vec<Selection>,
+
+    /// The offsets of the closing characters that were inserted along with
+    /// their opening characters, which are typed over instead of inserted
+    /// again.
+    auto_closers: Vec<usize>,
+
+    /// The selection and the new automatically inserted closers resulting
+    /// from the pending edit, in the coordinates of the edited text, for
+    /// edits whose delta doesn't determine them.
+    pending_selection: Option<(Selection, Vec<usize>)>,
+
+    /// The anchor and the head of the last column selection. The selection
+    /// is still a column selection if it is the selection between them.
+    column_selection: Option<(ColumnPosition, ColumnPosition)>,
+
+    /// The mode and the partly typed command of the modal input layer.
+    modal: ModalState,
+
+    /// The tab stops of the snippet being filled in, if any.
+    snippet: Option<SnippetSession>,
+
+    /// The session of the snippet inserted by the pending edit, in the
+    /// coordinates of the edited text.
+    pending_snippet: Option<SnippetSession>,
 }
 
 /// Indicates what changed in the find state.
@@ -140,6 +187,8 @@ pub enum SelectionGranularity {
     Word,
     /// Selects one line at a time
     Line,
+    /// Selects a block of the same columns on each line
+    Column,
 }
 
 /// State required to resolve a drag gesture into a selection.
@@ -174,6 +223,7 @@ impl View {
             height: 10,
             breaks: None,
             wrap_col: WrapWidth::None,
+            folds: Folds::new(),
             lc_shadow: LineCacheShadow::default(),
             find: Vec::new(),
             find_id_counter: Counter::default(),
@@ -181,6 +231,15 @@ impl View {
             highlight_find: false,
             replace: None,
             replace_changed: false,
+            brackets: Vec::new(),
+            brackets_for: None,
+            expansions: Vec::new(),
+            auto_closers: Vec::new(),
+            pending_selection: None,
+            column_selection: None,
+            modal: ModalState::new(),
+            snippet: None,
+            pending_snippet: None,
         }
     }
 
@@ -192,6 +251,10 @@ impl View {
         self.view_id
     }
 
+    pub(crate) fn modal_state(&mut self) -> &mut ModalState {
+        &mut self.modal
+    }
+
     pub(crate) fn get_replace(&self) -> Option<Replace> {
         self.replace.clone()
     }
@@ -204,16 +267,26 @@ impl View {
         self.pending_render
     }
 
-    pub(crate) fn do_edit(&mut self, text: &Rope, cmd: ViewEvent) {
+    pub(crate) fn do_edit(
+        &mut self,
+        text: &Rope,
+        layers: &Layers,
+        config: &BufferItems,
+        cmd: ViewEvent,
+    ) {
         use self::ViewEvent::*;
         match cmd {
-            Move(movement) => self.do_move(text, movement, false),
-            ModifySelection(movement) => self.do_move(text, movement, true),
+            Move(movement) => self.do_move(text, config, movement, false),
+            ModifySelection(movement) => self.do_move(text, config, movement, true),
             SelectAll => self.select_all(text),
             Scroll(range) => self.set_scroll(range.first, range.last),
-            AddSelectionAbove => self.add_selection_by_movement(text, Movement::UpExactPosition),
-            AddSelectionBelow => self.add_selection_by_movement(text, Movement::DownExactPosition),
-            Gesture { line, col, ty } => self.do_gesture(text, line, col, ty),
+            AddSelectionAbove => {
+                self.add_selection_by_movement(text, config, Movement::UpExactPosition)
+            }
+            AddSelectionBelow => {
+                self.add_selection_by_movement(text, config, Movement::DownExactPosition)
+            }
+            Gesture { line, col, ty } => self.do_gesture(text, config, line, col, ty),
             GotoLine { line } => self.goto_line(text, line),
             Find { chars, case_sensitive, regex, whole_words } => {
                 let id = self.find.first().and_then(|q| Some(q.id()));
@@ -233,17 +306,17 @@ impl View {
                 // should be removed in favor of do_gesture
                 warn!("Usage of click is deprecated; use do_gesture");
                 if (flags & FLAG_SELECT) != 0 {
-                    self.do_gesture(text, line, column, GestureType::RangeSelect)
+                    self.do_gesture(text, config, line, column, GestureType::RangeSelect)
                 } else if click_count == Some(2) {
-                    self.do_gesture(text, line, column, GestureType::WordSelect)
+                    self.do_gesture(text, config, line, column, GestureType::WordSelect)
                 } else if click_count == Some(3) {
-                    self.do_gesture(text, line, column, GestureType::LineSelect)
+                    self.do_gesture(text, config, line, column, GestureType::LineSelect)
                 } else {
-                    self.do_gesture(text, line, column, GestureType::PointSelect)
+                    self.do_gesture(text, config, line, column, GestureType::PointSelect)
                 }
             }
             Drag(MouseAction { line, column, .. }) => {
-                self.do_drag(text, line, column, Affinity::default())
+                self.do_drag(text, config, line, column, Affinity::default())
             }
             Cancel => self.do_cancel(text),
             HighlightFind { visible } => {
@@ -255,10 +328,32 @@ impl View {
             Replace { chars, preserve_case } => self.do_set_replace(chars, preserve_case),
             SelectionForReplace => self.do_selection_for_replace(text),
             SelectionIntoLines => self.do_split_selection_into_lines(text),
+            ColumnSelectionIntoLines => self.do_column_selection_into_lines(text, config),
+            ColumnSelect(movement) => self.move_column_selection(text, config, movement),
+            SelectMatchingBrackets => self.select_matching_brackets(text),
+            ExpandSelection => {
+                let matcher = BracketMatcher::new(text, &config.bracket_pairs, layers);
+                self.expand_selection(text, layers, &matcher)
+            }
+            ShrinkSelection => self.shrink_selection(text),
+            SelectTextObject { object, count } => self.select_text_object(text, object, count),
+            NextTabStop => self.move_to_tab_stop(text, true),
+            PrevTabStop => self.move_to_tab_stop(text, false),
+            Fold => self.do_fold(text, layers),
+            Unfold => self.do_unfold(text),
+            ToggleFold => self.do_toggle_fold(text, layers),
+            UnfoldAll => self.do_unfold_all(text),
         }
     }
 
-    fn do_gesture(&mut self, text: &Rope, line: u64, col: u64, ty: GestureType) {
+    fn do_gesture(
+        &mut self,
+        text: &Rope,
+        config: &BufferItems,
+        line: u64,
+        col: u64,
+        ty: GestureType,
+    ) {
         let line = line as usize;
         let col = col as usize;
         let offset = self.line_col_to_offset(text, line, col);
@@ -273,10 +368,16 @@ impl View {
             GestureType::WordSelect => self.select_word(text, offset, false),
             GestureType::MultiLineSelect => self.select_line(text, offset, line, true),
             GestureType::MultiWordSelect => self.select_word(text, offset, true),
+            GestureType::ColumnSelect => {
+                let position = self.column_position(text, config, line, col);
+                self.set_column_selection(text, config, position, position);
+                self.start_drag(offset, offset, offset, SelectionGranularity::Column, false);
+            }
         }
     }
 
     fn do_cancel(&mut self, text: &Rope) {
+        self.snippet = None;
         // if we have active find highlights, we don't collapse selections
         if self.find.is_empty() {
             self.collapse_selections(text);
@@ -311,6 +412,15 @@ impl View {
         self.height
     }
 
+    pub fn first_line(&self) -> usize {
+        self.first_line
+    }
+
+    /// Asks the frontend to scroll the given offset into view.
+    pub fn scroll_to_offset(&mut self, offset: usize) {
+        self.scroll_to = Some(offset);
+    }
+
     fn scroll_to_cursor(&mut self, text: &Rope) {
         let end = self.sel_regions().last().unwrap().end;
         let line = self.line_of_offset(text, end);
@@ -348,15 +458,17 @@ impl View {
     ///
     /// If `modify` is `true`, the selections are modified, otherwise the results
     /// of individual region movements become carets.
-    pub fn do_move(&mut self, text: &Rope, movement: Movement, modify: bool) {
+    pub fn do_move(&mut self, text: &Rope, config: &BufferItems, movement: Movement, modify: bool) {
         self.drag_state = None;
-        let new_sel = selection_movement(movement, &self.selection, self, text, modify);
+        let separators = &config.subword_separators;
+        let new_sel = selection_movement(movement, &self.selection, self, text, separators, modify);
         self.set_selection(text, new_sel);
     }
 
     /// Set the selection to a new value.
     pub fn set_selection<S: Into<Selection>>(&mut self, text: &Rope, sel: S) {
         self.set_selection_raw(text, sel.into());
+        self.prune_auto_closers(text);
         self.scroll_to_cursor(text);
     }
 
@@ -390,11 +502,209 @@ impl View {
         self.lc_shadow.partial_invalidate(first_line, last_line, invalid);
     }
 
-    fn add_selection_by_movement(&mut self, text: &Rope, movement: Movement) {
+    /// Finds the bracket pairs around the carets on the visible lines, which
+    /// are highlighted, unless the text, the selection and the visible lines
+    /// are the same as when they were last found.
+    pub(crate) fn update_brackets(&mut self, text: &Rope, matcher: &BracketMatcher) {
+        let (first_line, height) = (self.first_line, self.height);
+        let unchanged = self.brackets_for.as_ref().map_or(false, |&(ref sel, first, h)| {
+            **sel == *self.selection && first == first_line && h == height
+        });
+        if unchanged {
+            return;
+        }
+        let brackets = self
+            .selection
+            .iter()
+            .filter(|region| region.is_caret())
+            .filter(|region| {
+                let line = self.line_of_offset(text, region.end);
+                line >= first_line && line <= first_line + height
+            })
+            .filter_map(|region| matcher.pair_at(region.end).map(|pair| (region.end, pair)))
+            .collect();
+        self.set_brackets(text, brackets);
+        self.brackets_for = Some((self.selection.clone(), first_line, height));
+    }
+
+    /// Finds the bracket pair around the end of each selection region, for
+    /// bracket movements.
+    pub(crate) fn find_all_brackets(&mut self, text: &Rope, matcher: &BracketMatcher) {
+        let brackets = self
+            .selection
+            .iter()
+            .filter_map(|region| matcher.pair_at(region.end).map(|pair| (region.end, pair)))
+            .collect();
+        self.set_brackets(text, brackets);
+        // only the visible carets' brackets are highlighted
+        self.brackets_for = None;
+    }
+
+    /// Marks the highlighted brackets as out of date, as the scopes that
+    /// tell which brackets are in strings and comments changed.
+    pub(crate) fn scopes_changed(&mut self) {
+        self.brackets_for = None;
+    }
+
+    /// Replaces the bracket pairs, invalidating the lines whose highlighted
+    /// brackets change.
+    fn set_brackets(&mut self, text: &Rope, brackets: Vec<(usize, BracketPair)>) {
+        if brackets != self.brackets {
+            self.invalidate_brackets(text);
+            self.brackets = brackets;
+            self.invalidate_brackets(text);
+        }
+    }
+
+    /// Returns the bracket pair around `offset`, if it is the end of a
+    /// selection region.
+    pub fn bracket_pair_at(&self, offset: usize) -> Option<BracketPair> {
+        self.brackets.iter().find(|&&(end, _)| end == offset).map(|&(_, pair)| pair)
+    }
+
+    fn invalidate_brackets(&mut self, text: &Rope) {
+        for (_, pair) in self.brackets.clone() {
+            for bracket in &[pair.open, pair.close] {
+                self.invalidate_styles(text, bracket.start(), bracket.end());
+            }
+        }
+    }
+
+    /// Returns the brackets that are highlighted, those of the pairs
+    /// around carets.
+    pub(crate) fn highlighted_brackets(&self) -> Vec<Interval> {
+        let mut brackets = self
+            .brackets
+            .iter()
+            .filter(|&&(end, _)| self.selection.iter().any(|r| r.is_caret() && r.end == end))
+            .flat_map(|&(_, pair)| vec![pair.open, pair.close])
+            .collect::<Vec<_>>();
+        brackets.sort_by_key(|iv| iv.start());
+        brackets.dedup();
+        brackets
+    }
+
+    /// Selects the bracket pair around each selection region, including
+    /// the brackets.
+    fn select_matching_brackets(&mut self, text: &Rope) {
+        let mut sel = Selection::new();
+        for &region in self.sel_regions() {
+            match self.bracket_pair_at(region.end) {
+                Some(pair) => sel.add_region(SelRegion::new(pair.open.start(), pair.close.end())),
+                None => sel.add_region(region),
+            }
+        }
+        self.set_selection(text, sel);
+    }
+
+    /// Expands each selection region to the syntactic unit around it,
+    /// remembering the selection so that it can be restored by shrinking.
+    fn expand_selection(&mut self, text: &Rope, layers: &Layers, matcher: &BracketMatcher) {
+        let is_current = |sel: &Selection| **sel == *self.selection;
+        if !self.expansions.last().map_or(false, is_current) {
+            self.expansions = vec![self.selection.clone()];
+        }
+        let mut sel = Selection::new();
+        for &region in self.sel_regions() {
+            sel.add_region(expand_region(text, layers, matcher, region));
+        }
+        if *sel != *self.selection {
+            self.expansions.push(sel.clone());
+            self.set_selection(text, sel);
+        }
+    }
+
+    /// Restores the selection from before the last expansion, if the
+    /// selection hasn't changed since.
+    fn shrink_selection(&mut self, text: &Rope) {
+        let expanded = self.expansions.pop();
+        match (expanded, self.expansions.last().cloned()) {
+            (Some(expanded), Some(sel)) if *expanded == *self.selection => {
+                self.set_selection(text, sel)
+            }
+            _ => self.expansions.clear(),
+        }
+    }
+
+    /// Selects the text object around each selection region, leaving the
+    /// regions without one unchanged.
+    fn select_text_object(&mut self, text: &Rope, object: TextObject, count: usize) {
+        let mut sel = Selection::new();
+        for &region in self.sel_regions() {
+            sel.add_region(text_object_region(text, region, object, count).unwrap_or(region));
+        }
+        self.set_selection(text, sel);
+    }
+
+    /// Returns `true` if the closing character at `offset` was inserted
+    /// along with its opening character.
+    pub(crate) fn is_auto_closer(&self, offset: usize) -> bool {
+        self.auto_closers.contains(&offset)
+    }
+
+    pub(crate) fn remove_auto_closer(&mut self, offset: usize) {
+        self.auto_closers.retain(|&closer| closer != offset);
+    }
+
+    /// Sets the selection and the automatically inserted closers that result
+    /// from the pending edit, which are given in the coordinates of the
+    /// edited text, as they can't be derived from the edit's delta.
+    pub(crate) fn set_auto_close_result(&mut self, sel: Selection, closers: Vec<usize>) {
+        self.pending_selection = Some((sel, closers));
+    }
+
+    /// Sets the selection that results from the pending edit, given in the
+    /// coordinates of the edited text, for edits that replace the selected
+    /// text and would otherwise collapse the selection.
+    pub(crate) fn set_pending_selection(&mut self, sel: Selection) {
+        self.pending_selection = Some((sel, Vec::new()));
+    }
+
+    /// Starts filling in the snippet inserted by the pending edit, whose tab
+    /// stops are given in the coordinates of the edited text, by selecting
+    /// its first tab stop.
+    pub(crate) fn start_snippet(&mut self, session: SnippetSession) {
+        self.pending_selection = Some((session.selection(), Vec::new()));
+        self.snippet = None;
+        if !session.is_finished() {
+            self.pending_snippet = Some(session);
+        }
+    }
+
+    /// Selects the next tab stop of the snippet being filled in, or the
+    /// previous one. Selecting the final tab stop finishes the snippet.
+    fn move_to_tab_stop(&mut self, text: &Rope, forward: bool) {
+        let mut session = match self.snippet.take() {
+            Some(session) => session,
+            None => return,
+        };
+        session.move_to_tab_stop(forward);
+        self.set_selection(text, session.selection());
+        if !session.is_finished() {
+            self.snippet = Some(session);
+        }
+    }
+
+    /// Forgets the automatically inserted closers that are not after a caret
+    /// on the same line, so that only the closers the user is still typing
+    /// towards are typed over.
+    fn prune_auto_closers(&mut self, text: &Rope) {
+        let selection = &self.selection;
+        self.auto_closers.retain(|&closer| {
+            selection.iter().any(|region| {
+                region.is_caret()
+                    && region.end <= closer
+                    && text.line_of_offset(region.end) == text.line_of_offset(closer)
+            })
+        });
+    }
+
+    fn add_selection_by_movement(&mut self, text: &Rope, config: &BufferItems, movement: Movement) {
+        let separators = &config.subword_separators;
         let mut sel = Selection::new();
         for &region in self.sel_regions() {
             sel.add_region(region);
-            let new_region = region_movement(movement, region, self, &text, false);
+            let new_region = region_movement(movement, region, self, &text, separators, false);
             sel.add_region(new_region);
         }
         self.set_selection(text, sel);
@@ -495,6 +805,87 @@ impl View {
         self.set_selection_raw(text, selection);
     }
 
+    /// Sets the selection to the column selection between `anchor` and `head`.
+    fn set_column_selection(
+        &mut self,
+        text: &Rope,
+        config: &BufferItems,
+        anchor: ColumnPosition,
+        head: ColumnPosition,
+    ) {
+        let sel = column_selection(text, anchor, head, config.tab_size);
+        self.set_selection(text, sel);
+        self.column_selection = Some((anchor, head));
+    }
+
+    /// Returns the anchor and the head of the column selection, which, if
+    /// the selection is not a column selection, are the start and the end
+    /// of its last region.
+    fn get_column_selection(
+        &self,
+        text: &Rope,
+        config: &BufferItems,
+    ) -> (ColumnPosition, ColumnPosition) {
+        if let Some((anchor, head)) = self.column_selection {
+            if *column_selection(text, anchor, head, config.tab_size) == *self.selection {
+                return (anchor, head);
+            }
+        }
+        let region = self.selection.last().unwrap();
+        let anchor = ColumnPosition::from_offset(text, region.start, config.tab_size);
+        let head = ColumnPosition::from_offset(text, region.end, config.tab_size);
+        (anchor, head)
+    }
+
+    /// Returns the position of the client's `line` and `col`. Past the end
+    /// of the line, the column is kept rather than clamped to the line's end,
+    /// so that dragging over a short line doesn't narrow the block.
+    fn column_position(
+        &self,
+        text: &Rope,
+        config: &BufferItems,
+        line: usize,
+        col: usize,
+    ) -> ColumnPosition {
+        let offset = self.line_col_to_offset(text, line, col);
+        let mut position = ColumnPosition::from_offset(text, offset, config.tab_size);
+        if offset == fold::end_of_line(text, position.line) {
+            let line_len = offset - self.offset_of_line(text, line);
+            position.col += col.saturating_sub(line_len);
+        }
+        position
+    }
+
+    /// Extends the column selection by moving its head up or down by a line,
+    /// or left or right by a column.
+    fn move_column_selection(&mut self, text: &Rope, config: &BufferItems, movement: Movement) {
+        self.drag_state = None;
+        let (anchor, mut head) = self.get_column_selection(text, config);
+        match movement {
+            Movement::Up => head.line = head.line.saturating_sub(1),
+            Movement::Down => head.line = min(head.line + 1, text.measure::<LinesMetric>()),
+            Movement::Left => head.col = head.col.saturating_sub(1),
+            Movement::Right => head.col += 1,
+            _ => return,
+        }
+        self.set_column_selection(text, config, anchor, head);
+    }
+
+    /// Replaces each region with a column selection between its start and
+    /// its end, so that a region spanning several lines becomes a region on
+    /// each of them.
+    fn do_column_selection_into_lines(&mut self, text: &Rope, config: &BufferItems) {
+        let mut selection = Selection::new();
+        for region in self.selection.iter() {
+            let anchor = ColumnPosition::from_offset(text, region.start, config.tab_size);
+            let head = ColumnPosition::from_offset(text, region.end, config.tab_size);
+            for &region in column_selection(text, anchor, head, config.tab_size).iter() {
+                selection.add_region(region);
+            }
+        }
+        self.set_selection(text, selection);
+    }
+
     /// Starts a drag operation.
     pub fn start_drag(
         &mut self,
@@ -513,13 +904,26 @@ impl View {
 
     /// Does a drag gesture, setting the selection from a combination of the drag
     /// state and new offset.
-    fn do_drag(&mut self, text: &Rope, line: u64, col: u64, affinity: Affinity) {
+    fn do_drag(
+        &mut self,
+        text: &Rope,
+        config: &BufferItems,
+        line: u64,
+        col: u64,
+        affinity: Affinity,
+    ) {
         let offset = self.line_col_to_offset(text, line as usize, col as usize);
+        if let Some(DragState { granularity: SelectionGranularity::Column, .. }) = self.drag_state {
+            let (anchor, _) = self.get_column_selection(text, config);
+            let head = self.column_position(text, config, line as usize, col as usize);
+            self.set_column_selection(text, config, anchor, head);
+            return;
+        }
         let new_sel = self.drag_state.as_ref().map(|drag_state| {
             let mut sel = drag_state.base_sel.clone();
             // Determine which word or line the cursor is in
             let (unit_start, unit_end) = match drag_state.granularity {
-                SelectionGranularity::Point => (offset, offset),
+                SelectionGranularity::Point | SelectionGranularity::Column => (offset, offset),
                 SelectionGranularity::Word => {
                     let mut word_cursor = WordCursor::new(text, offset);
                     word_cursor.select_word()
@@ -569,61 +973,116 @@ impl View {
         styles: &StyleMap,
         text: &Rope,
         start_of_line: &mut Cursor<RopeInfo>,
-        soft_breaks: Option<&mut Cursor<BreaksInfo>>,
+        mut soft_breaks: Option<&mut Cursor<BreaksInfo>>,
         style_spans: &Spans<Style>,
         line_num: usize,
     ) -> Value {
         let start_pos = start_of_line.pos();
-        let pos = soft_breaks
-            .map_or(start_of_line.next::<LinesMetric>(), |bc| {
-                let pos = bc.next::<BreaksMetric>();
-                // if using breaks update cursor
-                if let Some(pos) = pos {
-                    start_of_line.set(pos)
-                }
-                pos
-            }).unwrap_or(text.len());
+        let mut pos =
+            next_line_start(text, start_of_line, soft_breaks.as_mut().map(|bc| &mut **bc));
+
+        // A fold starting on this line hides its contents, and joins this line
+        // with the rest of the line on which the fold ends.
+        let mut segments = Vec::new();
+        let mut seg_start = start_pos;
+        while let Some(fold) = self.folds.first_starting_in(seg_start, pos) {
+            segments.push(seg_start..fold.start());
+            seg_start = fold.end();
+            start_of_line.set(seg_start);
+            if let Some(bc) = soft_breaks.as_mut() {
+                bc.set(seg_start);
+            }
+            pos = next_line_start(text, start_of_line, soft_breaks.as_mut().map(|bc| &mut **bc));
+        }
+        segments.push(seg_start..pos);
 
-        let l_str = text.slice_to_cow(start_pos..pos);
+        let mut l_str = String::new();
         let mut cursors = Vec::new();
         let mut selections = Vec::new();
-        for region in self.selection.regions_in_range(start_pos, pos) {
-            // cursor
-            let c = region.end;
-            if (c > start_pos && c < pos)
-                || (!region.is_upstream() && c == start_pos)
-                || (region.is_upstream() && c == pos)
-                || (c == pos && c == text.len() && self.line_of_offset(text, c) == line_num)
-            {
-                cursors.push(c - start_pos);
-            }
+        let mut hls = vec![Vec::new(); if self.highlight_find { self.find.len() } else { 0 }];
+        let mut brackets = Vec::new();
+        let highlighted_brackets = self.highlighted_brackets();
+        let mut folds = Vec::new();
+        for (i, seg) in segments.iter().enumerate() {
+            let (start, end) = (seg.start, seg.end);
+            let out = l_str.len();
+            let is_first = i == 0;
+            let is_last = i == segments.len() - 1;
+            for region in self.selection.regions_in_range(start, end) {
+                // cursor
+                let c = region.end;
+                if (c > start && c < end)
+                    || ((!region.is_upstream() || !is_first) && c == start)
+                    || ((region.is_upstream() || !is_last) && c == end)
+                    || (c == end && c == text.len() && self.line_of_offset(text, c) == line_num)
+                {
+                    cursors.push(out + c - start);
+                }
 
-            // selection with interior
-            let sel_start_ix = clamp(region.min(), start_pos, pos) - start_pos;
-            let sel_end_ix = clamp(region.max(), start_pos, pos) - start_pos;
-            if sel_end_ix > sel_start_ix {
-                selections.push((sel_start_ix, sel_end_ix));
+                // selection with interior
+                let sel_start_ix = clamp(region.min(), start, end) - start;
+                let sel_end_ix = clamp(region.max(), start, end) - start;
+                if sel_end_ix > sel_start_ix {
+                    selections.push((out + sel_start_ix, out + sel_end_ix));
+                }
             }
-        }
 
-        let mut hls = Vec::new();
-
-        if self.highlight_find {
-            for find in &self.find {
-                let mut cur_hls = Vec::new();
-                for region in find.occurrences().regions_in_range(start_pos, pos) {
-                    let sel_start_ix = clamp(region.min(), start_pos, pos) - start_pos;
-                    let sel_end_ix = clamp(region.max(), start_pos, pos) - start_pos;
+            for (find, cur_hls) in self.find.iter().zip(hls.iter_mut()) {
+                for region in find.occurrences().regions_in_range(start, end) {
+                    let sel_start_ix = clamp(region.min(), start, end) - start;
+                    let sel_end_ix = clamp(region.max(), start, end) - start;
                     if sel_end_ix > sel_start_ix {
-                        cur_hls.push((sel_start_ix, sel_end_ix));
+                        cur_hls.push((out + sel_start_ix, out + sel_end_ix));
                     }
                 }
-                hls.push(cur_hls);
             }
-        }
 
-        let styles =
-            self.render_styles(client, styles, start_pos, pos, &selections, &hls, style_spans);
+            for bracket in highlighted_brackets.iter() {
+                if bracket.start() >= start && bracket.end() <= end {
+                    brackets.push((out + bracket.start() - start, out + bracket.end() - start));
+                }
+            }
+
+            l_str.push_str(&text.slice_to_cow(seg.clone()));
+            if !is_last {
+                folds.push(l_str.len());
+            }
+        }
+        cursors.dedup();
+
+        let styles = if folds.is_empty() {
+            self.render_styles(
+                client,
+                styles,
+                start_pos,
+                pos,
+                &selections,
+                &hls,
+                &brackets,
+                style_spans,
+            )
+        } else {
+            // Style spans are given relative to the joined text of the line.
+            let mut sb = SpansBuilder::new(l_str.len());
+            let mut out = 0;
+            for seg in &segments {
+                for (iv, style) in style_spans.subseq(Interval::new(seg.start, seg.end)).iter() {
+                    sb.add_span(iv.translate(out), style.clone());
+                }
+                out += seg.end - seg.start;
+            }
+            let line_spans = sb.build();
+            self.render_styles(
+                client,
+                styles,
+                0,
+                l_str.len(),
+                &selections,
+                &hls,
+                &brackets,
+                &line_spans,
+            )
+        };
 
         let mut result = json!({
             "text": &l_str,
@@ -633,6 +1092,9 @@ impl View {
         if !cursors.is_empty() {
             result["cursor"] = json!(cursors);
         }
+        if !folds.is_empty() {
+            result["folds"] = json!(folds);
+        }
         result
     }
 
@@ -644,23 +1106,31 @@ impl View {
         end: usize,
         sel: &[(usize, usize)],
         hls: &Vec<Vec<(usize, usize)>>,
+        brackets: &[(usize, usize)],
         style_spans: &Spans<Style>,
     ) -> Vec<isize> {
         let mut rendered_styles = Vec::new();
         let style_spans = style_spans.subseq(Interval::new(start, end));
 
         let mut ix = 0;
-        // we add the special find highlights (1 to N) and selection (0) styles first.
-        // We add selection after find because we want it to be preferred if the
-        // same span exists in both sets (as when there is an active selection)
+        // we add the special find highlights (1 to N), matching brackets and
+        // selection (0) styles first. We add selection after find because we
+        // want it to be preferred if the same span exists in both sets (as
+        // when there is an active selection)
         for (index, cur_find_hls) in hls.iter().enumerate() {
             for &(sel_start, sel_end) in cur_find_hls {
                 rendered_styles.push((sel_start as isize) - ix);
                 rendered_styles.push(sel_end as isize - sel_start as isize);
-                rendered_styles.push(index as isize + 1);
+                rendered_styles.push(min(index, N_FIND_STYLES - 1) as isize + 1);
                 ix = sel_end as isize;
             }
         }
+        for &(br_start, br_end) in brackets {
+            rendered_styles.push((br_start as isize) - ix);
+            rendered_styles.push(br_end as isize - br_start as isize);
+            rendered_styles.push(MATCHING_BRACKETS_STYLE as isize);
+            ix = br_end as isize;
+        }
         for &(sel_start, sel_end) in sel {
             rendered_styles.push((sel_start as isize) - ix);
             rendered_styles.push(sel_end as isize - sel_start as isize);
@@ -873,11 +1343,27 @@ impl View {
 
     pub(crate) fn offset_to_line_col(&self, text: &Rope, offset: usize) -> (usize, usize) {
         let line = self.line_of_offset(text, offset);
-        (line, offset - self.offset_of_line(text, line))
+        let line_start = self.offset_of_line(text, line);
+        // folded text does not count towards the column
+        let hidden: usize = self
+            .folds
+            .iter()
+            .filter(|fold| fold.start() >= line_start && fold.start() < offset)
+            .map(|fold| min(fold.end(), offset) - fold.start())
+            .sum();
+        (line, offset - line_start - hidden)
     }
 
     pub(crate) fn line_col_to_offset(&self, text: &Rope, line: usize, col: usize) -> usize {
-        let mut offset = self.offset_of_line(text, line).saturating_add(col);
+        let line_start = self.offset_of_line(text, line);
+        let next_line_offset = self.offset_of_line(text, line + 1);
+        let mut offset = line_start.saturating_add(col);
+        for fold in self.folds.iter().filter(|fold| fold.start() >= line_start) {
+            if fold.start() >= offset || fold.start() >= next_line_offset {
+                break;
+            }
+            offset = offset.saturating_add(fold.size());
+        }
         if offset >= text.len() {
             offset = text.len();
             if self.line_of_offset(text, offset) <= line {
@@ -889,7 +1375,6 @@ impl View {
         }
 
         // clamp to end of line
-        let next_line_offset = self.offset_of_line(text, line + 1);
         if offset >= next_line_offset {
             if let Some(prev) = text.prev_grapheme_offset(next_line_offset) {
                 offset = prev;
@@ -898,18 +1383,42 @@ impl View {
         offset
     }
 
-    // use own breaks if present, or text if not (no line wrapping)
-
     /// Returns the visible line number containing the given offset.
+    ///
+    /// Lines hidden by folds are not counted; an offset inside a fold
+    /// belongs to the line on which the fold starts.
     pub fn line_of_offset(&self, text: &Rope, offset: usize) -> usize {
+        let mut line = self.unfolded_line_of_offset(text, offset);
+        for fold in self.folds.iter().take_while(|fold| fold.start() < offset) {
+            let fold_line = self.unfolded_line_of_offset(text, fold.start());
+            line -= self.unfolded_line_of_offset(text, min(fold.end(), offset)) - fold_line;
+        }
+        line
+    }
+
+    /// Returns the byte offset corresponding to the line `line`.
+    pub fn offset_of_line(&self, text: &Rope, line: usize) -> usize {
+        let mut hidden = 0;
+        for fold in self.folds.iter() {
+            let fold_line = self.unfolded_line_of_offset(text, fold.start());
+            if fold_line - hidden >= line {
+                break;
+            }
+            hidden += self.unfolded_line_of_offset(text, fold.end()) - fold_line;
+        }
+        self.unfolded_offset_of_line(text, line + hidden)
+    }
+
+    // use own breaks if present, or text if not (no line wrapping)
+
+    fn unfolded_line_of_offset(&self, text: &Rope, offset: usize) -> usize {
         match self.breaks {
             Some(ref breaks) => breaks.convert_metrics::<BreaksBaseMetric, BreaksMetric>(offset),
             None => text.line_of_offset(offset),
         }
     }
 
-    /// Returns the byte offset corresponding to the line `line`.
-    pub fn offset_of_line(&self, text: &Rope, line: usize) -> usize {
+    fn unfolded_offset_of_line(&self, text: &Rope, line: usize) -> usize {
         match self.breaks {
             Some(ref breaks) => breaks.convert_metrics::<BreaksMetric, BreaksBaseMetric>(line),
             None => {
@@ -920,6 +1429,16 @@ impl View {
         }
     }
 
+    /// If `offset` is inside a folded region, returns the nearest visible
+    /// offset in the given direction; otherwise returns `offset` unchanged.
+    pub(crate) fn skip_folded(&self, offset: usize, backward: bool) -> usize {
+        match self.folds.containing(offset) {
+            Some(fold) if backward => fold.start(),
+            Some(fold) => fold.end(),
+            None => offset,
+        }
+    }
+
     pub(crate) fn rewrap(&mut self, text: &Rope, wrap_col: usize) {
         if wrap_col > 0 {
             self.breaks = Some(linewrap::linewrap(text, wrap_col));
@@ -956,7 +1475,14 @@ impl View {
         width_cache: &mut WidthCache,
         drift: InsertDrift,
     ) {
+        // the brackets are found again before the next render
+        self.invalidate_brackets(last_text);
+        self.brackets.clear();
+        self.brackets_for = None;
+
         let (iv, new_len) = delta.summary();
+        let had_folds = !self.folds.is_empty();
+        self.folds.apply_delta(delta);
         if let Some(breaks) = self.breaks.as_mut() {
             match self.wrap_col {
                 WrapWidth::None => (),
@@ -966,9 +1492,9 @@ impl View {
                 }
             }
         }
-        if self.breaks.is_some() {
-            // TODO: finer grain invalidation for the line wrapping, needs info
-            // about what wrapped.
+        if self.breaks.is_some() || had_folds {
+            // TODO: finer grain invalidation for the line wrapping and folds,
+            // needs info about what wrapped.
             self.set_dirty(text);
         } else {
             let start = self.line_of_offset(last_text, iv.start());
@@ -987,12 +1513,123 @@ impl View {
 
         self.find_changed = FindStatusChange::Matches;
 
+        let mut transformer = Transformer::new(delta);
+        let mut auto_closers = self
+            .auto_closers
+            .iter()
+            .map(|&closer| transformer.transform(closer, true))
+            .filter(|&closer| closer < text.len())
+            .collect::<Vec<_>>();
+
         // Note: for committing plugin edits, we probably want to know the priority
         // of the delta so we can set the cursor before or after the edit, as needed.
-        let new_sel = self.selection.apply_delta(delta, true, drift);
+        let new_sel = match self.pending_selection.take() {
+            Some((sel, closers)) => {
+                auto_closers.extend(closers);
+                sel
+            }
+            None => self.selection.apply_delta(delta, true, drift),
+        };
+        auto_closers.sort();
+        auto_closers.dedup();
+        self.auto_closers = auto_closers;
+        match self.pending_snippet.take() {
+            Some(session) => self.snippet = Some(session),
+            None => {
+                if let Some(session) = self.snippet.as_mut() {
+                    session.apply_delta(delta);
+                }
+            }
+        }
         self.set_selection_for_edit(text, new_sel);
     }
 
+    /// Folds each selected region. For carets, folds the syntax scope or,
+    /// failing that, the indentation block beginning on the caret's line,
+    /// if any.
+    fn do_fold(&mut self, text: &Rope, layers: &Layers) {
+        for region in self.selection.iter() {
+            if region.is_caret() {
+                let line = text.line_of_offset(region.end);
+                let fold = fold::scope_fold_for_line(text, layers, line)
+                    .or_else(|| fold::indent_fold_for_line(text, line));
+                if let Some(iv) = fold {
+                    self.folds.add(iv);
+                }
+            } else {
+                self.folds.add(Interval::new(region.min(), region.max()));
+            }
+        }
+        // Regions that are now partly hidden collapse to the start of the fold.
+        let mut selection = Selection::new();
+        for &region in self.selection.iter() {
+            let hidden = self.folds.containing(region.end).or(self.folds.containing(region.start));
+            let new_region = match hidden {
+                Some(fold) => SelRegion::caret(fold.start()),
+                None if !region.is_caret()
+                    && self.folds.intersects(region.min() + 1, region.max() - 1) =>
+                {
+                    SelRegion::caret(region.min())
+                }
+                None => region,
+            };
+            selection.add_region(new_region);
+        }
+        self.set_dirty(text);
+        self.set_selection(text, selection);
+    }
+
+    /// Removes any folds on the lines touched by the selection.
+    fn do_unfold(&mut self, text: &Rope) {
+        let mut changed = false;
+        for (start, end) in self.selected_line_ranges(text) {
+            changed |= self.folds.remove_range(start, end);
+        }
+        if changed {
+            self.set_dirty(text);
+        }
+    }
+
+    /// Unfolds if there are folds on the lines touched by the selection,
+    /// and folds otherwise.
+    fn do_toggle_fold(&mut self, text: &Rope, layers: &Layers) {
+        let is_folded = self
+            .selected_line_ranges(text)
+            .into_iter()
+            .any(|(start, end)| self.folds.intersects(start, end));
+        if is_folded {
+            self.do_unfold(text);
+        } else {
+            self.do_fold(text, layers);
+        }
+    }
+
+    fn do_unfold_all(&mut self, text: &Rope) {
+        if !self.folds.is_empty() {
+            self.folds.clear();
+            self.set_dirty(text);
+        }
+    }
+
+    /// Returns, for each selection region, the offsets of the start and end
+    /// of the visible lines it touches.
+    fn selected_line_ranges(&self, text: &Rope) -> Vec<(usize, usize)> {
+        self.selection
+            .iter()
+            .map(|region| {
+                let first_line = self.line_of_offset(text, region.min());
+                let last_line = self.line_of_offset(text, region.max());
+                let start = self.offset_of_line(text, first_line);
+                let next_line_start = self.offset_of_line(text, last_line + 1);
+                let end = if next_line_start == text.len() {
+                    next_line_start
+                } else {
+                    next_line_start - 1
+                };
+                (start, end)
+            }).collect()
+    }
+
     fn do_selection_for_find(&mut self, text: &Rope, case_sensitive: bool) {
         // set last selection or word under current cursor as search query
         let search_query = match self.selection.last() {
@@ -1027,6 +1664,11 @@ impl View {
         self.find.push(Find::new(id));
     }
 
+    /// Returns queries that recreate the active searches.
+    pub fn find_queries(&self) -> Vec<FindQuery> {
+        self.find.iter().filter_map(Find::query).collect()
+    }
+
     pub fn do_find(&mut self, text: &Rope, queries: Vec<FindQuery>) {
         self.set_dirty(text);
         self.find_changed = FindStatusChange::Matches;
@@ -1173,6 +1815,27 @@ impl View {
         self.do_set_replace(replacement.into_owned(), false);
     }
 
+    /// Returns the text that should replace the find match `region`, with
+    /// any capture groups expanded and, if requested, the case of the match
+    /// preserved.
+    pub(crate) fn replacement_for(
+        &self,
+        text: &Rope,
+        region: &SelRegion,
+        replace: &Replace,
+    ) -> String {
+        let matched = text.slice_to_cow(region);
+        let chars = match self.find.iter().find(|f| f.is_occurrence(region)) {
+            Some(find) => find.expand_replacement(text, region, &replace.chars),
+            None => replace.chars.clone(),
+        };
+        if replace.preserve_case {
+            find::preserve_case(&matched, &chars)
+        } else {
+            chars
+        }
+    }
+
     /// Get the line range of a selected region.
     pub fn get_line_range(&self, text: &Rope, region: &SelRegion) -> Range<usize> {
         let (first_line, _) = self.offset_to_line_col(text, region.min());
@@ -1195,6 +1858,24 @@ impl View {
     }
 }
 
+/// Advances the cursors to the start of the next visual line, returning its
+/// offset (or the end of the text, if this is the last line).
+fn next_line_start(
+    text: &Rope,
+    start_of_line: &mut Cursor<RopeInfo>,
+    soft_breaks: Option<&mut Cursor<BreaksInfo>>,
+) -> usize {
+    soft_breaks
+        .map_or(start_of_line.next::<LinesMetric>(), |bc| {
+            let pos = bc.next::<BreaksMetric>();
+            // if using breaks update cursor
+            if let Some(pos) = pos {
+                start_of_line.set(pos)
+            }
+            pos
+        }).unwrap_or(text.len())
+}
+
 // utility function to clamp a value within the given range
 fn clamp(x: usize, min: usize, max: usize) -> usize {
     if x < min {
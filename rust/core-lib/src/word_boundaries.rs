@@ -65,6 +65,29 @@ impl<'a> WordCursor<'a> {
         None
     }
 
+    /// Get the start of the next word, past any whitespace and line breaks,
+    /// and set the cursor there. An empty line counts as a word.
+    pub fn next_start(&mut self) -> Option<usize> {
+        use self::WordProperty::*;
+        let mut prop = get_word_property(self.inner.next_codepoint()?);
+        let mut candidate = self.inner.pos();
+        while let Some(next) = self.inner.next_codepoint() {
+            let prop_next = get_word_property(next);
+            let is_start = match (prop, prop_next) {
+                (Lf, Lf) => true,
+                (_, Lf) | (_, Space) | (Other, Other) | (Punctuation, Punctuation) => false,
+                _ => true,
+            };
+            if is_start {
+                break;
+            }
+            prop = prop_next;
+            candidate = self.inner.pos();
+        }
+        self.inner.set(candidate);
+        Some(candidate)
+    }
+
     /// Return the selection for the word containing the current cursor. The
     /// cursor is moved to the end of that selection.
     pub fn select_word(&mut self) -> (usize, usize) {
@@ -131,6 +154,61 @@ impl<'a> WordCursor<'a> {
     }
 }
 
+/// A cursor over subword boundaries, which are word boundaries and also
+/// the boundaries inside words at case transitions, such as in `parseHttp`
+/// and `HTTPRequest`, between letters and digits, and around the separator
+/// characters, such as the `_` of `snake_case`.
+pub struct SubwordCursor<'a> {
+    inner: Cursor<'a, RopeInfo>,
+    separators: &'a str,
+}
+
+impl<'a> SubwordCursor<'a> {
+    pub fn new(text: &'a Rope, pos: usize, separators: &'a str) -> SubwordCursor<'a> {
+        let inner = Cursor::new(text, pos);
+        SubwordCursor { inner, separators }
+    }
+
+    /// Get previous boundary, and set the cursor at the boundary found.
+    pub fn prev_boundary(&mut self) -> Option<usize> {
+        if let Some(mut ch) = self.inner.prev_codepoint() {
+            let mut after = None;
+            let mut candidate = self.inner.pos();
+            while let Some(prev) = self.inner.prev_codepoint() {
+                if classify_subword_boundary(prev, ch, after, self.separators).is_start() {
+                    break;
+                }
+                after = Some(ch);
+                ch = prev;
+                candidate = self.inner.pos();
+            }
+            self.inner.set(candidate);
+            return Some(candidate);
+        }
+        None
+    }
+
+    /// Get next boundary, and set the cursor at the boundary found.
+    pub fn next_boundary(&mut self) -> Option<usize> {
+        if let Some(mut ch) = self.inner.next_codepoint() {
+            let mut candidate = self.inner.pos();
+            while let Some(next) = self.inner.next_codepoint() {
+                let pos = self.inner.pos();
+                let after = self.inner.next_codepoint();
+                self.inner.set(pos);
+                if classify_subword_boundary(ch, next, after, self.separators).is_end() {
+                    break;
+                }
+                ch = next;
+                candidate = pos;
+            }
+            self.inner.set(candidate);
+            return Some(candidate);
+        }
+        None
+    }
+}
+
 #[derive(PartialEq, Eq)]
 enum WordBoundary {
     Interior,
@@ -169,6 +247,38 @@ fn classify_boundary(prev: WordProperty, next: WordProperty) -> WordBoundary {
     }
 }
 
+/// Classifies the boundary between `prev` and `next`, where `after` is the
+/// character after `next`, for subwords. Separators are treated as spaces.
+fn classify_subword_boundary(
+    prev: char,
+    next: char,
+    after: Option<char>,
+    separators: &str,
+) -> WordBoundary {
+    let property = |c| {
+        if separators.contains(c) {
+            WordProperty::Space
+        } else {
+            get_word_property(c)
+        }
+    };
+    match (property(prev), property(next)) {
+        (WordProperty::Other, WordProperty::Other) => {
+            let split = (!prev.is_uppercase() && next.is_uppercase())
+                || prev.is_numeric() != next.is_numeric()
+                || (prev.is_uppercase()
+                    && next.is_uppercase()
+                    && after.map_or(false, char::is_lowercase));
+            if split {
+                WordBoundary::Both
+            } else {
+                WordBoundary::Interior
+            }
+        }
+        (prev, next) => classify_boundary(prev, next),
+    }
+}
+
 fn classify_boundary_initial(prev: WordProperty, next: WordProperty) -> WordBoundary {
     use self::WordBoundary::*;
     use self::WordProperty::*;
@@ -213,3 +323,50 @@ fn get_word_property(codepoint: char) -> WordProperty {
     }
     WordProperty::Other
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subword_boundaries(s: &str, separators: &str) -> (Vec<usize>, Vec<usize>) {
+        let text = Rope::from(s);
+        let mut cursor = SubwordCursor::new(&text, 0, separators);
+        let mut next = Vec::new();
+        while let Some(pos) = cursor.next_boundary() {
+            next.push(pos);
+        }
+        let mut cursor = SubwordCursor::new(&text, text.len(), separators);
+        let mut prev = Vec::new();
+        while let Some(pos) = cursor.prev_boundary() {
+            prev.push(pos);
+        }
+        (next, prev)
+    }
+
+    #[test]
+    fn subwords() {
+        let (next, prev) = subword_boundaries("parseHttpRequestHeader", "_");
+        assert_eq!(next, vec![5, 9, 16, 22]);
+        assert_eq!(prev, vec![16, 9, 5, 0]);
+        let (next, prev) = subword_boundaries("HTTPRequest utf8_str", "_");
+        assert_eq!(next, vec![4, 11, 15, 16, 20]);
+        assert_eq!(prev, vec![17, 15, 12, 4, 0]);
+        let (next, prev) = subword_boundaries("MAX_SIZE-1", "_");
+        assert_eq!(next, vec![3, 8, 10]);
+        assert_eq!(prev, vec![9, 4, 0]);
+        let (next, _) = subword_boundaries("foo_bar-baz", "");
+        assert_eq!(next, vec![7, 11]);
+    }
+
+    #[test]
+    fn next_word_starts() {
+        let text = Rope::from("foo.bar  baz\n\n  qux");
+        let mut cursor = WordCursor::new(&text, 0);
+        let mut starts = Vec::new();
+        while let Some(pos) = cursor.next_start() {
+            starts.push(pos);
+        }
+        // punctuation is a word, and an empty line counts as one
+        assert_eq!(starts, vec![3, 4, 9, 13, 16, 19]);
+    }
+}
@@ -238,6 +238,10 @@ const MOVEMENT_RPCS: &str = r#"{"method":"edit","params":{"view_id":"view-id-1",
 {"method":"edit","params":{"view_id":"view-id-1","method":"move_word_right","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"move_word_left_and_modify_selection","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"move_word_right_and_modify_selection","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"move_subword_left","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"move_subword_right","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"move_subword_left_and_modify_selection","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"move_subword_right_and_modify_selection","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"move_to_beginning_of_paragraph","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"move_to_end_of_paragraph","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"move_to_left_end_of_line","params":[]}}
@@ -253,6 +257,9 @@ const MOVEMENT_RPCS: &str = r#"{"method":"edit","params":{"view_id":"view-id-1",
 {"method":"edit","params":{"view_id":"view-id-1","method":"page_up_and_modify_selection","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"page_down_and_modify_selection","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"select_all","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"expand_selection","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"shrink_selection","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"select_text_object","params":{"object":"parentheses","inner":true}}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"add_selection_above","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"add_selection_below","params":[]}}"#;
 
@@ -262,7 +269,10 @@ const TEXT_EDIT_RPCS: &str =
 {"method":"edit","params":{"view_id":"view-id-1","method":"delete_forward","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"delete_word_forward","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"delete_word_backward","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"delete_subword_forward","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"delete_subword_backward","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"delete_to_end_of_paragraph","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"delete_text_object","params":{"object":"double_quotes"}}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"insert_newline","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"insert_tab","params":[]}}
 {"method":"edit","params":{"view_id":"view-id-1","method":"yank","params":[]}}
@@ -29,6 +29,54 @@ use xi_core::LanguageDefinition;
 
 const OUT_FILE_NAME: &str = "generated_manifest.toml";
 
+/// The comment tokens of languages, by scope: the token that starts a line
+/// comment, and the tokens that start and end a block comment. Syntect's
+/// syntax definitions don't include these. Line comment tokens made of
+/// letters end in a space, so that they only match as a separate word.
+const COMMENT_TOKENS: &[(&str, Option<&str>, Option<(&str, &str)>)] = &[
+    ("source.actionscript.2", Some("//"), Some(("/*", "*/"))),
+    ("source.applescript", Some("--"), Some(("(*", "*)"))),
+    ("source.asp", Some("'"), None),
+    ("source.c", Some("//"), Some(("/*", "*/"))),
+    ("source.c++", Some("//"), Some(("/*", "*/"))),
+    ("source.clojure", Some(";"), None),
+    ("source.cs", Some("//"), Some(("/*", "*/"))),
+    ("source.css", None, Some(("/*", "*/"))),
+    ("source.d", Some("//"), Some(("/*", "*/"))),
+    ("source.dosbatch", Some("REM "), None),
+    ("source.dot", Some("//"), Some(("/*", "*/"))),
+    ("source.erlang", Some("%"), None),
+    ("source.go", Some("//"), Some(("/*", "*/"))),
+    ("source.groovy", Some("//"), Some(("/*", "*/"))),
+    ("source.haskell", Some("--"), Some(("{-", "-}"))),
+    ("source.java", Some("//"), Some(("/*", "*/"))),
+    ("source.java-props", Some("#"), None),
+    ("source.js", Some("//"), Some(("/*", "*/"))),
+    ("source.lisp", Some(";"), None),
+    ("source.lua", Some("--"), Some(("--[[", "]]"))),
+    ("source.makefile", Some("#"), None),
+    ("source.matlab", Some("%"), None),
+    ("source.objc", Some("//"), Some(("/*", "*/"))),
+    ("source.objc++", Some("//"), Some(("/*", "*/"))),
+    ("source.ocaml", None, Some(("(*", "*)"))),
+    ("source.pascal", Some("//"), Some(("{", "}"))),
+    ("source.perl", Some("#"), None),
+    ("source.python", Some("#"), None),
+    ("source.r", Some("#"), None),
+    ("source.ruby", Some("#"), None),
+    ("source.rust", Some("//"), Some(("/*", "*/"))),
+    ("source.scala", Some("//"), Some(("/*", "*/"))),
+    ("source.shell.bash", Some("#"), None),
+    ("source.sql", Some("--"), Some(("/*", "*/"))),
+    ("source.tcl", Some("#"), None),
+    ("source.yaml", Some("#"), None),
+    ("text.html.basic", None, Some(("<!--", "-->"))),
+    ("text.html.markdown", None, Some(("<!--", "-->"))),
+    ("text.tex", Some("%"), None),
+    ("text.tex.latex", Some("%"), None),
+    ("text.xml", None, Some(("<!--", "-->"))),
+];
+
 /// Extracts the name and version from Cargo.toml
 fn parse_name_and_version() -> Result<(String, String), io::Error> {
     eprintln!("exe: {:?}", ::std::env::current_exe());
@@ -70,11 +118,17 @@ fn main() -> Result<(), io::Error> {
 }
 
 fn lang_from_syn<'a>(src: &'a SyntaxReference) -> LanguageDefinition {
+    let scope = src.scope.to_string();
+    let comments = COMMENT_TOKENS.iter().find(|(s, _, _)| *s == scope);
     LanguageDefinition {
         name: src.name.as_str().into(),
         extensions: src.file_extensions.clone(),
+        filenames: Vec::new(),
         first_line_match: src.first_line_match.clone(),
-        scope: src.scope.to_string(),
+        line_comment: comments.and_then(|(_, line, _)| line.map(String::from)),
+        block_comment: comments
+            .and_then(|(_, _, block)| block.map(|(open, close)| (open.into(), close.into()))),
+        scope,
         default_config: None,
     }
 }